@@ -17,3 +17,23 @@ impl std::error::Error for Discover {
         Some(&*self.0)
     }
 }
+
+/// Every ready endpoint became unavailable before a request could be dispatched to it.
+#[derive(Debug)]
+pub struct Unavailable {
+    _p: (),
+}
+
+impl Unavailable {
+    pub(crate) fn new() -> Self {
+        Unavailable { _p: () }
+    }
+}
+
+impl fmt::Display for Unavailable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("no ready endpoint was available for the request")
+    }
+}
+
+impl std::error::Error for Unavailable {}
@@ -0,0 +1,28 @@
+//! This module implements a consistent-hashing load balancer.
+//!
+//! Rather than spreading requests across services at random, a consistent-hashing balancer
+//! extracts a key from each request and always sends requests with the same key to the same
+//! service. This keeps per-key state, such as caches, local to a single backend, which improves
+//! hit rates for cache-heavy services.
+//!
+//! Services are placed on a hash ring at a number of pseudo-random points ("virtual nodes"). A
+//! request is sent to the service owning the first point on the ring at or after the hash of its
+//! key. When a service is inserted or removed via [`Discover`](tower::discover::Discover), only
+//! the keys owned by its points move to a different service; all other keys keep their affinity.
+//!
+//! If the service owning a key is not ready, the request is sent to the next ready service on
+//! the ring, so that a single busy service does not stall the balancer. A service's readiness is
+//! checked again when a request is dispatched to it. If no service on the ring is still ready, the
+//! request fails with an [`Unavailable`](super::error::Unavailable) error.
+//!
+//! See [Consistent Hashing and Random Trees] for a description of the algorithm.
+//!
+//! [Consistent Hashing and Random Trees]: https://www.akamai.com/us/en/multimedia/documents/technical-publication/consistent-hashing-and-random-trees-distributed-caching-protocols-for-relieving-hot-spots-on-the-world-wide-web-technical-publication.pdf
+
+mod ring;
+mod service;
+
+#[cfg(test)]
+mod test;
+
+pub use service::Balance;
@@ -0,0 +1,55 @@
+use crate::hash::hash;
+use std::hash::Hash;
+
+/// A hash ring which maps hashes to the keys of the services that own them.
+#[derive(Debug)]
+pub(super) struct Ring<K> {
+    /// Points on the ring, sorted by hash.
+    points: Vec<(u64, K)>,
+    replicas: usize,
+}
+
+impl<K: Clone + Eq + Hash> Ring<K> {
+    pub(super) fn new(replicas: usize) -> Self {
+        assert!(
+            replicas > 0,
+            "a service must have at least one point on the ring"
+        );
+        Ring {
+            points: Vec::new(),
+            replicas,
+        }
+    }
+
+    /// Adds `replicas` points owned by `key` to the ring.
+    ///
+    /// Keys that are already on the ring are not added again.
+    pub(super) fn insert(&mut self, key: &K) {
+        if self.points.iter().any(|(_, k)| k == key) {
+            return;
+        }
+        let points = (0..self.replicas).map(|replica| (hash(&(key, replica)), key.clone()));
+        self.points.extend(points);
+        // The sort is stable, so points which collide keep the order in which
+        // their keys were inserted.
+        self.points.sort_by_key(|(p, _)| *p);
+        self.points.dedup();
+    }
+
+    /// Removes all of the points owned by `key` from the ring.
+    pub(super) fn remove(&mut self, key: &K) {
+        self.points.retain(|(_, k)| k != key);
+    }
+
+    /// Iterates over the keys which own `hash`, starting with the first point at or after it on
+    /// the ring and wrapping around.
+    ///
+    /// Keys may be yielded more than once.
+    pub(super) fn successors(&self, hash: u64) -> impl Iterator<Item = &K> {
+        let start = match self.points.binary_search_by_key(&hash, |(p, _)| *p) {
+            Ok(idx) | Err(idx) => idx,
+        };
+        let (before, after) = self.points.split_at(start);
+        after.iter().chain(before.iter()).map(|(_, k)| k)
+    }
+}
@@ -0,0 +1,258 @@
+use super::super::error;
+use super::ring::Ring;
+use crate::discover::{Change, Discover};
+use crate::ready_cache::{error::Failed, ReadyCache};
+use futures_core::ready;
+use futures_util::future::{self, TryFutureExt};
+use futures_util::task::noop_waker_ref;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::{
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tower_service::Service;
+use tracing::{debug, trace};
+
+/// The number of points each service is placed at on the ring by default.
+const DEFAULT_REPLICAS: usize = 100;
+
+/// Distributes requests across services by consistently hashing a key extracted from each
+/// request.
+///
+/// See the [module-level documentation](..) for details.
+///
+/// Note that `Balance` requires that the `Discover` you use is `Unpin` in order to implement
+/// `Service`. You can achieve this easily by wrapping your `Discover` in [`Box::pin`] before you
+/// construct the `Balance` instance.
+///
+/// [`Box::pin`]: https://doc.rust-lang.org/std/boxed/struct.Box.html#method.pin
+pub struct Balance<D, F, Req>
+where
+    D: Discover,
+    D::Key: Hash,
+{
+    discover: D,
+
+    services: ReadyCache<D::Key, D::Service, Req>,
+    ring: Ring<D::Key>,
+
+    key: F,
+
+    _req: PhantomData<Req>,
+}
+
+impl<D: Discover, F, Req> fmt::Debug for Balance<D, F, Req>
+where
+    D: fmt::Debug,
+    D::Key: Hash + fmt::Debug,
+    D::Service: fmt::Debug,
+    Req: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Balance")
+            .field("discover", &self.discover)
+            .field("services", &self.services)
+            .field("ring", &self.ring)
+            .finish()
+    }
+}
+
+impl<D, F, Req> Balance<D, F, Req>
+where
+    D: Discover,
+    D::Key: Hash + Clone,
+    D::Service: Service<Req>,
+    <D::Service as Service<Req>>::Error: Into<crate::BoxError>,
+{
+    /// Constructs a consistent-hashing load balancer, which routes each request by the key
+    /// returned by `key`.
+    ///
+    /// Each service is placed at 100 points on the ring.
+    pub fn new(discover: D, key: F) -> Self {
+        Self::with_replicas(discover, key, DEFAULT_REPLICAS)
+    }
+
+    /// Constructs a consistent-hashing load balancer, which places each service at `replicas`
+    /// points on the ring.
+    ///
+    /// More points spread keys across services more evenly, at the cost of memory and of the time
+    /// it takes to insert and remove services.
+    ///
+    /// # Panics
+    ///
+    /// If `replicas` is zero.
+    pub fn with_replicas(discover: D, key: F, replicas: usize) -> Self {
+        Self {
+            discover,
+            services: ReadyCache::default(),
+            ring: Ring::new(replicas),
+            key,
+            _req: PhantomData,
+        }
+    }
+
+    /// Returns the number of endpoints currently tracked by the balancer.
+    pub fn len(&self) -> usize {
+        self.services.len()
+    }
+
+    /// Returns whether or not the balancer is empty.
+    pub fn is_empty(&self) -> bool {
+        self.services.len() == 0
+    }
+}
+
+impl<D, F, Req> Balance<D, F, Req>
+where
+    D: Discover + Unpin,
+    D::Key: Hash + Clone,
+    D::Error: Into<crate::BoxError>,
+    D::Service: Service<Req>,
+    <D::Service as Service<Req>>::Error: Into<crate::BoxError>,
+{
+    /// Polls `discover` for updates, adding new items to the ring.
+    fn update_pending_from_discover(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<(), error::Discover>>> {
+        debug!("updating from discover");
+        loop {
+            match ready!(Pin::new(&mut self.discover).poll_discover(cx))
+                .transpose()
+                .map_err(|e| error::Discover(e.into()))?
+            {
+                None => return Poll::Ready(None),
+                Some(Change::Remove(key)) => {
+                    trace!("remove");
+                    self.services.evict(&key);
+                    self.ring.remove(&key);
+                }
+                Some(Change::Insert(key, svc)) => {
+                    trace!("insert");
+                    // If this service already existed in the set, it will be
+                    // replaced as the new one becomes ready, and keeps its
+                    // points on the ring.
+                    self.ring.insert(&key);
+                    self.services.push(key, svc);
+                }
+                // Services are placed on the ring by key alone.
+                Some(Change::Update(..)) => {}
+            }
+        }
+    }
+
+    fn promote_pending_to_ready(&mut self, cx: &mut Context<'_>) {
+        loop {
+            match self.services.poll_pending(cx) {
+                Poll::Ready(Ok(())) => {
+                    // There are no remaining pending services.
+                    debug_assert_eq!(self.services.pending_len(), 0);
+                    break;
+                }
+                Poll::Pending => {
+                    // None of the pending services are ready.
+                    debug_assert!(self.services.pending_len() > 0);
+                    break;
+                }
+                Poll::Ready(Err(Failed(key, error))) => {
+                    // An individual service was lost; continue processing
+                    // pending services.
+                    debug!(%error, "dropping failed endpoint");
+                    self.forget_failed(&key);
+                }
+            }
+        }
+        trace!(
+            ready = %self.services.ready_len(),
+            pending = %self.services.pending_len(),
+            "poll_unready"
+        );
+    }
+
+    /// Removes the points of a failed endpoint from the ring, unless the service it replaced or
+    /// its replacement is still tracked.
+    fn forget_failed(&mut self, key: &D::Key) {
+        if self.services.get_ready(key).is_none() && !self.services.pending_contains(key) {
+            self.ring.remove(key);
+        }
+    }
+}
+
+impl<D, F, H, Req> Service<Req> for Balance<D, F, Req>
+where
+    D: Discover + Unpin,
+    D::Key: Hash + Clone,
+    D::Error: Into<crate::BoxError>,
+    D::Service: Service<Req>,
+    <D::Service as Service<Req>>::Error: Into<crate::BoxError>,
+    F: Fn(&Req) -> H,
+    H: Hash,
+{
+    type Response = <D::Service as Service<Req>>::Response;
+    type Error = crate::BoxError;
+    type Future = future::Either<
+        future::MapErr<
+            <D::Service as Service<Req>>::Future,
+            fn(<D::Service as Service<Req>>::Error) -> crate::BoxError,
+        >,
+        future::Ready<Result<Self::Response, crate::BoxError>>,
+    >;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let _ = self.update_pending_from_discover(cx)?;
+        self.promote_pending_to_ready(cx);
+
+        // The service for a request can only be chosen once its key is known,
+        // so the balancer is ready as long as any service is ready.
+        if self.services.ready_len() == 0 {
+            // We have previously registered interest in updates from
+            // discover and pending services.
+            return Poll::Pending;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Req) -> Self::Future {
+        let hash = crate::hash::hash(&(self.key)(&request));
+
+        // The service that owns the key may have become unready since the
+        // balancer was polled, so its readiness is checked again before it is
+        // called. There is no task to wake here: a service that is no longer
+        // ready is moved back to the pending set, which is polled with the
+        // balancer's task the next time it is polled.
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let mut failed = Vec::new();
+        let mut selected = None;
+        for key in self.ring.successors(hash) {
+            let index = match self.services.get_ready(key) {
+                Some((index, _, _)) => index,
+                None => continue,
+            };
+            match self.services.check_ready_index(&mut cx, index) {
+                Ok(true) => {
+                    selected = Some(index);
+                    break;
+                }
+                Ok(false) => trace!("ready service became unavailable"),
+                Err(Failed(key, error)) => {
+                    debug!(%error, "endpoint failed");
+                    failed.push(key);
+                }
+            }
+        }
+        for key in failed {
+            self.forget_failed(&key);
+        }
+
+        match selected {
+            Some(index) => {
+                trace!(hash, index, "consistent hash");
+                let future = self.services.call_ready_index(index, request);
+                future::Either::Left(future.map_err(Into::into))
+            }
+            None => future::Either::Right(future::err(error::Unavailable::new().into())),
+        }
+    }
+}
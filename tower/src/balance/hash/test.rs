@@ -0,0 +1,227 @@
+use crate::discover::{Change, Discover, ServiceList};
+use futures_util::pin_mut;
+use std::{collections::HashMap, task::Poll};
+use tokio::sync::mpsc;
+use tokio_test::{assert_pending, assert_ready_ok, task};
+use tower_test::{assert_request_eq, mock};
+
+use super::*;
+
+type Req = &'static str;
+type Mock = mock::Mock<Req, &'static str>;
+type Handle = mock::Handle<Req, &'static str>;
+type Changes = mpsc::UnboundedReceiver<Result<Change<usize, Mock>, crate::BoxError>>;
+type ChangeTx = mpsc::UnboundedSender<Result<Change<usize, Mock>, crate::BoxError>>;
+type Svc = mock::Spawn<Balance<Changes, KeyFn, Req>>;
+type KeyFn = fn(&Req) -> Req;
+
+const KEYS: &[&str] = &[
+    "a", "b", "c", "d", "e", "f", "g", "h", "i", "j", "k", "l", "m", "n", "o", "p",
+];
+
+fn key(req: &Req) -> Req {
+    req
+}
+
+/// Sends a request for each key, and returns the endpoint that each one was sent to.
+fn route<D>(
+    svc: &mut mock::Spawn<Balance<D, KeyFn, Req>>,
+    handles: &mut [Handle],
+) -> HashMap<Req, usize>
+where
+    D: Discover<Key = usize, Service = Mock> + Unpin,
+    D::Error: Into<crate::BoxError>,
+{
+    let mut routes = HashMap::new();
+    for k in KEYS {
+        assert_ready_ok!(svc.poll_ready());
+        let mut fut = task::spawn(svc.call(k));
+        for (i, h) in handles.iter_mut().enumerate() {
+            if let Poll::Ready(Some((req, tx))) = h.poll_request() {
+                assert_eq!(req, *k);
+                tx.send_response("done");
+                routes.insert(*k, i);
+            }
+        }
+        assert_ready_ok!(fut.poll());
+    }
+    routes
+}
+
+#[tokio::test]
+async fn empty() {
+    let empty: Vec<Mock> = vec![];
+    let disco = ServiceList::new(empty);
+    let mut svc = mock::Spawn::new(Balance::new(disco, key as KeyFn));
+    assert_pending!(svc.poll_ready());
+}
+
+#[tokio::test]
+async fn affinity() {
+    let (mocks, mut handles): (Vec<_>, Vec<_>) = (0..3).map(|_| mock::pair()).unzip();
+    for h in handles.iter_mut() {
+        h.allow(100);
+    }
+    let disco = ServiceList::new(mocks);
+    let mut svc = mock::Spawn::new(Balance::new(disco, key as KeyFn));
+
+    let routes = route(&mut svc, &mut handles);
+    assert_eq!(routes.len(), KEYS.len());
+    // Keys are spread across endpoints.
+    assert!((0..3).all(|i| routes.values().any(|e| *e == i)));
+    // The same keys are sent to the same endpoints.
+    assert_eq!(route(&mut svc, &mut handles), routes);
+}
+
+#[tokio::test]
+async fn remove_keeps_affinity() {
+    let (tx, rx): (_, Changes) = mpsc::unbounded_channel();
+    let mut handles = Vec::new();
+    for i in 0..3 {
+        let (mock, mut handle) = mock::pair();
+        handle.allow(100);
+        tx.send(Ok(Change::Insert(i, mock))).unwrap();
+        handles.push(handle);
+    }
+    let mut svc = mock::Spawn::new(Balance::new(rx, key as KeyFn));
+
+    let before = route(&mut svc, &mut handles);
+
+    tx.send(Ok(Change::Remove(2))).unwrap();
+    let after = route(&mut svc, &mut handles);
+
+    // Only the keys of the removed endpoint were moved.
+    for k in KEYS {
+        if before[k] == 2 {
+            assert_ne!(after[k], 2);
+        } else {
+            assert_eq!(after[k], before[k]);
+        }
+    }
+
+    // Re-inserting the endpoint restores the original routes.
+    let (mock, mut handle) = mock::pair();
+    handle.allow(100);
+    handles[2] = handle;
+    tx.send(Ok(Change::Insert(2, mock))).unwrap();
+    assert_eq!(route(&mut svc, &mut handles), before);
+}
+
+#[tokio::test]
+async fn unready_endpoint_falls_back() {
+    let (mock_a, handle_a) = mock::pair();
+    let (mock_b, handle_b) = mock::pair();
+    pin_mut!(handle_a);
+    pin_mut!(handle_b);
+    handle_a.allow(1);
+    handle_b.allow(0);
+
+    let disco = ServiceList::new(vec![mock_a, mock_b]);
+    let mut svc = mock::Spawn::new(Balance::new(disco, key as KeyFn));
+
+    // Every key is sent to the only ready endpoint.
+    assert_ready_ok!(svc.poll_ready());
+    let mut fut = task::spawn(svc.call("a"));
+    assert_request_eq!(handle_a, "a").send_response("done");
+    assert_ready_ok!(fut.poll());
+
+    // Neither endpoint is ready.
+    assert_pending!(svc.poll_ready());
+}
+
+#[tokio::test]
+async fn endpoint_failed_at_call_falls_back() {
+    let (mocks, mut handles): (Vec<_>, Vec<_>) = (0..2).map(|_| mock::pair()).unzip();
+    for h in handles.iter_mut() {
+        h.allow(100);
+    }
+    let disco = ServiceList::new(mocks);
+    let mut svc = mock::Spawn::new(Balance::new(disco, key as KeyFn));
+    let routes = route(&mut svc, &mut handles);
+    let (k, owner) = routes.iter().next().map(|(k, e)| (*k, *e)).unwrap();
+
+    // The owner of the key fails after the balancer was polled.
+    assert_ready_ok!(svc.poll_ready());
+    handles[owner].send_error("endpoint lost");
+    let mut fut = task::spawn(svc.call(k));
+
+    let other = 1 - owner;
+    match handles[other].poll_request() {
+        Poll::Ready(Some((req, tx))) => {
+            assert_eq!(req, k);
+            tx.send_response("done");
+        }
+        _ => panic!("request was not sent to the next ready endpoint"),
+    }
+    assert_ready_ok!(fut.poll());
+    assert_eq!(svc.get_ref().len(), 1);
+}
+
+/// Returns a balancer over two endpoints, their handles, and the routes of `KEYS`.
+fn two_endpoints() -> (ChangeTx, Svc, Vec<Handle>, HashMap<Req, usize>) {
+    let (tx, rx): (_, Changes) = mpsc::unbounded_channel();
+    let mut handles = Vec::new();
+    for i in 0..2 {
+        let (mock, mut handle) = mock::pair();
+        handle.allow(100);
+        tx.send(Ok(Change::Insert(i, mock))).unwrap();
+        handles.push(handle);
+    }
+    let mut svc = mock::Spawn::new(Balance::new(rx, key as KeyFn));
+    let routes = route(&mut svc, &mut handles);
+    // The last endpoint that was called is pending until the balancer is polled again.
+    assert_ready_ok!(svc.poll_ready());
+    (tx, svc, handles, routes)
+}
+
+#[tokio::test]
+async fn failed_replacement_keeps_routes() {
+    let (tx, mut svc, mut handles, routes) = two_endpoints();
+    let owner = routes.values().next().copied().unwrap();
+
+    // A replacement for an endpoint fails before it becomes ready.
+    let (mock, mut replacement) = mock::pair();
+    replacement.allow(0);
+    tx.send(Ok(Change::Insert(owner, mock))).unwrap();
+    assert_ready_ok!(svc.poll_ready());
+    replacement.send_error("replacement lost");
+    assert_ready_ok!(svc.poll_ready());
+
+    // The service it was to replace keeps its keys.
+    assert_eq!(svc.get_ref().len(), 2);
+    assert_eq!(route(&mut svc, &mut handles), routes);
+}
+
+#[tokio::test]
+async fn endpoint_failed_at_call_keeps_routes_for_replacement() {
+    let (tx, mut svc, mut handles, routes) = two_endpoints();
+    let (k, owner) = routes.iter().next().map(|(k, e)| (*k, *e)).unwrap();
+
+    // An endpoint fails while its replacement is pending.
+    let (mock, mut replacement) = mock::pair();
+    replacement.allow(0);
+    tx.send(Ok(Change::Insert(owner, mock))).unwrap();
+    assert_ready_ok!(svc.poll_ready());
+    handles[owner].send_error("endpoint lost");
+    let mut fut = task::spawn(svc.call(k));
+    let other = 1 - owner;
+    match handles[other].poll_request() {
+        Poll::Ready(Some((req, tx))) => {
+            assert_eq!(req, k);
+            tx.send_response("done");
+        }
+        _ => panic!("request was not sent to the next ready endpoint"),
+    }
+    assert_ready_ok!(fut.poll());
+
+    // Once the replacement is ready, it is sent the keys of the failed endpoint.
+    replacement.allow(100);
+    handles[owner] = replacement;
+    assert_eq!(route(&mut svc, &mut handles), routes);
+}
+
+#[test]
+fn ring_hash_is_stable() {
+    // Keys must keep their points across builds.
+    assert_eq!(crate::hash::hash(&0u8), 0xb903_4ad3_7056_f5fb);
+}
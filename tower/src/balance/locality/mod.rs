@@ -0,0 +1,50 @@
+//! This module implements a locality-aware load balancer with failover tiers.
+//!
+//! When services are spread across several localities, such as availability zones or regions,
+//! sending requests to a nearby service is usually faster and cheaper than sending them farther
+//! away. A locality-aware balancer groups discovered services by the locality label in their
+//! discovered metadata, which implements [`Locality`], and ranks the groups in a configured order
+//! of preference: the first label is the local tier, the next label the first failover tier, and
+//! so on. Services whose label is not listed form a final tier.
+//!
+//! Requests are spread across the services of a tier by a [`p2c::Balance`](super::p2c::Balance).
+//! A request is sent to the most preferred tier that is healthy, meaning that:
+//!
+//! - at least a minimum fraction of its services are ready, and
+//! - optionally, its least loaded ready service is at most a maximum
+//!   [`Load`](crate::load::Load).
+//!
+//! If no tier is healthy, the most preferred tier with any ready service is used, so that traffic
+//! spills over to remote tiers only while the local tier is degraded or overloaded.
+
+mod service;
+
+#[cfg(test)]
+mod test;
+
+pub use service::Balance;
+
+/// Discovery metadata that carries the locality of a service.
+pub trait Locality {
+    /// The type of locality labels, such as zone names.
+    type Label: PartialEq;
+
+    /// Returns the locality label of the service.
+    fn locality(&self) -> &Self::Label;
+}
+
+impl<'a> Locality for &'a str {
+    type Label = &'a str;
+
+    fn locality(&self) -> &Self::Label {
+        self
+    }
+}
+
+impl Locality for String {
+    type Label = String;
+
+    fn locality(&self) -> &Self::Label {
+        self
+    }
+}
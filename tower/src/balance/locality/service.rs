@@ -0,0 +1,323 @@
+use super::super::error;
+use super::super::p2c;
+use super::Locality;
+use crate::discover::{Change, Discover};
+use crate::load::Load;
+use futures_core::ready;
+use futures_util::future;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::hash::Hash;
+use std::{
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::sync::mpsc;
+use tower_service::Service;
+use tracing::{debug, trace};
+
+/// The fraction of a tier's services that must be ready for it to be healthy, by default.
+const DEFAULT_MIN_READY_FRACTION: f64 = 0.5;
+
+/// Distributes requests across the services of the most preferred healthy locality.
+///
+/// See the [module-level documentation](..) for details.
+///
+/// The locality of each service is taken from its metadata, so a discovered service only receives
+/// requests once its metadata has been discovered. If the locality of a service changes, it moves
+/// to its new tier once it is replaced.
+///
+/// Note that `Balance` requires that the `Discover` you use is `Unpin` in order to implement
+/// `Service`. You can achieve this easily by wrapping your `Discover` in [`Box::pin`] before you
+/// construct the `Balance` instance.
+///
+/// [`Box::pin`]: https://doc.rust-lang.org/std/boxed/struct.Box.html#method.pin
+pub struct Balance<D, Req>
+where
+    D: Discover,
+    D::Key: Hash,
+    D::Service: Load,
+    D::Meta: Locality,
+{
+    discover: D,
+
+    labels: Vec<<D::Meta as Locality>::Label>,
+    tiers: Vec<Tier<D::Key, D::Service, Req>>,
+    keys: HashMap<D::Key, usize>,
+    unplaced: HashMap<D::Key, D::Service>,
+    metadata: HashMap<D::Key, D::Meta>,
+    ready_tier: Option<usize>,
+
+    min_ready_fraction: f64,
+    max_load: Option<<D::Service as Load>::Metric>,
+}
+
+type TierDiscover<K, S> = mpsc::UnboundedReceiver<Result<Change<K, S>, Infallible>>;
+
+/// The services of a single locality, balanced with power of two choices.
+struct Tier<K, S, Req>
+where
+    K: Eq + Hash,
+{
+    changes: mpsc::UnboundedSender<Result<Change<K, S>, Infallible>>,
+    balance: p2c::Balance<TierDiscover<K, S>, Req>,
+}
+
+impl<D, Req> fmt::Debug for Balance<D, Req>
+where
+    D: Discover + fmt::Debug,
+    D::Key: Hash + fmt::Debug,
+    D::Service: Load,
+    <D::Service as Load>::Metric: fmt::Debug,
+    D::Meta: Locality,
+    <D::Meta as Locality>::Label: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Balance")
+            .field("discover", &self.discover)
+            .field("labels", &self.labels)
+            .field("keys", &self.keys)
+            .field("unplaced", &self.unplaced.keys())
+            .field("min_ready_fraction", &self.min_ready_fraction)
+            .field("max_load", &self.max_load)
+            .finish()
+    }
+}
+
+impl<D, Req> Balance<D, Req>
+where
+    D: Discover,
+    D::Key: Hash,
+    D::Service: Service<Req> + Load,
+    <D::Service as Service<Req>>::Error: Into<crate::BoxError>,
+    D::Meta: Locality,
+{
+    /// Constructs a locality-aware load balancer.
+    ///
+    /// `labels` lists the localities in order of preference. Services whose locality is not in
+    /// `labels` are only used once all listed localities are unhealthy.
+    ///
+    /// By default, a locality is healthy while at least half of its services are ready,
+    /// regardless of their load.
+    pub fn new(discover: D, labels: Vec<<D::Meta as Locality>::Label>) -> Self {
+        let tiers = (0..=labels.len())
+            .map(|_| {
+                let (changes, rx) = mpsc::unbounded_channel();
+                Tier {
+                    changes,
+                    balance: p2c::Balance::new(rx),
+                }
+            })
+            .collect();
+        Balance {
+            discover,
+            labels,
+            tiers,
+            keys: HashMap::new(),
+            unplaced: HashMap::new(),
+            metadata: HashMap::new(),
+            ready_tier: None,
+            min_ready_fraction: DEFAULT_MIN_READY_FRACTION,
+            max_load: None,
+        }
+    }
+
+    /// Sets the fraction of a locality's services that must be ready for it to be healthy.
+    ///
+    /// The default value is 0.5.
+    ///
+    /// # Panics
+    ///
+    /// If `fraction` is not between 0 and 1.
+    pub fn min_ready_fraction(mut self, fraction: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&fraction),
+            "min_ready_fraction must be between 0 and 1"
+        );
+        self.min_ready_fraction = fraction;
+        self
+    }
+
+    /// Sets the maximum load of a locality's least loaded ready service for it to be healthy.
+    ///
+    /// No maximum load is imposed by default.
+    pub fn max_load(mut self, load: <D::Service as Load>::Metric) -> Self {
+        self.max_load = Some(load);
+        self
+    }
+
+    /// Returns the number of endpoints currently tracked by the balancer, including those whose
+    /// metadata has not been discovered yet.
+    pub fn len(&self) -> usize {
+        self.tiers.iter().map(|t| t.balance.len()).sum::<usize>() + self.unplaced.len()
+    }
+
+    /// Returns whether or not the balancer is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the latest metadata discovered for the endpoint identified by `key`.
+    pub fn metadata(&self, key: &D::Key) -> Option<&D::Meta> {
+        self.metadata.get(key)
+    }
+}
+
+impl<D, Req> Balance<D, Req>
+where
+    D: Discover + Unpin,
+    D::Key: Hash + Clone,
+    D::Error: Into<crate::BoxError>,
+    D::Service: Service<Req> + Load,
+    <D::Service as Service<Req>>::Error: Into<crate::BoxError>,
+    <D::Service as Load>::Metric: fmt::Debug,
+    D::Meta: Locality,
+{
+    /// Polls `discover` for updates, routing each service to the tier of its locality.
+    fn update_tiers_from_discover(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<(), error::Discover>>> {
+        debug!("updating from discover");
+        loop {
+            match ready!(Pin::new(&mut self.discover).poll_discover(cx))
+                .transpose()
+                .map_err(|e| error::Discover(e.into()))?
+            {
+                None => return Poll::Ready(None),
+                Some(Change::Remove(key)) => {
+                    trace!("remove");
+                    self.metadata.remove(&key);
+                    self.unplaced.remove(&key);
+                    if let Some(tier) = self.keys.remove(&key) {
+                        self.tiers[tier].send(Change::Remove(key));
+                    }
+                }
+                Some(Change::Insert(key, svc)) => match self.metadata.get(&key) {
+                    Some(meta) => {
+                        let tier = self.tier(meta);
+                        self.place(key, svc, tier);
+                    }
+                    None => {
+                        trace!("insert; waiting for metadata");
+                        self.unplaced.insert(key, svc);
+                    }
+                },
+                Some(Change::Update(key, meta)) => {
+                    let tier = self.tier(&meta);
+                    if let Some(svc) = self.unplaced.remove(&key) {
+                        self.place(key.clone(), svc, tier);
+                    } else if let Some(&current) = self.keys.get(&key) {
+                        if current != tier {
+                            debug!(tier, "locality changed; moving once replaced");
+                        }
+                    } else {
+                        debug!("ignoring metadata of an unknown endpoint");
+                        continue;
+                    }
+                    trace!("update");
+                    self.metadata.insert(key, meta);
+                }
+            }
+        }
+    }
+
+    /// Returns the tier of the locality in `meta`.
+    fn tier(&self, meta: &D::Meta) -> usize {
+        let label = meta.locality();
+        self.labels
+            .iter()
+            .position(|l| l == label)
+            .unwrap_or(self.labels.len())
+    }
+
+    /// Inserts a service into `tier`.
+    fn place(&mut self, key: D::Key, svc: D::Service, tier: usize) {
+        trace!(tier, "insert");
+        // If the service moved to another locality, its old version is
+        // removed from its previous tier.
+        if let Some(old) = self.keys.insert(key.clone(), tier) {
+            if old != tier {
+                self.tiers[old].send(Change::Remove(key.clone()));
+            }
+        }
+        self.tiers[tier].send(Change::Insert(key, svc));
+    }
+
+    /// Returns whether the tier can handle requests without spilling over to the next one.
+    fn is_healthy(&self, tier: usize) -> bool {
+        let balance = &self.tiers[tier].balance;
+        let ready = balance.ready_len() as f64;
+        if ready == 0.0 || ready < self.min_ready_fraction * balance.len() as f64 {
+            return false;
+        }
+        match (&self.max_load, balance.min_ready_load()) {
+            (Some(max), Some(load)) => load <= *max,
+            _ => true,
+        }
+    }
+}
+
+impl<D, Req> Service<Req> for Balance<D, Req>
+where
+    D: Discover + Unpin,
+    D::Key: Hash + Clone,
+    D::Error: Into<crate::BoxError>,
+    D::Service: Service<Req> + Load,
+    <D::Service as Service<Req>>::Error: Into<crate::BoxError>,
+    <D::Service as Load>::Metric: fmt::Debug,
+    D::Meta: Locality,
+{
+    type Response = <D::Service as Service<Req>>::Response;
+    type Error = crate::BoxError;
+    type Future = future::MapErr<
+        <D::Service as Service<Req>>::Future,
+        fn(<D::Service as Service<Req>>::Error) -> crate::BoxError,
+    >;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let _ = self.update_tiers_from_discover(cx)?;
+
+        // Tiers are polled in order of preference, until one is found that is
+        // healthy. Less preferred tiers are only polled while all of the more
+        // preferred ones are unhealthy.
+        self.ready_tier = None;
+        let mut fallback = None;
+        for tier in 0..self.tiers.len() {
+            if self.tiers[tier].balance.poll_ready(cx)?.is_pending() {
+                continue;
+            }
+            if self.is_healthy(tier) {
+                trace!(tier, "selected healthy tier");
+                self.ready_tier = Some(tier);
+                return Poll::Ready(Ok(()));
+            }
+            fallback = fallback.or(Some(tier));
+        }
+
+        match fallback {
+            Some(tier) => {
+                debug!(tier, "no healthy tier; using most preferred ready tier");
+                self.ready_tier = Some(tier);
+                Poll::Ready(Ok(()))
+            }
+            // We have previously registered interest in updates from
+            // discover and every tier.
+            None => Poll::Pending,
+        }
+    }
+
+    fn call(&mut self, request: Req) -> Self::Future {
+        let tier = self.ready_tier.take().expect("called before ready");
+        self.tiers[tier].balance.call(request)
+    }
+}
+
+impl<K: Eq + Hash, S, Req> Tier<K, S, Req> {
+    fn send(&self, change: Change<K, S>) {
+        // The receiver is owned by the tier's balancer, so it cannot have
+        // been dropped.
+        let _ = self.changes.send(Ok(change));
+    }
+}
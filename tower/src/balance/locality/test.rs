@@ -0,0 +1,182 @@
+use crate::discover::Change;
+use crate::load;
+use std::task::Poll;
+use tokio::sync::mpsc;
+use tokio_test::{assert_pending, assert_ready_ok, task};
+use tower_service::Service;
+use tower_test::mock;
+
+use super::*;
+
+type Key = (&'static str, usize);
+type Mock = mock::Mock<(), &'static str>;
+type Handle = mock::Handle<(), &'static str>;
+type Svc = load::Constant<Mock, usize>;
+type Tx = mpsc::UnboundedSender<Result<Change<Key, Svc, &'static str>, crate::BoxError>>;
+type Changes = mpsc::UnboundedReceiver<Result<Change<Key, Svc, &'static str>, crate::BoxError>>;
+
+/// Discovers a service, and then its zone.
+fn discover(tx: &Tx, ((zone, n), svc): (Key, Svc)) {
+    tx.send(Ok(Change::Insert((zone, n), svc))).unwrap();
+    tx.send(Ok(Change::Update((zone, n), zone))).unwrap();
+}
+
+/// Creates a service in `zone` with a constant `load`, that can handle `allow` requests.
+fn endpoint(zone: &'static str, n: usize, load: usize, allow: u64) -> ((Key, Svc), Handle) {
+    let (mock, mut handle) = mock::pair();
+    handle.allow(allow);
+    (((zone, n), load::Constant::new(mock, load)), handle)
+}
+
+fn new_balance(max_load: Option<usize>) -> (mock::Spawn<Balance<Changes, ()>>, Tx) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let mut balance = Balance::new(rx, vec!["local", "remote"]);
+    if let Some(max_load) = max_load {
+        balance = balance.max_load(max_load);
+    }
+    (mock::Spawn::new(balance), tx)
+}
+
+fn balance(
+    endpoints: Vec<(Key, Svc)>,
+    max_load: Option<usize>,
+) -> mock::Spawn<Balance<Changes, ()>> {
+    let (balance, tx) = new_balance(max_load);
+    for endpoint in endpoints {
+        discover(&tx, endpoint);
+    }
+    balance
+}
+
+/// Sends a request, and asserts that `handle` receives it.
+fn assert_routed_to<S>(svc: &mut mock::Spawn<S>, handle: &mut Handle)
+where
+    S: Service<(), Response = &'static str, Error = crate::BoxError>,
+{
+    assert_ready_ok!(svc.poll_ready());
+    let mut fut = task::spawn(svc.call(()));
+    match handle.poll_request() {
+        Poll::Ready(Some((_, tx))) => tx.send_response("done"),
+        _ => panic!("request was not routed to the expected service"),
+    }
+    assert_ready_ok!(fut.poll());
+}
+
+#[tokio::test]
+async fn prefers_local_tier() {
+    let (local, mut local_handle) = endpoint("local", 0, 10, 100);
+    let (remote, mut remote_handle) = endpoint("remote", 0, 0, 100);
+    let mut svc = balance(vec![remote, local], None);
+
+    for _ in 0..4 {
+        assert_routed_to(&mut svc, &mut local_handle);
+    }
+    assert_pending!(remote_handle.poll_request());
+}
+
+#[tokio::test]
+async fn spills_over_when_local_tier_is_unready() {
+    let (local_a, _local_a_handle) = endpoint("local", 0, 0, 0);
+    let (local_b, mut local_b_handle) = endpoint("local", 1, 0, 100);
+    let (local_c, _local_c_handle) = endpoint("local", 2, 0, 0);
+    let (remote, mut remote_handle) = endpoint("remote", 0, 0, 100);
+    let mut svc = balance(vec![local_a, local_b, local_c, remote], None);
+
+    // Only a third of the local services are ready.
+    assert_routed_to(&mut svc, &mut remote_handle);
+    assert_pending!(local_b_handle.poll_request());
+}
+
+#[tokio::test]
+async fn spills_over_when_local_tier_is_overloaded() {
+    let (local, mut local_handle) = endpoint("local", 0, 10, 100);
+    let (remote, mut remote_handle) = endpoint("remote", 0, 1, 100);
+    let (other, mut other_handle) = endpoint("elsewhere", 0, 0, 100);
+    let mut svc = balance(vec![local, remote, other], Some(5));
+
+    assert_routed_to(&mut svc, &mut remote_handle);
+    assert_pending!(local_handle.poll_request());
+    assert_pending!(other_handle.poll_request());
+}
+
+#[tokio::test]
+async fn uses_most_preferred_ready_tier_when_none_are_healthy() {
+    let (local, mut local_handle) = endpoint("local", 0, 10, 100);
+    let (remote, _remote_handle) = endpoint("remote", 0, 10, 0);
+    let mut svc = balance(vec![remote, local], Some(5));
+
+    assert_routed_to(&mut svc, &mut local_handle);
+}
+
+#[tokio::test]
+async fn unlisted_localities_are_least_preferred() {
+    let (local, _local_handle) = endpoint("local", 0, 0, 0);
+    let (remote, mut remote_handle) = endpoint("remote", 0, 0, 100);
+    let (other, _other_handle) = endpoint("elsewhere", 0, 0, 100);
+    let mut svc = balance(vec![other, local, remote], None);
+
+    assert_routed_to(&mut svc, &mut remote_handle);
+}
+
+#[tokio::test]
+async fn discovered_services_join_their_tier() {
+    let (mut svc, tx) = new_balance(None);
+
+    let (remote, mut remote_handle) = endpoint("remote", 0, 0, 100);
+    discover(&tx, remote);
+    assert_routed_to(&mut svc, &mut remote_handle);
+
+    let ((key, local), mut local_handle) = endpoint("local", 0, 0, 100);
+    discover(&tx, (key, local));
+    assert_routed_to(&mut svc, &mut local_handle);
+    assert_eq!(svc.get_ref().len(), 2);
+
+    tx.send(Ok(Change::Remove(key))).unwrap();
+    assert_routed_to(&mut svc, &mut remote_handle);
+    assert_eq!(svc.get_ref().len(), 1);
+}
+
+#[tokio::test]
+async fn services_wait_for_their_locality() {
+    let (mut svc, tx) = new_balance(None);
+
+    let (remote, mut remote_handle) = endpoint("remote", 0, 0, 100);
+    discover(&tx, remote);
+    let ((key, local), mut local_handle) = endpoint("local", 0, 0, 100);
+    tx.send(Ok(Change::Insert(key, local))).unwrap();
+    assert_routed_to(&mut svc, &mut remote_handle);
+    assert_eq!(svc.get_ref().len(), 2);
+
+    tx.send(Ok(Change::Update(key, "local"))).unwrap();
+    assert_routed_to(&mut svc, &mut local_handle);
+    assert_eq!(svc.get_ref().metadata(&key), Some(&"local"));
+}
+
+#[tokio::test]
+async fn locality_changes_apply_to_replacements() {
+    let (mut svc, tx) = new_balance(None);
+
+    let ((key, remote), mut remote_handle) = endpoint("remote", 0, 0, 100);
+    discover(&tx, (key, remote));
+    assert_routed_to(&mut svc, &mut remote_handle);
+
+    // The service keeps its tier until it is replaced.
+    tx.send(Ok(Change::Update(key, "local"))).unwrap();
+    assert_routed_to(&mut svc, &mut remote_handle);
+
+    let ((_, local), mut local_handle) = endpoint("local", 0, 0, 100);
+    tx.send(Ok(Change::Insert(key, local))).unwrap();
+    assert_routed_to(&mut svc, &mut local_handle);
+    assert_pending!(remote_handle.poll_request());
+}
+
+#[tokio::test]
+async fn metadata_of_unknown_services_is_ignored() {
+    let (mut svc, tx) = new_balance(None);
+
+    let (remote, _remote_handle) = endpoint("remote", 0, 0, 100);
+    discover(&tx, remote);
+    tx.send(Ok(Change::Update(("local", 0), "local"))).unwrap();
+    assert_ready_ok!(svc.poll_ready());
+    assert_eq!(svc.get_ref().metadata(&("local", 0)), None);
+}
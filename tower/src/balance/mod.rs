@@ -14,6 +14,21 @@
 //!
 //! [Power of Two Random Choices]: http://www.eecs.harvard.edu/~michaelm/postscripts/handbook2001.pdf
 //!
+//! The balancer can also choose services using the other [`strategy`]
+//! implementations, such as round robin or least loaded.
+//!
+//! If requests with the same key should be handled by the same service, [`hash`] implements a
+//! consistent-hashing balancer, which keeps that affinity stable as services come and go.
+//!
+//! Failing services can be ejected from either balancer with [`outlier`] detection.
+//!
+//! When services are spread across availability zones or regions, [`locality`] implements a
+//! balancer that prefers nearby services, and fails over to more distant ones while the nearby
+//! services are unready or overloaded.
+//!
+//! The endpoints tracked by a [`p2c`] balancer, along with their readiness, load and how often
+//! they were selected, can be inspected with a [`snapshot`].
+//!
 //! Second, [`pool`] implements a dynamically sized pool of services. It estimates the overall
 //! current load by tracking successful and unsuccessful calls to `poll_ready`, and uses an
 //! exponentially weighted moving average to add (using [`tower::make_service::MakeService`]) or
@@ -54,5 +69,10 @@
 //! ```
 
 pub mod error;
+pub mod hash;
+pub mod locality;
+pub mod outlier;
 pub mod p2c;
 pub mod pool;
+pub mod snapshot;
+pub mod strategy;
@@ -0,0 +1,478 @@
+//! This module implements outlier detection, which passively ejects failing services from a
+//! balancer.
+//!
+//! A service that fails quickly may look _less_ loaded than its healthy peers (particularly when
+//! its load is measured with [`PeakEwma`](crate::load::PeakEwma)), so a balancer such as
+//! [`p2c::Balance`](super::p2c::Balance) would keep sending it traffic for as long as its
+//! `poll_ready` succeeds. Outlier detection instead tracks the responses of each discovered
+//! service, and ejects a service once it has failed too many times in a row, or once too large a
+//! fraction of its recent requests have failed.
+//!
+//! An ejected service reports that it is not ready, which makes the balancer move it out of its
+//! set of ready services. Once its ejection period has elapsed, the service becomes ready again
+//! and is re-admitted. Each time a service is ejected, its ejection period is doubled (up to a
+//! maximum); the period shrinks back as the service stays healthy.
+//!
+//! To avoid ejecting every service when a failure is not specific to a few of them, at most a
+//! limited fraction of the services may be ejected at once.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use std::time::Duration;
+//! use tower::balance::{outlier, p2c::Balance};
+//! use tower::discover::ServiceList;
+//! use tower::load::Load;
+//! use tower::Service;
+//!
+//! fn balance<Req, S>(svc1: S, svc2: S) -> impl Service<Req>
+//! where
+//!     S: Service<Req> + Load,
+//!     S::Error: Into<tower::BoxError>,
+//!     S::Metric: std::fmt::Debug,
+//! {
+//!     let discover = outlier::Builder::new()
+//!         .consecutive_errors(Some(3))
+//!         .base_ejection_time(Duration::from_secs(10))
+//!         .build(ServiceList::new(vec![svc1, svc2]));
+//!     Balance::new(discover)
+//! }
+//! ```
+
+use crate::discover::{Change, Discover};
+use crate::load::Load;
+use futures_core::{ready, Stream};
+use pin_project::pin_project;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::time::{delay_until, Delay, Instant};
+use tower_service::Service;
+use tracing::debug;
+
+#[cfg(test)]
+mod test;
+
+/// A [builder] that configures when services are ejected, and for how long.
+///
+/// See the [module-level documentation](index.html) for details.
+///
+///  [builder]: https://rust-lang-nursery.github.io/api-guidelines/type-safety.html#builders-enable-construction-of-complex-values-c-builder
+#[derive(Copy, Clone, Debug)]
+pub struct Builder {
+    consecutive_errors: Option<u32>,
+    error_rate: Option<f64>,
+    min_requests: u32,
+    interval: Duration,
+    base_ejection_time: Duration,
+    max_ejection_time: Duration,
+    max_ejected_fraction: f64,
+}
+
+/// Wraps a `D`-typed stream of discovered services with [`Outlier`] detection.
+#[pin_project]
+#[derive(Debug)]
+pub struct OutlierDiscover<D> {
+    #[pin]
+    discover: D,
+    detector: Arc<Detector>,
+}
+
+/// Ejects the underlying service while it is an outlier.
+///
+/// Created by [`OutlierDiscover`].
+#[derive(Debug)]
+pub struct Outlier<S> {
+    service: S,
+    endpoint: Arc<Mutex<Endpoint>>,
+    detector: Arc<Detector>,
+    readmit: Option<Delay>,
+}
+
+/// Records the outcome of a response from an [`Outlier`] service.
+#[pin_project]
+#[derive(Debug)]
+pub struct ResponseFuture<F> {
+    #[pin]
+    inner: F,
+    endpoint: Arc<Mutex<Endpoint>>,
+    detector: Arc<Detector>,
+}
+
+/// State shared by all of the services discovered by an `OutlierDiscover`.
+#[derive(Debug)]
+struct Detector {
+    config: Builder,
+    counts: Mutex<Counts>,
+}
+
+#[derive(Debug, Default)]
+struct Counts {
+    total: usize,
+    ejected: usize,
+}
+
+/// The recent outcomes of a single service.
+#[derive(Debug)]
+struct Endpoint {
+    consecutive_errors: u32,
+    window_start: Instant,
+    window_requests: u32,
+    window_errors: u32,
+    ejections: u32,
+    ejected_until: Option<Instant>,
+    /// Set once the `Outlier` service has been dropped, so that responses that are still in
+    /// flight no longer count towards ejections.
+    removed: bool,
+}
+
+// ===== impl Builder =====
+
+impl Default for Builder {
+    fn default() -> Self {
+        Builder {
+            consecutive_errors: Some(5),
+            error_rate: None,
+            min_requests: 20,
+            interval: Duration::from_secs(10),
+            base_ejection_time: Duration::from_secs(30),
+            max_ejection_time: Duration::from_secs(300),
+            max_ejected_fraction: 0.1,
+        }
+    }
+}
+
+impl Builder {
+    /// Create a new builder with default values for all outlier detection settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Eject a service once this many of its responses in a row have failed.
+    ///
+    /// The default value is 5. `None` disables ejection based on consecutive errors.
+    pub fn consecutive_errors(&mut self, limit: Option<u32>) -> &mut Self {
+        self.consecutive_errors = limit;
+        self
+    }
+
+    /// Eject a service once at least this fraction of its responses in the current interval have
+    /// failed.
+    ///
+    /// The rate is only considered once the service has received [`min_requests`] requests in the
+    /// interval. Ejection based on the error rate is disabled by default.
+    ///
+    /// [`min_requests`]: Builder::min_requests
+    pub fn error_rate(&mut self, rate: Option<f64>) -> &mut Self {
+        self.error_rate = rate;
+        self
+    }
+
+    /// The number of requests a service must receive in an interval before its error rate is
+    /// considered.
+    ///
+    /// The default value is 20.
+    pub fn min_requests(&mut self, min: u32) -> &mut Self {
+        self.min_requests = min;
+        self
+    }
+
+    /// The interval over which error rates are measured.
+    ///
+    /// The ejection period of a service is also shortened after every interval in which it was
+    /// not ejected.
+    ///
+    /// The default value is 10 seconds.
+    pub fn interval(&mut self, interval: Duration) -> &mut Self {
+        self.interval = interval;
+        self
+    }
+
+    /// How long a service is ejected for the first time.
+    ///
+    /// Every subsequent ejection doubles this time, up to [`max_ejection_time`].
+    ///
+    /// The default value is 30 seconds.
+    ///
+    /// [`max_ejection_time`]: Builder::max_ejection_time
+    pub fn base_ejection_time(&mut self, time: Duration) -> &mut Self {
+        self.base_ejection_time = time;
+        self
+    }
+
+    /// The longest time a service is ejected for.
+    ///
+    /// The default value is 300 seconds.
+    pub fn max_ejection_time(&mut self, time: Duration) -> &mut Self {
+        self.max_ejection_time = time;
+        self
+    }
+
+    /// The largest fraction of services that may be ejected at once.
+    ///
+    /// Regardless of this value, one service may be ejected as long as there is more than one
+    /// service.
+    ///
+    /// Must be between 0 and 1. The default value is 0.1.
+    pub fn max_ejected_fraction(&mut self, fraction: f64) -> &mut Self {
+        assert!(
+            (0.0..=1.0).contains(&fraction),
+            "fraction must be between 0 and 1"
+        );
+        self.max_ejected_fraction = fraction;
+        self
+    }
+
+    /// Wraps a `Discover`, detecting outliers among all of its services.
+    pub fn build<D: Discover>(&self, discover: D) -> OutlierDiscover<D> {
+        OutlierDiscover {
+            discover,
+            detector: Arc::new(Detector {
+                config: *self,
+                counts: Mutex::new(Counts::default()),
+            }),
+        }
+    }
+}
+
+// ===== impl OutlierDiscover =====
+
+impl<D: Discover> OutlierDiscover<D> {
+    /// Wraps a `Discover` with the default outlier detection settings.
+    pub fn new(discover: D) -> Self {
+        Builder::default().build(discover)
+    }
+
+    /// Returns the number of services that are currently ejected.
+    pub fn ejected(&self) -> usize {
+        self.detector.counts.lock().unwrap().ejected
+    }
+}
+
+impl<D: Discover> Stream for OutlierDiscover<D> {
+    type Item = Result<Change<D::Key, Outlier<D::Service>, D::Meta>, D::Error>;
+
+    /// Yields the next discovery change set.
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        use self::Change::*;
+
+        let this = self.project();
+        let change = match ready!(this.discover.poll_discover(cx)).transpose()? {
+            None => return Poll::Ready(None),
+            Some(Insert(k, svc)) => Insert(k, Outlier::new(svc, this.detector.clone())),
+            Some(Remove(k)) => Remove(k),
+            Some(Update(k, meta)) => Update(k, meta),
+        };
+
+        Poll::Ready(Some(Ok(change)))
+    }
+}
+
+// ===== impl Outlier =====
+
+impl<S> Outlier<S> {
+    fn new(service: S, detector: Arc<Detector>) -> Self {
+        detector.counts.lock().unwrap().total += 1;
+        Outlier {
+            service,
+            endpoint: Arc::new(Mutex::new(Endpoint::new())),
+            detector,
+            readmit: None,
+        }
+    }
+
+    /// Returns `true` if the service is currently ejected.
+    pub fn is_ejected(&self) -> bool {
+        self.endpoint.lock().unwrap().ejected_until.is_some()
+    }
+
+    /// Get a reference to the inner service
+    pub fn get_ref(&self) -> &S {
+        &self.service
+    }
+}
+
+impl<S: Load> Load for Outlier<S> {
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.service.load()
+    }
+}
+
+impl<S, Request> Service<Request> for Outlier<S>
+where
+    S: Service<Request>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let ejected_until = self.endpoint.lock().unwrap().ejected_until;
+        if let Some(until) = ejected_until {
+            let readmit = self.readmit.get_or_insert_with(|| delay_until(until));
+            ready!(Pin::new(readmit).poll(cx));
+            self.readmit = None;
+
+            debug!("readmitting ejected endpoint");
+            {
+                // The interval in which the service was ejected starts again,
+                // so that it must stay healthy for a whole interval before
+                // its ejection period is shortened.
+                let mut endpoint = self.endpoint.lock().unwrap();
+                endpoint.ejected_until = None;
+                endpoint.reset_window(Instant::now());
+            }
+            self.detector.counts.lock().unwrap().ejected -= 1;
+        }
+
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        ResponseFuture {
+            inner: self.service.call(request),
+            endpoint: self.endpoint.clone(),
+            detector: self.detector.clone(),
+        }
+    }
+}
+
+impl<S> Drop for Outlier<S> {
+    fn drop(&mut self) {
+        let mut endpoint = self.endpoint.lock().unwrap();
+        endpoint.removed = true;
+        let mut counts = self.detector.counts.lock().unwrap();
+        counts.total -= 1;
+        if endpoint.ejected_until.is_some() {
+            counts.ejected -= 1;
+        }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, T, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<T, E>>,
+{
+    type Output = Result<T, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let result = ready!(this.inner.poll(cx));
+        this.endpoint
+            .lock()
+            .unwrap()
+            .record(result.is_err(), this.detector);
+        Poll::Ready(result)
+    }
+}
+
+// ===== impl Detector =====
+
+impl Detector {
+    /// Returns `true` if another service may be ejected, and counts it as ejected.
+    fn try_eject(&self) -> bool {
+        let mut counts = self.counts.lock().unwrap();
+        let mut max = (counts.total as f64 * self.config.max_ejected_fraction) as usize;
+        if counts.total > 1 {
+            max = max.max(1);
+        }
+        if counts.ejected >= max {
+            return false;
+        }
+        counts.ejected += 1;
+        true
+    }
+}
+
+// ===== impl Endpoint =====
+
+impl Endpoint {
+    fn new() -> Self {
+        Endpoint {
+            consecutive_errors: 0,
+            window_start: Instant::now(),
+            window_requests: 0,
+            window_errors: 0,
+            ejections: 0,
+            ejected_until: None,
+            removed: false,
+        }
+    }
+
+    fn record(&mut self, is_err: bool, detector: &Detector) {
+        if self.removed {
+            // The service is gone, so it can no longer be ejected.
+            return;
+        }
+
+        let config = &detector.config;
+        let now = Instant::now();
+        if now.duration_since(self.window_start) >= config.interval {
+            // Every interval without an ejection shortens the next ejection.
+            if self.ejected_until.is_none() {
+                self.ejections = self.ejections.saturating_sub(1);
+            }
+            self.reset_window(now);
+        }
+
+        self.window_requests += 1;
+        if is_err {
+            self.consecutive_errors += 1;
+            self.window_errors += 1;
+        } else {
+            self.consecutive_errors = 0;
+        }
+
+        if self.ejected_until.is_some() {
+            // Responses to requests sent before the ejection may still arrive.
+            return;
+        }
+
+        let consecutive = match config.consecutive_errors {
+            Some(limit) => self.consecutive_errors >= limit,
+            None => false,
+        };
+        let rate = match config.error_rate {
+            Some(rate) => {
+                self.window_requests >= config.min_requests
+                    && f64::from(self.window_errors) >= rate * f64::from(self.window_requests)
+            }
+            None => false,
+        };
+        if !(consecutive || rate) || !detector.try_eject() {
+            return;
+        }
+
+        let multiplier = 2u32.saturating_pow(self.ejections);
+        let ejection = config
+            .base_ejection_time
+            .checked_mul(multiplier)
+            .unwrap_or(config.max_ejection_time)
+            .min(config.max_ejection_time);
+        debug!(
+            consecutive_errors = self.consecutive_errors,
+            window.requests = self.window_requests,
+            window.errors = self.window_errors,
+            ?ejection,
+            "ejecting endpoint"
+        );
+        self.ejections = self.ejections.saturating_add(1);
+        self.ejected_until = Some(now + ejection);
+        self.consecutive_errors = 0;
+        self.reset_window(now);
+    }
+
+    fn reset_window(&mut self, now: Instant) {
+        self.window_start = now;
+        self.window_requests = 0;
+        self.window_errors = 0;
+    }
+}
@@ -0,0 +1,185 @@
+use crate::balance::p2c::Balance;
+use crate::discover::ServiceList;
+use crate::load;
+use futures_util::pin_mut;
+use std::time::Duration;
+use tokio::time;
+use tokio_test::{assert_ready_err, assert_ready_ok, task};
+use tower_test::{assert_request_eq, mock};
+
+use super::*;
+
+type Mock = mock::Mock<(), &'static str>;
+type Disco = OutlierDiscover<ServiceList<Vec<load::Constant<Mock, usize>>>>;
+
+#[tokio::test]
+async fn ejects_and_readmits() {
+    time::pause();
+
+    let (mock_a, handle_a) = mock::pair();
+    let (mock_b, handle_b) = mock::pair();
+    pin_mut!(handle_a);
+    pin_mut!(handle_b);
+    handle_a.allow(100);
+    handle_b.allow(100);
+
+    // `a` is always preferred while it is ready.
+    let services = vec![
+        load::Constant::new(mock_a, 0),
+        load::Constant::new(mock_b, 1),
+    ];
+    let disco: Disco = Builder::new()
+        .consecutive_errors(Some(2))
+        .base_ejection_time(Duration::from_secs(10))
+        .max_ejected_fraction(0.5)
+        .build(ServiceList::new(services));
+    let mut svc = mock::Spawn::new(Balance::new(disco));
+
+    for _ in 0..2 {
+        assert_ready_ok!(svc.poll_ready());
+        let mut fut = task::spawn(svc.call(()));
+        assert_request_eq!(handle_a, ()).send_error("failed");
+        assert_ready_err!(fut.poll());
+    }
+    assert_eq!(svc.get_mut().discover_mut().ejected(), 1);
+
+    // Requests are sent to `b` while `a` is ejected.
+    assert_ready_ok!(svc.poll_ready());
+    let mut fut = task::spawn(svc.call(()));
+    assert_request_eq!(handle_b, ()).send_response("b");
+    assert_eq!(assert_ready_ok!(fut.poll()), "b");
+
+    // `a` is readmitted once its ejection period has elapsed.
+    time::advance(Duration::from_millis(10_001)).await;
+    assert_ready_ok!(svc.poll_ready());
+    assert_eq!(svc.get_mut().discover_mut().ejected(), 0);
+    let mut fut = task::spawn(svc.call(()));
+    assert_request_eq!(handle_a, ()).send_response("a");
+    assert_eq!(assert_ready_ok!(fut.poll()), "a");
+}
+
+#[tokio::test]
+async fn ejection_time_grows() {
+    time::pause();
+
+    let (mock, mut handle) = mock::pair::<(), &'static str>();
+    handle.allow(100);
+
+    let mut builder = Builder::new();
+    builder
+        .consecutive_errors(Some(1))
+        .base_ejection_time(Duration::from_secs(10))
+        .max_ejection_time(Duration::from_secs(25))
+        .max_ejected_fraction(1.0);
+    let detector = builder.build(ServiceList::new(Vec::<Mock>::new())).detector;
+    let mut svc = mock::Spawn::new(Outlier::new(mock, detector));
+
+    // Every ejection doubles the ejection period, up to the maximum.
+    for &secs in &[10, 20, 25] {
+        assert_ready_ok!(svc.poll_ready());
+        let mut fut = task::spawn(svc.call(()));
+        assert_request_eq!(handle, ()).send_error("failed");
+        assert_ready_err!(fut.poll());
+        assert!(svc.get_ref().is_ejected());
+
+        time::advance(Duration::from_secs(secs) - Duration::from_millis(1)).await;
+        assert!(svc.poll_ready().is_pending());
+        time::advance(Duration::from_millis(2)).await;
+        assert_ready_ok!(svc.poll_ready());
+        assert!(!svc.get_ref().is_ejected());
+    }
+}
+
+#[tokio::test]
+async fn error_rate() {
+    time::pause();
+
+    let (mock, mut handle) = mock::pair::<(), &'static str>();
+    handle.allow(100);
+
+    let mut builder = Builder::new();
+    builder
+        .consecutive_errors(None)
+        .error_rate(Some(0.5))
+        .min_requests(4)
+        .max_ejected_fraction(1.0);
+    let detector = builder.build(ServiceList::new(Vec::<Mock>::new())).detector;
+    let mut svc = mock::Spawn::new(Outlier::new(mock, detector));
+
+    for &fail in &[true, false, true, false] {
+        assert!(!svc.get_ref().is_ejected());
+        assert_ready_ok!(svc.poll_ready());
+        let mut fut = task::spawn(svc.call(()));
+        let (_, tx) = handle.next_request().await.unwrap();
+        if fail {
+            tx.send_error("failed");
+            assert_ready_err!(fut.poll());
+        } else {
+            tx.send_response("ok");
+            assert_ready_ok!(fut.poll());
+        }
+    }
+    assert!(svc.get_ref().is_ejected());
+}
+
+#[tokio::test]
+async fn max_ejected_fraction() {
+    let (mock_a, handle_a) = mock::pair();
+    let (mock_b, handle_b) = mock::pair();
+    pin_mut!(handle_a);
+    pin_mut!(handle_b);
+    handle_a.allow(100);
+    handle_b.allow(100);
+
+    let services = vec![
+        load::Constant::new(mock_a, 0),
+        load::Constant::new(mock_b, 1),
+    ];
+    let disco: Disco = Builder::new()
+        .consecutive_errors(Some(1))
+        .max_ejected_fraction(0.0)
+        .build(ServiceList::new(services));
+    let mut svc = mock::Spawn::new(Balance::new(disco));
+
+    // `a` is ejected, since one service may always be ejected.
+    assert_ready_ok!(svc.poll_ready());
+    let mut fut = task::spawn(svc.call(()));
+    assert_request_eq!(handle_a, ()).send_error("failed");
+    assert_ready_err!(fut.poll());
+
+    // `b` is not ejected, since that would exceed the limit.
+    for _ in 0..2 {
+        assert_ready_ok!(svc.poll_ready());
+        let mut fut = task::spawn(svc.call(()));
+        assert_request_eq!(handle_b, ()).send_error("failed");
+        assert_ready_err!(fut.poll());
+    }
+    assert_eq!(svc.get_mut().discover_mut().ejected(), 1);
+}
+
+#[tokio::test]
+async fn removed_endpoint_is_not_ejected() {
+    let (mock, mut handle) = mock::pair::<(), &'static str>();
+    handle.allow(100);
+
+    let mut builder = Builder::new();
+    builder
+        .consecutive_errors(Some(1))
+        .max_ejected_fraction(0.5);
+    let disco = builder.build(ServiceList::new(Vec::<Mock>::new()));
+    let others = (0..2)
+        .map(|_| Outlier::new(mock::pair::<(), &'static str>().0, disco.detector.clone()))
+        .collect::<Vec<_>>();
+    let mut svc = mock::Spawn::new(Outlier::new(mock, disco.detector.clone()));
+
+    assert_ready_ok!(svc.poll_ready());
+    let mut fut = task::spawn(svc.call(()));
+    let (_, rsp) = handle.next_request().await.unwrap();
+
+    // The service is removed while its request is in flight.
+    drop(svc);
+    rsp.send_error("failed");
+    assert_ready_err!(fut.poll());
+    assert_eq!(disco.ejected(), 0);
+    drop(others);
+}
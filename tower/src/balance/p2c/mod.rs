@@ -20,6 +20,10 @@
 //! when services become available or go away. If you have a fixed set of services, consider using
 //! [`ServiceList`](tower::discover::ServiceList).
 //!
+//! If some services have more capacity than others, wrap each of them in
+//! [`Weighted`](tower::load::Weighted) when it is discovered. Its load is then divided by its
+//! weight before it is compared, so that services receive traffic in proportion to their weights.
+//!
 //! Since the load balancer needs to perform _random_ choices, the constructors in this module
 //! usually come in two forms: one that uses randomness provided by the operating system, and one
 //! that lets you specify the random seed to use. Usually the former is what you'll want, though
@@ -1,11 +1,14 @@
 use super::super::error;
+use super::super::snapshot::{self, Endpoint};
+use super::super::strategy::{PowerOfTwoChoices, Strategy};
 use crate::discover::{Change, Discover};
-use crate::load::Load;
+use crate::load::{HasWeight, Load, WeightedDiscover};
 use crate::ready_cache::{error::Failed, ReadyCache};
 use futures_core::ready;
 use futures_util::future::{self, TryFutureExt};
 use pin_project::pin_project;
-use rand::{rngs::SmallRng, Rng, SeedableRng};
+use rand::Rng;
+use std::collections::HashMap;
 use std::hash::Hash;
 use std::marker::PhantomData;
 use std::{
@@ -22,6 +25,14 @@ use tracing::{debug, trace};
 ///
 /// See the [module-level documentation](..) for details.
 ///
+/// The latest metadata yielded for each service by the `Discover`, such as its weight or zone,
+/// is available through [`Balance::metadata`]. A balancer constructed with [`Balance::weighted`]
+/// also weights each service by its metadata.
+///
+/// Ready services are chosen using [power of two choices](PowerOfTwoChoices) by default. Other
+/// [strategies](crate::balance::strategy) can be used by constructing the balancer with
+/// [`Balance::with_strategy`].
+///
 /// Note that `Balance` requires that the `Discover` you use is `Unpin` in order to implement
 /// `Service`. This is because it needs to be accessed from `Service::poll_ready`, which takes
 /// `&mut self`. You can achieve this easily by wrapping your `Discover` in [`Box::pin`] before you
@@ -29,7 +40,7 @@ use tracing::{debug, trace};
 ///
 /// [`Box::pin`]: https://doc.rust-lang.org/std/boxed/struct.Box.html#method.pin
 /// [#319]: https://github.com/tower-rs/tower/issues/319
-pub struct Balance<D, Req>
+pub struct Balance<D, Req, St = PowerOfTwoChoices>
 where
     D: Discover,
     D::Key: Hash,
@@ -38,23 +49,27 @@ where
 
     services: ReadyCache<D::Key, D::Service, Req>,
     ready_index: Option<usize>,
+    selections: HashMap<D::Key, u64>,
+    metadata: HashMap<D::Key, D::Meta>,
 
-    rng: SmallRng,
+    strategy: St,
 
     _req: PhantomData<Req>,
 }
 
-impl<D: Discover, Req> fmt::Debug for Balance<D, Req>
+impl<D: Discover, Req, St> fmt::Debug for Balance<D, Req, St>
 where
     D: fmt::Debug,
     D::Key: Hash + fmt::Debug,
     D::Service: fmt::Debug,
     Req: fmt::Debug,
+    St: fmt::Debug,
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Balance")
             .field("discover", &self.discover)
             .field("services", &self.services)
+            .field("strategy", &self.strategy)
             .finish()
     }
 }
@@ -87,43 +102,136 @@ where
 {
     /// Constructs a load balancer that uses operating system entropy.
     pub fn new(discover: D) -> Self {
-        Self {
-            rng: SmallRng::from_entropy(),
-            discover,
-            services: ReadyCache::default(),
-            ready_index: None,
-
-            _req: PhantomData,
-        }
+        Self::with_strategy(discover, PowerOfTwoChoices::new())
     }
 
     /// Constructs a load balancer seeded with the provided random number generator.
     pub fn from_rng<R: Rng>(discover: D, rng: R) -> Result<Self, rand::Error> {
-        let rng = SmallRng::from_rng(rng)?;
-        Ok(Self {
-            rng,
+        Ok(Self::with_strategy(
+            discover,
+            PowerOfTwoChoices::from_rng(rng)?,
+        ))
+    }
+}
+
+impl<D, Req> Balance<WeightedDiscover<D>, Req>
+where
+    D: Discover,
+    D::Key: Hash + Clone,
+    D::Meta: HasWeight,
+    D::Service: Service<Req>,
+    <D::Service as Service<Req>>::Error: Into<crate::BoxError>,
+{
+    /// Constructs a load balancer that divides the load of each service by the weight in its
+    /// latest metadata, using [`WeightedDiscover`].
+    ///
+    /// Services are weighted with the default weight until their metadata is discovered.
+    pub fn weighted(discover: D) -> Self {
+        Self::new(WeightedDiscover::new(discover))
+    }
+}
+
+impl<D, Req, St> Balance<D, Req, St>
+where
+    D: Discover,
+    D::Key: Hash,
+    D::Service: Service<Req>,
+    <D::Service as Service<Req>>::Error: Into<crate::BoxError>,
+{
+    /// Constructs a load balancer that chooses ready services using `strategy`.
+    pub fn with_strategy(discover: D, strategy: St) -> Self {
+        Self {
             discover,
             services: ReadyCache::default(),
             ready_index: None,
+            selections: HashMap::new(),
+            metadata: HashMap::new(),
+
+            strategy,
 
             _req: PhantomData,
-        })
+        }
     }
 
     /// Returns the number of endpoints currently tracked by the balancer.
     pub fn len(&self) -> usize {
         self.services.len()
     }
+
+    /// Returns the latest metadata discovered for the endpoint identified by `key`.
+    pub fn metadata(&self, key: &D::Key) -> Option<&D::Meta> {
+        self.metadata.get(key)
+    }
+
+    /// Returns the number of endpoints that were ready when the balancer was last polled.
+    pub(crate) fn ready_len(&self) -> usize {
+        self.services.ready_len()
+    }
 }
 
-impl<D, Req> Balance<D, Req>
+impl<D, Req, St> Balance<D, Req, St>
+where
+    D: Discover,
+    D::Key: Hash + Clone,
+    D::Service: Load,
+{
+    /// Returns a snapshot of every endpoint currently tracked by the balancer.
+    ///
+    /// Ready endpoints are listed before pending ones. An endpoint that is being replaced is listed
+    /// once, as ready, until its replacement becomes ready.
+    pub fn snapshot(&self) -> Vec<Endpoint<D::Key, <D::Service as Load>::Metric>> {
+        let selections = |key: &D::Key| self.selections.get(key).cloned().unwrap_or(0);
+        let ready = (0..self.services.ready_len()).map(|idx| {
+            let (key, svc) = self.services.get_ready_index(idx).expect("invalid index");
+            Endpoint {
+                key: key.clone(),
+                state: snapshot::State::Ready,
+                load: Some(svc.load()),
+                selections: selections(key),
+            }
+        });
+        let pending = self
+            .services
+            .pending_keys()
+            .filter(|key| self.services.get_ready(*key).is_none())
+            .map(|key| Endpoint {
+                key: key.clone(),
+                state: snapshot::State::Pending,
+                load: None,
+                selections: selections(key),
+            });
+        ready.chain(pending).collect()
+    }
+
+    /// Returns the load of the least loaded ready endpoint.
+    pub(crate) fn min_ready_load(&self) -> Option<<D::Service as Load>::Metric> {
+        self.ready_loads()
+            .map(|(_, load)| load)
+            .fold(None, |least, load| match least {
+                Some(least) if least <= load => Some(least),
+                _ => Some(load),
+            })
+    }
+
+    /// Iterates over the keys and loads of the ready endpoints, without cloning their keys.
+    pub(crate) fn ready_loads(
+        &self,
+    ) -> impl Iterator<Item = (&D::Key, <D::Service as Load>::Metric)> + '_ {
+        (0..self.services.ready_len()).map(move |idx| {
+            let (key, svc) = self.services.get_ready_index(idx).expect("invalid index");
+            (key, svc.load())
+        })
+    }
+}
+
+impl<D, Req, St> Balance<D, Req, St>
 where
     D: Discover + Unpin,
     D::Key: Hash + Clone,
     D::Error: Into<crate::BoxError>,
-    D::Service: Service<Req> + Load,
-    <D::Service as Load>::Metric: std::fmt::Debug,
+    D::Service: Service<Req>,
     <D::Service as Service<Req>>::Error: Into<crate::BoxError>,
+    St: Strategy<D::Key, D::Service, Req>,
 {
     /// Polls `discover` for updates, adding new items to `not_ready`.
     ///
@@ -141,14 +249,32 @@ where
                 None => return Poll::Ready(None),
                 Some(Change::Remove(key)) => {
                     trace!("remove");
-                    self.services.evict(&key);
+                    // Evicting a ready service may reorder the remaining ones,
+                    // so any prior selection is discarded.
+                    if self.services.evict(&key) {
+                        self.ready_index = None;
+                    }
+                    self.selections.remove(&key);
+                    self.metadata.remove(&key);
                 }
                 Some(Change::Insert(key, svc)) => {
                     trace!("insert");
                     // If this service already existed in the set, it will be
-                    // replaced as the new one becomes ready.
+                    // replaced as the new one becomes ready, and keeps its
+                    // metadata.
+                    self.selections.entry(key.clone()).or_insert(0);
                     self.services.push(key, svc);
                 }
+                Some(Change::Update(key, meta)) => {
+                    if self.services.get_ready(&key).is_none()
+                        && !self.services.pending_contains(&key)
+                    {
+                        debug!("ignoring metadata of an unknown endpoint");
+                        continue;
+                    }
+                    trace!("update");
+                    self.metadata.insert(key, meta);
+                }
             }
         }
     }
@@ -166,10 +292,11 @@ where
                     debug_assert!(self.services.pending_len() > 0);
                     break;
                 }
-                Poll::Ready(Err(error)) => {
+                Poll::Ready(Err(Failed(key, error))) => {
                     // An individual service was lost; continue processing
                     // pending services.
                     debug!(%error, "dropping failed endpoint");
+                    self.forget_failed(&key);
                 }
             }
         }
@@ -180,41 +307,16 @@ where
         );
     }
 
-    /// Performs P2C on inner services to find a suitable endpoint.
-    fn p2c_ready_index(&mut self) -> Option<usize> {
-        match self.services.ready_len() {
-            0 => None,
-            1 => Some(0),
-            len => {
-                // Get two distinct random indexes (in a random order) and
-                // compare the loads of the service at each index.
-                let idxs = rand::seq::index::sample(&mut self.rng, len, 2);
-
-                let aidx = idxs.index(0);
-                let bidx = idxs.index(1);
-                debug_assert_ne!(aidx, bidx, "random indices must be distinct");
-
-                let aload = self.ready_index_load(aidx);
-                let bload = self.ready_index_load(bidx);
-                let chosen = if aload <= bload { aidx } else { bidx };
-
-                trace!(
-                    a.index = aidx,
-                    a.load = ?aload,
-                    b.index = bidx,
-                    b.load = ?bload,
-                    chosen = if chosen == aidx { "a" } else { "b" },
-                    "p2c",
-                );
-                Some(chosen)
-            }
+    /// Forgets the selections of a failed endpoint, unless the service it replaced or its
+    /// replacement is still tracked.
+    fn forget_failed(&mut self, key: &D::Key) {
+        if self.services.get_ready(key).is_none() && !self.services.pending_contains(key) {
+            self.selections.remove(key);
         }
     }
 
-    /// Accesses a ready endpoint by index and returns its current load.
-    fn ready_index_load(&self, index: usize) -> <D::Service as Load>::Metric {
-        let (_, svc) = self.services.get_ready_index(index).expect("invalid index");
-        svc.load()
+    pub(crate) fn discover(&self) -> &D {
+        &self.discover
     }
 
     pub(crate) fn discover_mut(&mut self) -> &mut D {
@@ -222,14 +324,14 @@ where
     }
 }
 
-impl<D, Req> Service<Req> for Balance<D, Req>
+impl<D, Req, St> Service<Req> for Balance<D, Req, St>
 where
     D: Discover + Unpin,
     D::Key: Hash + Clone,
     D::Error: Into<crate::BoxError>,
-    D::Service: Service<Req> + Load,
-    <D::Service as Load>::Metric: std::fmt::Debug,
+    D::Service: Service<Req>,
     <D::Service as Service<Req>>::Error: Into<crate::BoxError>,
+    St: Strategy<D::Key, D::Service, Req>,
 {
     type Response = <D::Service as Service<Req>>::Response;
     type Error = crate::BoxError;
@@ -239,8 +341,9 @@ where
     >;
 
     fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        // `ready_index` may have already been set by a prior invocation. These
-        // updates cannot disturb the order of existing ready services.
+        // `ready_index` may have already been set by a prior invocation. It is
+        // discarded if these updates disturb the order of existing ready
+        // services.
         let _ = self.update_pending_from_discover(cx)?;
         self.promote_pending_to_ready(cx);
 
@@ -262,17 +365,18 @@ where
                         // The service is no longer ready. Try to find a new one.
                         trace!("ready service became unavailable");
                     }
-                    Err(Failed(_, error)) => {
+                    Err(Failed(key, error)) => {
                         // The ready endpoint failed, so log the error and try
                         // to find a new one.
                         debug!(%error, "endpoint failed");
+                        self.forget_failed(&key);
                     }
                 }
             }
 
-            // Select a new service by comparing two at random and using the
-            // lesser-loaded service.
-            self.ready_index = self.p2c_ready_index();
+            // Select a new service using the strategy (by default, by
+            // comparing two at random and using the lesser-loaded service).
+            self.ready_index = self.strategy.select(&self.services);
             if self.ready_index.is_none() {
                 debug_assert_eq!(self.services.ready_len(), 0);
                 // We have previously registered interest in updates from
@@ -284,6 +388,13 @@ where
 
     fn call(&mut self, request: Req) -> Self::Future {
         let index = self.ready_index.take().expect("called before ready");
+        let (key, _) = self
+            .services
+            .get_ready_index(index)
+            .expect("called before ready");
+        if let Some(count) = self.selections.get_mut(key) {
+            *count += 1;
+        }
         self.services
             .call_ready_index(index, request)
             .map_err(Into::into)
@@ -1,11 +1,12 @@
 use crate::discover::ServiceList;
 use crate::load;
 use futures_util::pin_mut;
-use std::task::Poll;
+use std::{pin::Pin, task::Poll};
 use tokio_test::{assert_pending, assert_ready, assert_ready_ok, task};
 use tower_test::{assert_request_eq, mock};
 
 use super::*;
+use crate::balance::strategy::{LeastLoaded, Random, RoundRobin};
 
 #[tokio::test]
 async fn empty() {
@@ -123,3 +124,406 @@ async fn two_endpoints_with_equal_load() {
         "balancer must drop failed endpoints",
     );
 }
+
+#[tokio::test]
+async fn weighted_endpoints() {
+    let (mock_a, handle_a) = mock::pair::<(), &'static str>();
+    let (mock_b, handle_b) = mock::pair::<(), &'static str>();
+    let completion = load::CompleteOnResponse::default();
+    let mock_a = load::Weighted::new(
+        load::PendingRequests::new(mock_a, completion),
+        load::Weight::new(1.0),
+    );
+    let mock_b = load::Weighted::new(
+        load::PendingRequests::new(mock_b, completion),
+        load::Weight::new(3.0),
+    );
+
+    pin_mut!(handle_a);
+    pin_mut!(handle_b);
+    handle_a.allow(100);
+    handle_b.allow(100);
+
+    let disco = ServiceList::new(vec![mock_a, mock_b].into_iter());
+    let mut svc = mock::Spawn::new(Balance::new(disco));
+
+    // Keep every request pending, so that the load of each endpoint grows with the number of
+    // requests it has been sent.
+    let mut futs = Vec::new();
+    for _ in 0..40 {
+        assert_ready_ok!(svc.poll_ready());
+        futs.push(task::spawn(svc.call(())));
+    }
+
+    let count = |mut h: Pin<&mut mock::Handle<(), &'static str>>| {
+        let mut n = 0;
+        while let Poll::Ready(Some(_)) = h.as_mut().poll_request() {
+            n += 1;
+        }
+        n
+    };
+    let a = count(handle_a.as_mut());
+    let b = count(handle_b.as_mut());
+
+    // The endpoint with three times the weight receives three times the requests.
+    assert_eq!(a + b, 40);
+    assert!((9..=11).contains(&a), "a received {} requests", a);
+    assert!((29..=31).contains(&b), "b received {} requests", b);
+}
+
+#[tokio::test]
+async fn weighted_by_metadata() {
+    use crate::discover::Dynamic;
+
+    let (disco, discover) = Dynamic::with_metadata();
+    let mut svc = mock::Spawn::new(Balance::<_, ()>::weighted(disco));
+
+    let mut handles = Vec::new();
+    for (name, load) in &[("a", 2usize), ("b", 3)] {
+        let (mock, mut handle) = mock::pair::<(), &'static str>();
+        handle.allow(100);
+        discover
+            .insert(*name, load::Constant::new(mock, *load))
+            .unwrap();
+        handles.push(handle);
+    }
+
+    // With two endpoints, both are compared for every request.
+    let mut dispatch = |handles: &mut Vec<mock::Handle<(), &'static str>>| {
+        assert_ready_ok!(svc.poll_ready());
+        let _fut = svc.call(());
+        handles
+            .iter_mut()
+            .position(|h| h.poll_request().is_ready())
+            .expect("request was not sent")
+    };
+
+    // Services have the default weight until their metadata is discovered.
+    assert_eq!(dispatch(&mut handles), 0);
+
+    // Updating the weight of a ready service takes effect immediately.
+    discover.update("b", load::Weight::new(3.0)).unwrap();
+    assert_eq!(dispatch(&mut handles), 1);
+    assert_eq!(svc.get_ref().metadata(&"b"), Some(&load::Weight::new(3.0)));
+}
+
+#[tokio::test]
+async fn slow_start_endpoints() {
+    use std::time::Duration;
+
+    tokio::time::pause();
+
+    let (mock_a, handle_a) = mock::pair::<(), &'static str>();
+    let (mock_b, handle_b) = mock::pair::<(), &'static str>();
+    // Both endpoints are idle, but `b` was just started.
+    let warm = load::SlowStart::new(load::Constant::new(mock_a, 0), Duration::from_secs(0));
+    let cold = load::SlowStart::new(load::Constant::new(mock_b, 0), Duration::from_secs(10));
+
+    pin_mut!(handle_a);
+    pin_mut!(handle_b);
+    handle_a.allow(100);
+    handle_b.allow(100);
+
+    let disco = ServiceList::new(vec![warm, cold]);
+    let mut svc = mock::Spawn::new(Balance::new(disco));
+
+    for _ in 0..20 {
+        assert_ready_ok!(svc.poll_ready());
+        let mut fut = task::spawn(svc.call(()));
+        for h in &mut [handle_a.as_mut(), handle_b.as_mut()] {
+            if let Poll::Ready(Some((_, tx))) = h.as_mut().poll_request() {
+                tx.send_response("done");
+            }
+        }
+        assert_ready_ok!(fut.poll());
+    }
+
+    // The cold endpoint looks loaded even though it is idle.
+    let snapshot = svc.get_ref().snapshot();
+    let selections = |key| {
+        snapshot
+            .iter()
+            .find(|e| *e.key() == key)
+            .unwrap()
+            .selections()
+    };
+    assert_eq!(selections(0), 20);
+    assert_eq!(selections(1), 0);
+}
+
+/// Sends `n` requests through `svc`, and returns the name of the endpoint each was sent to.
+fn dispatch<St>(
+    svc: &mut mock::Spawn<Balance<ServiceList<Vec<Named>>, (), St>>,
+    handles: &mut [(Pin<&mut mock::Handle<(), &'static str>>, &'static str)],
+    n: usize,
+) -> Vec<&'static str>
+where
+    St: crate::balance::strategy::Strategy<usize, Named, ()>,
+{
+    let mut chosen = Vec::new();
+    for _ in 0..n {
+        assert_ready_ok!(svc.poll_ready());
+        let mut fut = task::spawn(svc.call(()));
+        for (h, name) in handles.iter_mut() {
+            if let Poll::Ready(Some((_, tx))) = h.as_mut().poll_request() {
+                tx.send_response(name);
+                chosen.push(*name);
+            }
+        }
+        assert_ready_ok!(fut.poll());
+    }
+    chosen
+}
+
+type Named = load::Constant<mock::Mock<(), &'static str>, usize>;
+
+#[tokio::test]
+async fn round_robin() {
+    let (mock_a, handle_a) = mock::pair();
+    let (mock_b, handle_b) = mock::pair();
+    let (mock_c, handle_c) = mock::pair();
+    pin_mut!(handle_a);
+    pin_mut!(handle_b);
+    pin_mut!(handle_c);
+    handle_a.allow(100);
+    handle_b.allow(100);
+    handle_c.allow(100);
+
+    let services = vec![
+        load::Constant::new(mock_a, 0),
+        load::Constant::new(mock_b, 0),
+        load::Constant::new(mock_c, 0),
+    ];
+    let mut svc = mock::Spawn::new(Balance::with_strategy(
+        ServiceList::new(services),
+        RoundRobin::new(),
+    ));
+
+    let mut handles = [(handle_a, "a"), (handle_b, "b"), (handle_c, "c")];
+    let chosen = dispatch(&mut svc, &mut handles, 6);
+    for name in &["a", "b", "c"] {
+        assert_eq!(chosen.iter().filter(|c| *c == name).count(), 2);
+    }
+    // Each endpoint is used once before any endpoint is reused.
+    assert_eq!(chosen[..3], chosen[3..]);
+}
+
+#[tokio::test]
+async fn least_loaded() {
+    let (mock_a, handle_a) = mock::pair();
+    let (mock_b, handle_b) = mock::pair();
+    let (mock_c, handle_c) = mock::pair();
+    pin_mut!(handle_a);
+    pin_mut!(handle_b);
+    pin_mut!(handle_c);
+    handle_a.allow(100);
+    handle_b.allow(100);
+    handle_c.allow(100);
+
+    let services = vec![
+        load::Constant::new(mock_a, 2),
+        load::Constant::new(mock_b, 1),
+        load::Constant::new(mock_c, 3),
+    ];
+    let mut svc = mock::Spawn::new(Balance::with_strategy(
+        ServiceList::new(services),
+        LeastLoaded::new(),
+    ));
+
+    let mut handles = [(handle_a, "a"), (handle_b, "b"), (handle_c, "c")];
+    assert_eq!(dispatch(&mut svc, &mut handles, 10), vec!["b"; 10]);
+}
+
+#[tokio::test]
+async fn random() {
+    let (mock_a, handle_a) = mock::pair();
+    let (mock_b, handle_b) = mock::pair();
+    pin_mut!(handle_a);
+    pin_mut!(handle_b);
+    handle_a.allow(100);
+    handle_b.allow(100);
+
+    // Random ignores load, so the more loaded endpoint is also used.
+    let services = vec![
+        load::Constant::new(mock_a, 0),
+        load::Constant::new(mock_b, 100),
+    ];
+    let mut svc = mock::Spawn::new(Balance::with_strategy(
+        ServiceList::new(services),
+        Random::new(),
+    ));
+
+    let mut handles = [(handle_a, "a"), (handle_b, "b")];
+    let chosen = dispatch(&mut svc, &mut handles, 50);
+    assert_eq!(chosen.len(), 50);
+    assert!(chosen.contains(&"a"));
+    assert!(chosen.contains(&"b"));
+}
+
+#[tokio::test]
+async fn snapshot() {
+    use crate::balance::snapshot::State;
+
+    let (mock_a, handle_a) = mock::pair();
+    let (mock_b, handle_b) = mock::pair();
+    pin_mut!(handle_a);
+    pin_mut!(handle_b);
+
+    let services: Vec<Named> = vec![
+        load::Constant::new(mock_a, 3),
+        load::Constant::new(mock_b, 7),
+    ];
+    let mut svc = mock::Spawn::new(Balance::new(ServiceList::new(services)));
+
+    handle_a.allow(1);
+    handle_b.allow(0);
+    assert_ready_ok!(svc.poll_ready());
+
+    let snapshot = svc.get_ref().snapshot();
+    assert_eq!(snapshot.len(), 2);
+    assert_eq!(*snapshot[0].key(), 0);
+    assert_eq!(snapshot[0].state(), State::Ready);
+    assert_eq!(snapshot[0].load(), Some(&3));
+    assert_eq!(snapshot[0].selections(), 0);
+    assert_eq!(*snapshot[1].key(), 1);
+    assert_eq!(snapshot[1].state(), State::Pending);
+    assert_eq!(snapshot[1].load(), None);
+
+    let mut fut = task::spawn(svc.call(()));
+    assert_request_eq!(handle_a, ()).send_response("a");
+    assert_ready_ok!(fut.poll());
+
+    // The called service is pending until it is polled again.
+    let snapshot = svc.get_ref().snapshot();
+    assert!(snapshot.iter().all(|e| e.state() == State::Pending));
+    let a = snapshot.iter().find(|e| *e.key() == 0).unwrap();
+    assert_eq!(a.selections(), 1);
+
+    // Failed endpoints are dropped from the snapshot.
+    handle_a.send_error("endpoint lost");
+    handle_b.allow(1);
+    assert_ready_ok!(svc.poll_ready());
+    let snapshot = svc.get_ref().snapshot();
+    assert_eq!(snapshot.len(), 1);
+    assert_eq!(*snapshot[0].key(), 1);
+    assert_eq!(snapshot[0].load(), Some(&7));
+}
+
+#[tokio::test]
+async fn metadata() {
+    use crate::discover::Dynamic;
+
+    let (disco, discover) = Dynamic::with_metadata();
+    let mut svc = mock::Spawn::new(Balance::<_, ()>::new(disco));
+
+    let (mock_a, _handle_a) = mock::pair::<(), &'static str>();
+    discover
+        .insert("a", load::Constant::new(mock_a, 0))
+        .unwrap();
+    discover.update("a", "zone-1").unwrap();
+    assert_ready_ok!(svc.poll_ready());
+    assert_eq!(svc.get_ref().metadata(&"a"), Some(&"zone-1"));
+
+    // Replacing the service keeps its metadata, which can be updated
+    // without replacing it again.
+    let (mock_b, _handle_b) = mock::pair();
+    discover
+        .insert("a", load::Constant::new(mock_b, 0))
+        .unwrap();
+    assert_ready_ok!(svc.poll_ready());
+    assert_eq!(svc.get_ref().metadata(&"a"), Some(&"zone-1"));
+    discover.update("a", "zone-2").unwrap();
+    assert_ready_ok!(svc.poll_ready());
+    assert_eq!(svc.get_ref().len(), 1);
+    assert_eq!(svc.get_ref().metadata(&"a"), Some(&"zone-2"));
+
+    // Replacing it explicitly removes it first, which clears its metadata.
+    let (mock_c, _handle_c) = mock::pair();
+    discover
+        .replace("a", load::Constant::new(mock_c, 0))
+        .unwrap();
+    assert_ready_ok!(svc.poll_ready());
+    assert_eq!(svc.get_ref().metadata(&"a"), None);
+
+    discover.remove("a").unwrap();
+    assert_pending!(svc.poll_ready());
+    assert_eq!(svc.get_ref().metadata(&"a"), None);
+
+    // Metadata of endpoints that aren't discovered is ignored.
+    discover.update("a", "zone-3").unwrap();
+    assert_pending!(svc.poll_ready());
+    assert_eq!(svc.get_ref().metadata(&"a"), None);
+}
+
+#[tokio::test]
+async fn snapshot_during_replacement() {
+    use crate::balance::snapshot::State;
+    use crate::discover::Dynamic;
+
+    let (disco, discover) = Dynamic::new();
+    let mut svc = mock::Spawn::new(Balance::new(disco));
+
+    let (mock_a, handle_a) = mock::pair();
+    pin_mut!(handle_a);
+    handle_a.allow(2);
+    discover
+        .insert("a", load::Constant::new(mock_a, 0))
+        .unwrap();
+    assert_ready_ok!(svc.poll_ready());
+    let mut fut = task::spawn(svc.call(()));
+    assert_request_eq!(handle_a, ()).send_response("a");
+    assert_ready_ok!(fut.poll());
+    assert_ready_ok!(svc.poll_ready());
+
+    // The replaced service is listed once while its replacement is pending.
+    let (mock_b, handle_b) = mock::pair::<(), &'static str>();
+    pin_mut!(handle_b);
+    handle_b.allow(0);
+    discover
+        .insert("a", load::Constant::new(mock_b, 0))
+        .unwrap();
+    assert_ready_ok!(svc.poll_ready());
+    let snapshot = svc.get_ref().snapshot();
+    assert_eq!(snapshot.len(), 1);
+    assert_eq!(snapshot[0].state(), State::Ready);
+    assert_eq!(snapshot[0].selections(), 1);
+
+    // The replacement fails, but the replaced service keeps its selections.
+    handle_b.send_error("endpoint lost");
+    assert_ready_ok!(svc.poll_ready());
+    let snapshot = svc.get_ref().snapshot();
+    assert_eq!(snapshot.len(), 1);
+    assert_eq!(snapshot[0].selections(), 1);
+}
+
+#[tokio::test]
+async fn remove_discards_selection() {
+    use crate::discover::Dynamic;
+
+    let (disco, discover) = Dynamic::new();
+    let mut svc = mock::Spawn::new(Balance::with_strategy(disco, LeastLoaded::new()));
+
+    let mut handles = Vec::new();
+    for (name, load) in &[("a", 0), ("b", 1), ("c", 2)] {
+        let (mock, mut handle) = mock::pair::<(), &'static str>();
+        handle.allow(100);
+        discover
+            .insert(*name, load::Constant::new(mock, *load))
+            .unwrap();
+        handles.push(handle);
+    }
+    // `a` is selected.
+    assert_ready_ok!(svc.poll_ready());
+
+    // Evicting `a` moves `c` into its place in the ready set, so the least
+    // loaded service must be selected again rather than whichever service now
+    // has the selected index.
+    discover.remove("a").unwrap();
+    assert_ready_ok!(svc.poll_ready());
+    let mut fut = task::spawn(svc.call(()));
+    match handles[1].poll_request() {
+        Poll::Ready(Some((_, tx))) => tx.send_response("b"),
+        _ => panic!("request was not sent to the least loaded service"),
+    }
+    assert_eq!(assert_ready_ok!(fut.poll()), "b");
+}
@@ -12,6 +12,19 @@
 //! more services, then the latest added service is removed. In either case, the load estimate is
 //! reset to its initial value (see [`Builder::initial`] to prevent services from being rapidly
 //! added or removed.
+//!
+//! Alternatively, a pool built with [`Builder::build_by_load`] adds and removes services based on
+//! the average [`Load`] of its ready services (see [`Builder::load_thresholds`]). When load is low,
+//! the least loaded service is removed. Such a pool is a `Pool<_, _, _, ByLoad>`, and requires the
+//! load metric of its services to be convertible into an `f64`.
+//!
+//! In either case, the pool never shrinks below a minimum number of services (see
+//! [`Builder::min_services`]), and services are only added or removed once a cooldown has elapsed
+//! since the pool was last resized (see [`Builder::scale_up_cooldown`] and
+//! [`Builder::scale_down_cooldown`]).
+//!
+//! As the pool is resized, and as its services die, it reports an [`Event`] to the receiver
+//! returned by [`Pool::events`], if any. Events are dropped while the receiver is full.
 #![deny(missing_docs)]
 
 use super::p2c::Balance;
@@ -24,14 +37,48 @@ use slab::Slab;
 use std::{
     fmt,
     future::Future,
+    marker::PhantomData,
     pin::Pin,
     task::{Context, Poll},
+    time::Duration,
 };
+use tokio::sync::mpsc;
+use tokio::time::Instant;
 use tower_service::Service;
 
 #[cfg(test)]
 mod test;
 
+/// A change in the size of a [`Pool`], reported to the receiver returned by [`Pool::events`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Event {
+    /// A new service was added to the pool, which now has `services` services.
+    ScaledUp {
+        /// The number of services in the pool.
+        services: usize,
+    },
+    /// A service is being removed from the over-provisioned pool, which will be left with
+    /// `services` services.
+    ScaledDown {
+        /// The number of services in the pool.
+        services: usize,
+    },
+    /// A service was dropped by the balancer, e.g. because it failed, and the pool now has
+    /// `services` services.
+    Died {
+        /// The number of services in the pool.
+        services: usize,
+    },
+    /// The `MakeService` failed to make a new service for the pool, which has `services`
+    /// services.
+    ///
+    /// The error itself is returned from `poll_ready`.
+    MakeFailed {
+        /// The number of services in the pool.
+        services: usize,
+    },
+}
+
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 enum Level {
     /// Load is low -- remove a service instance.
@@ -54,11 +101,15 @@ where
     making: Option<MS::Future>,
     target: Target,
     load: Level,
-    services: Slab<()>,
+    /// Whether each service has been removed from the balancer by the pool.
+    services: Slab<bool>,
+    remove: Option<usize>,
     died_tx: tokio::sync::mpsc::UnboundedSender<usize>,
     #[pin]
     died_rx: tokio::sync::mpsc::UnboundedReceiver<usize>,
     limit: Option<usize>,
+    min_services: usize,
+    events: Option<mpsc::Sender<Event>>,
 }
 
 impl<MS, Target, Request> fmt::Debug for PoolDiscoverer<MS, Target, Request>
@@ -74,6 +125,7 @@ where
             .field("load", &self.load)
             .field("services", &self.services)
             .field("limit", &self.limit)
+            .field("min_services", &self.min_services)
             .finish()
     }
 }
@@ -91,15 +143,23 @@ where
         let mut this = self.project();
 
         while let Poll::Ready(Some(sid)) = this.died_rx.as_mut().poll_recv(cx) {
-            this.services.remove(sid);
+            let removed = this.services.remove(sid);
             tracing::trace!(
                 pool.services = this.services.len(),
                 message = "removing dropped service"
             );
+            if !removed {
+                let services = this.services.len();
+                report(this.events, Event::Died { services });
+            }
         }
 
-        if this.services.len() == 0 && this.making.is_none() {
-            let _ = ready!(this.maker.poll_ready(cx))?;
+        if this.services.len() < *this.min_services && this.making.is_none() {
+            let services = this.services.len();
+            let _ = ready!(this.maker.poll_ready(cx)).map_err(|e| {
+                report(this.events, Event::MakeFailed { services });
+                e
+            })?;
             tracing::trace!("construct initial pool connection");
             this.making
                 .set(Some(this.maker.make_service(this.target.clone())));
@@ -119,7 +179,11 @@ where
                     pool.services = this.services.len(),
                     message = "decided to add service to loaded pool"
                 );
-                ready!(this.maker.poll_ready(cx))?;
+                let services = this.services.len();
+                ready!(this.maker.poll_ready(cx)).map_err(|e| {
+                    report(this.events, Event::MakeFailed { services });
+                    e
+                })?;
                 tracing::trace!("making new service");
                 // TODO: it'd be great if we could avoid the clone here and use, say, &Target
                 this.making
@@ -128,10 +192,14 @@ where
         }
 
         if let Some(fut) = this.making.as_mut().as_pin_mut() {
-            let svc = ready!(fut.poll(cx))?;
+            let services = this.services.len();
+            let svc = ready!(fut.poll(cx)).map_err(|e| {
+                report(this.events, Event::MakeFailed { services });
+                e
+            })?;
             this.making.set(None);
 
-            let id = this.services.insert(());
+            let id = this.services.insert(false);
             let svc = DropNotifyService {
                 svc,
                 id,
@@ -141,6 +209,8 @@ where
                 pool.services = this.services.len(),
                 message = "finished creating new service"
             );
+            let services = this.services.len();
+            report(this.events, Event::ScaledUp { services });
             *this.load = Level::Normal;
             return Poll::Ready(Some(Ok(Change::Insert(id, svc))));
         }
@@ -150,23 +220,52 @@ where
                 unreachable!("found high load but no Service being made");
             }
             Level::Normal => Poll::Pending,
-            Level::Low if this.services.len() == 1 => Poll::Pending,
+            Level::Low if this.services.len() <= *this.min_services => Poll::Pending,
             Level::Low => {
                 *this.load = Level::Normal;
-                // NOTE: this is a little sad -- we'd prefer to kill short-living services
-                let rm = this.services.iter().next().unwrap().0;
+                // NOTE: unless the pool chose a service to remove, this is a
+                // little sad -- we'd prefer to kill short-living services
+                let rm = match this.remove.take() {
+                    Some(rm) if this.services.contains(rm) => rm,
+                    _ => this.services.iter().next().unwrap().0,
+                };
                 // note that we _don't_ remove from self.services here
                 // that'll happen automatically on drop
                 tracing::trace!(
                     pool.services = this.services.len(),
                     message = "removing service for over-provisioned pool"
                 );
+                this.services[rm] = true;
+                let services = this.services.len() - 1;
+                report(this.events, Event::ScaledDown { services });
                 Poll::Ready(Some(Ok(Change::Remove(rm))))
             }
         }
     }
 }
 
+/// Reports `event` to the pool's events receiver, if any.
+fn report(events: &mut Option<mpsc::Sender<Event>>, event: Event) {
+    if let Some(events) = events {
+        // the receiver may be full, in which case the event is dropped, or it may have been
+        // dropped, which just means no one is listening anymore
+        let _ = events.try_send(event);
+    }
+}
+
+/// Resizes a [`Pool`] based on how often it is ready. See [`Builder::build`].
+#[derive(Debug, Clone, Copy)]
+pub struct ByReadiness {
+    _p: (),
+}
+
+/// Resizes a [`Pool`] based on the average load of its ready services. See
+/// [`Builder::build_by_load`].
+#[derive(Debug, Clone, Copy)]
+pub struct ByLoad {
+    _p: (),
+}
+
 /// A [builder] that lets you configure how a [`Pool`] determines whether the underlying service is
 /// loaded or not. See the [module-level documentation](index.html) and the builder's methods for
 /// details.
@@ -179,6 +278,11 @@ pub struct Builder {
     init: f64,
     alpha: f64,
     limit: Option<usize>,
+    min_services: usize,
+    up_cooldown: Duration,
+    down_cooldown: Duration,
+    load_low: f64,
+    load_high: f64,
 }
 
 impl Default for Builder {
@@ -189,6 +293,11 @@ impl Default for Builder {
             high: 0.2,
             alpha: 0.03,
             limit: None,
+            min_services: 1,
+            up_cooldown: Duration::from_secs(0),
+            down_cooldown: Duration::from_secs(0),
+            load_low: 0.5,
+            load_high: 2.0,
         }
     }
 }
@@ -262,12 +371,75 @@ impl Builder {
         self
     }
 
+    /// The minimum number of backing `Service` instances to maintain.
+    ///
+    /// The pool creates this many services up front, and never removes services when it would be
+    /// left with fewer.
+    ///
+    /// The default value is 1.
+    pub fn min_services(&mut self, min: usize) -> &mut Self {
+        self.min_services = min.max(1);
+        self
+    }
+
+    /// How long to wait after the pool was last resized before adding another service.
+    ///
+    /// The default value is 0.
+    pub fn scale_up_cooldown(&mut self, cooldown: Duration) -> &mut Self {
+        self.up_cooldown = cooldown;
+        self
+    }
+
+    /// How long to wait after the pool was last resized before removing a service.
+    ///
+    /// The default value is 0.
+    pub fn scale_down_cooldown(&mut self, cooldown: Duration) -> &mut Self {
+        self.down_cooldown = cooldown;
+        self
+    }
+
+    /// The average load of the ready services below which a service is removed, and above which
+    /// a service is added, for a pool built with [`Builder::build_by_load`].
+    ///
+    /// The average load is a moving average over samples taken every time the pool is polled,
+    /// updated as dictated by [`Builder::urgency`]. It starts out, and is reset whenever a service
+    /// is added or removed, halfway between `low` and `high`, so that the pool is not resized again
+    /// before the load of its new size has been measured.
+    ///
+    /// The default values are 0.5 and 2.0. That is, with [`PendingRequests`] load, a service is
+    /// added when services have more than two requests in flight on average, and removed when
+    /// fewer than every other service has a request in flight.
+    ///
+    /// [`PendingRequests`]: crate::load::PendingRequests
+    pub fn load_thresholds(&mut self, low: f64, high: f64) -> &mut Self {
+        self.load_low = low;
+        self.load_high = high;
+        self
+    }
+
     /// See [`Pool::new`].
     pub fn build<MS, Target, Request>(
         &self,
         make_service: MS,
         target: Target,
     ) -> Pool<MS, Target, Request>
+    where
+        MS: MakeService<Target, Request>,
+        MS::Service: Load,
+        <MS::Service as Load>::Metric: std::fmt::Debug,
+        MS::MakeError: Into<crate::BoxError>,
+        MS::Error: Into<crate::BoxError>,
+        Target: Clone,
+    {
+        self.build_pool(make_service, target, self.init)
+    }
+
+    fn build_pool<MS, Target, Request, Scale>(
+        &self,
+        make_service: MS,
+        target: Target,
+        ewma: f64,
+    ) -> Pool<MS, Target, Request, Scale>
     where
         MS: MakeService<Target, Request>,
         MS::Service: Load,
@@ -283,21 +455,55 @@ impl Builder {
             target,
             load: Level::Normal,
             services: Slab::new(),
+            remove: None,
             died_tx,
             died_rx,
             limit: self.limit,
+            min_services: self.min_services,
+            events: None,
         };
 
         Pool {
             balance: Balance::new(Box::pin(d)),
             options: *self,
-            ewma: self.init,
+            ewma,
+            last_scaled: None,
+            _scale: PhantomData,
         }
     }
+
+    /// Like [`Builder::build`], but the pool adds and removes services based on the average
+    /// [`Load`] of its ready services rather than on how often it is ready, and removes the least
+    /// loaded service when load is low.
+    ///
+    /// See [`Builder::load_thresholds`].
+    pub fn build_by_load<MS, Target, Request>(
+        &self,
+        make_service: MS,
+        target: Target,
+    ) -> Pool<MS, Target, Request, ByLoad>
+    where
+        MS: MakeService<Target, Request>,
+        MS::Service: Load,
+        <MS::Service as Load>::Metric: Into<f64> + std::fmt::Debug,
+        MS::MakeError: Into<crate::BoxError>,
+        MS::Error: Into<crate::BoxError>,
+        Target: Clone,
+    {
+        // start out neither loaded nor underutilized
+        self.build_pool(make_service, target, self.load_midpoint())
+    }
+
+    /// The average load that is neither high nor low.
+    fn load_midpoint(&self) -> f64 {
+        (self.load_low + self.load_high) / 2.0
+    }
 }
 
 /// A dynamically sized, load-balanced pool of `Service` instances.
-pub struct Pool<MS, Target, Request>
+///
+/// The pool is resized [`ByReadiness`] unless it is built with [`Builder::build_by_load`].
+pub struct Pool<MS, Target, Request, Scale = ByReadiness>
 where
     MS: MakeService<Target, Request>,
     MS::MakeError: Into<crate::BoxError>,
@@ -305,12 +511,21 @@ where
     Target: Clone,
 {
     // the Pin<Box<_>> here is needed since Balance requires the Service to be Unpin
-    balance: Balance<Pin<Box<PoolDiscoverer<MS, Target, Request>>>, Request>,
+    balance: PinBalance<PoolDiscoverer<MS, Target, Request>, Request>,
     options: Builder,
     ewma: f64,
+    last_scaled: Option<Instant>,
+    _scale: PhantomData<fn() -> Scale>,
 }
 
-impl<MS, Target, Request> fmt::Debug for Pool<MS, Target, Request>
+/// The load of a pool's ready services.
+#[derive(Debug)]
+struct LoadSample {
+    average: f64,
+    least_loaded: usize,
+}
+
+impl<MS, Target, Request, Scale> fmt::Debug for Pool<MS, Target, Request, Scale>
 where
     MS: MakeService<Target, Request> + fmt::Debug,
     MS::MakeError: Into<crate::BoxError>,
@@ -324,6 +539,7 @@ where
             .field("balance", &self.balance)
             .field("options", &self.options)
             .field("ewma", &self.ewma)
+            .field("last_scaled", &self.last_scaled)
             .finish()
     }
 }
@@ -348,9 +564,165 @@ where
     }
 }
 
+impl<MS, Target, Request, Scale> Pool<MS, Target, Request, Scale>
+where
+    MS: MakeService<Target, Request>,
+    MS::Service: Load,
+    <MS::Service as Load>::Metric: std::fmt::Debug,
+    MS::MakeError: Into<crate::BoxError>,
+    MS::Error: Into<crate::BoxError>,
+    Target: Clone,
+{
+    /// Returns the number of services in the pool.
+    pub fn len(&self) -> usize {
+        self.balance.discover().services.len()
+    }
+
+    /// Returns whether or not the pool is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns a receiver of the [`Event`]s reported as the pool is resized.
+    ///
+    /// The receiver buffers up to `capacity` events, and further events are dropped until it is
+    /// read from. A `capacity` of zero is treated as one. Events are only reported to the most
+    /// recently returned receiver.
+    pub fn events(&mut self, capacity: usize) -> mpsc::Receiver<Event> {
+        // tokio's bounded channels must have a capacity
+        let (tx, rx) = mpsc::channel(capacity.max(1));
+        *self.balance.discover_mut().as_mut().project().events = Some(tx);
+        rx
+    }
+}
+
 type PinBalance<S, Request> = Balance<Pin<Box<S>>, Request>;
 
-impl<MS, Target, Req> Service<Req> for Pool<MS, Target, Req>
+impl<MS, Target, Request, Scale> Pool<MS, Target, Request, Scale>
+where
+    MS: MakeService<Target, Request>,
+    MS::Service: Load,
+    <MS::Service as Load>::Metric: std::fmt::Debug,
+    MS::MakeError: Into<crate::BoxError>,
+    MS::Error: Into<crate::BoxError>,
+    Target: Clone,
+{
+    /// Returns whether `cooldown` has elapsed since the pool was last resized.
+    fn cooled_down(&self, now: Instant, cooldown: Duration) -> bool {
+        match self.last_scaled {
+            Some(last) => now.duration_since(last) >= cooldown,
+            None => true,
+        }
+    }
+}
+
+impl<MS, Target, Request> Pool<MS, Target, Request, ByLoad>
+where
+    MS: MakeService<Target, Request>,
+    MS::Service: Load,
+    <MS::Service as Load>::Metric: Into<f64> + std::fmt::Debug,
+    MS::MakeError: Into<crate::BoxError>,
+    MS::Error: Into<crate::BoxError>,
+    Target: Clone,
+{
+    /// Measures the average load of the pool's ready services, and finds the least loaded one.
+    fn measure_load(&self) -> Option<LoadSample> {
+        let mut total = 0.0;
+        let mut ready = 0;
+        let mut least_loaded = None;
+        for (key, load) in self.balance.ready_loads() {
+            let load = load.into();
+            total += load;
+            ready += 1;
+            match least_loaded {
+                Some((_, least)) if least <= load => {}
+                _ => least_loaded = Some((key, load)),
+            }
+        }
+
+        least_loaded.map(|(least_loaded, _)| LoadSample {
+            average: total / ready as f64,
+            least_loaded: *least_loaded,
+        })
+    }
+}
+
+impl<MS, Target, Req> Service<Req> for Pool<MS, Target, Req, ByLoad>
+where
+    MS: MakeService<Target, Req>,
+    MS::Service: Load,
+    <MS::Service as Load>::Metric: Into<f64> + std::fmt::Debug,
+    MS::MakeError: Into<crate::BoxError>,
+    MS::Error: Into<crate::BoxError>,
+    Target: Clone,
+{
+    type Response = <PinBalance<PoolDiscoverer<MS, Target, Req>, Req> as Service<Req>>::Response;
+    type Error = <PinBalance<PoolDiscoverer<MS, Target, Req>, Req> as Service<Req>>::Error;
+    type Future = <PinBalance<PoolDiscoverer<MS, Target, Req>, Req> as Service<Req>>::Future;
+
+    /// Polls the balancer, adding or removing a service based on the average load of the ready
+    /// services. See [`Builder::build_by_load`].
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let ready = self.balance.poll_ready(cx)?;
+        let sample = self.measure_load();
+        if let Some(ref sample) = sample {
+            self.ewma =
+                self.options.alpha * sample.average + (1.0 - self.options.alpha) * self.ewma;
+        }
+
+        let now = Instant::now();
+        let loaded = match sample {
+            Some(_) => self.ewma > self.options.load_high,
+            // no services are ready -- we're overloaded
+            None => ready.is_pending(),
+        };
+        let up = self.cooled_down(now, self.options.up_cooldown);
+        let down = self.cooled_down(now, self.options.down_cooldown);
+
+        let discover = self.balance.discover_mut().as_mut().project();
+        if loaded && up && discover.making.is_none() {
+            if *discover.load != Level::High {
+                tracing::trace!({ load = %self.ewma }, "pool is under-provisioned");
+            }
+            *discover.load = Level::High;
+            self.last_scaled = Some(now);
+            // the load average lags behind, so measure the new pool afresh
+            self.ewma = self.options.load_midpoint();
+
+            // we need to call balance again for PoolDiscover to realize
+            // it can make a new service
+            return self.balance.poll_ready(cx);
+        }
+
+        match sample {
+            Some(sample)
+                if self.ewma < self.options.load_low
+                    && down
+                    && discover.services.len() > self.options.min_services =>
+            {
+                tracing::trace!({ load = %self.ewma }, "pool is over-provisioned");
+                *discover.load = Level::Low;
+                *discover.remove = Some(sample.least_loaded);
+                self.last_scaled = Some(now);
+                self.ewma = self.options.load_midpoint();
+            }
+            _ => {
+                if *discover.load != Level::Normal && discover.making.is_none() {
+                    tracing::trace!({ load = %self.ewma }, "pool is appropriately provisioned");
+                }
+                *discover.load = Level::Normal;
+            }
+        }
+
+        ready.map(Ok)
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        self.balance.call(req)
+    }
+}
+
+impl<MS, Target, Req> Service<Req> for Pool<MS, Target, Req, ByReadiness>
 where
     MS: MakeService<Target, Req>,
     MS::Service: Load,
@@ -369,16 +741,19 @@ where
             // update ewma with a 0 sample
             self.ewma = (1.0 - self.options.alpha) * self.ewma;
 
+            let now = Instant::now();
+            let cooled_down = self.cooled_down(now, self.options.down_cooldown);
             let discover = self.balance.discover_mut().as_mut().project();
-            if self.ewma < self.options.low {
+            if self.ewma < self.options.low && cooled_down {
                 if *discover.load != Level::Low {
                     tracing::trace!({ ewma = %self.ewma }, "pool is over-provisioned");
                 }
                 *discover.load = Level::Low;
 
-                if discover.services.len() > 1 {
+                if discover.services.len() > self.options.min_services {
                     // reset EWMA so we don't immediately try to remove another service
                     self.ewma = self.options.init;
+                    self.last_scaled = Some(now);
                 }
             } else {
                 if *discover.load != Level::Normal {
@@ -390,13 +765,15 @@ where
             return Poll::Ready(Ok(()));
         }
 
+        let now = Instant::now();
+        let cooled_down = self.cooled_down(now, self.options.up_cooldown);
         let discover = self.balance.discover_mut().as_mut().project();
         if discover.making.is_none() {
             // no services are ready -- we're overloaded
             // update ewma with a 1 sample
             self.ewma = self.options.alpha + (1.0 - self.options.alpha) * self.ewma;
 
-            if self.ewma > self.options.high {
+            if self.ewma > self.options.high && cooled_down {
                 if *discover.load != Level::High {
                     tracing::trace!({ ewma = %self.ewma }, "pool is under-provisioned");
                 }
@@ -406,6 +783,7 @@ where
                 // `Ready`, so we won't try to launch another service immediately.
                 // we clamp it to high though in case the # of services is limited.
                 self.ewma = self.options.high;
+                self.last_scaled = Some(now);
 
                 // we need to call balance again for PoolDiscover to realize
                 // it can make a new service
@@ -188,3 +188,311 @@ async fn failing_service() {
     assert_request_eq!(svc2, ()).send_response("bar");
     assert_eq!(assert_ready_ok!(fut.poll()), "bar");
 }
+
+type PendingMock = load::PendingRequests<mock::Mock<(), &'static str>>;
+
+/// Returns the discovery keys of the pool's services.
+fn pool_keys<MS, Target, Request, Scale>(pool: &Pool<MS, Target, Request, Scale>) -> Vec<usize>
+where
+    MS: MakeService<Target, Request>,
+    MS::Service: Load,
+    MS::MakeError: Into<crate::BoxError>,
+    MS::Error: Into<crate::BoxError>,
+    Target: Clone,
+{
+    let mut keys: Vec<_> = pool.balance.snapshot().iter().map(|e| *e.key()).collect();
+    keys.sort();
+    keys
+}
+
+#[tokio::test]
+async fn load_based() {
+    // start the pool
+    let (mock, handle) = mock::pair::<(), PendingMock>();
+    pin_mut!(handle);
+
+    let pool = Builder::new()
+        .urgency(1.0) // so any sample changes the load average
+        .load_thresholds(0.75, 1.5)
+        .build_by_load(mock, ());
+    let mut pool = mock::Spawn::new(pool);
+    assert_pending!(pool.poll_ready());
+
+    // give the pool a backing service
+    let (svc1_m, svc1) = mock::pair();
+    pin_mut!(svc1);
+    assert_request_eq!(handle, ()).send_response(load::PendingRequests::new(
+        svc1_m,
+        load::CompleteOnResponse::default(),
+    ));
+
+    // the only service is idle, but it is not removed
+    assert_ready_ok!(pool.poll_ready());
+    let mut fut1 = task::spawn(pool.call(()));
+    assert_ready_ok!(pool.poll_ready());
+    let mut fut2 = task::spawn(pool.call(()));
+
+    // with two requests in flight, the pool is loaded and adds a service
+    assert_ready_ok!(pool.poll_ready());
+    let (svc2_m, svc2) = mock::pair();
+    pin_mut!(svc2);
+    assert_request_eq!(handle, ()).send_response(load::PendingRequests::new(
+        svc2_m,
+        load::CompleteOnResponse::default(),
+    ));
+
+    // svc1 was already selected, so it gets the next request
+    assert_ready_ok!(pool.poll_ready());
+    assert_eq!(pool_keys(pool.get_ref()), vec![0, 1]);
+    let mut fut3 = task::spawn(pool.call(()));
+
+    // but the new service is less loaded, so it gets the one after
+    assert_ready_ok!(pool.poll_ready());
+    let mut fut4 = task::spawn(pool.call(()));
+    let _rsp4 = assert_request_eq!(svc2, ());
+
+    // once svc1 is idle, the average load is low, and svc1 is removed
+    // since it is the least loaded
+    assert_request_eq!(svc1, ()).send_response("foo");
+    assert_request_eq!(svc1, ()).send_response("bar");
+    assert_request_eq!(svc1, ()).send_response("baz");
+    assert_eq!(assert_ready_ok!(fut1.poll()), "foo");
+    assert_eq!(assert_ready_ok!(fut2.poll()), "bar");
+    assert_eq!(assert_ready_ok!(fut3.poll()), "baz");
+    drop((fut1, fut2, fut3));
+    assert_ready_ok!(pool.poll_ready());
+    assert_ready_ok!(pool.poll_ready());
+    assert_eq!(pool_keys(pool.get_ref()), vec![1]);
+    assert_pending!(fut4.poll());
+}
+
+#[tokio::test]
+async fn min_services() {
+    let (mock, handle) = mock::pair::<(), load::Constant<mock::Mock<(), &'static str>, usize>>();
+    pin_mut!(handle);
+
+    let pool = Builder::new()
+        .urgency(1.0) // so any event will change the service count
+        .min_services(2)
+        .build(mock, ());
+    let mut pool = mock::Spawn::new(pool);
+
+    // the pool creates two services up front
+    assert_pending!(pool.poll_ready());
+    let (svc1_m, _svc1) = mock::pair();
+    assert_request_eq!(handle, ()).send_response(load::Constant::new(svc1_m, 0));
+    assert_ready_ok!(pool.poll_ready());
+    let (svc2_m, _svc2) = mock::pair();
+    assert_request_eq!(handle, ()).send_response(load::Constant::new(svc2_m, 0));
+    assert_ready_ok!(pool.poll_ready());
+    assert_eq!(pool_keys(pool.get_ref()), vec![0, 1]);
+
+    // and does not remove them even though it is underutilized
+    for _ in 0..3 {
+        assert_ready_ok!(pool.poll_ready());
+    }
+    assert_eq!(pool_keys(pool.get_ref()), vec![0, 1]);
+}
+
+#[tokio::test]
+async fn cooldown() {
+    tokio::time::pause();
+
+    let (mock, handle) = mock::pair::<(), load::Constant<mock::Mock<(), &'static str>, usize>>();
+    pin_mut!(handle);
+
+    let pool = Builder::new()
+        .urgency(1.0) // so _any_ Pending will add a service
+        .underutilized_below(0.0) // so no Ready will remove a service
+        .scale_up_cooldown(Duration::from_secs(10))
+        .build(mock, ());
+    let mut pool = mock::Spawn::new(pool);
+    assert_pending!(pool.poll_ready());
+
+    let (svc1_m, svc1) = mock::pair();
+    pin_mut!(svc1);
+    svc1.allow(1);
+    assert_request_eq!(handle, ()).send_response(load::Constant::new(svc1_m, 0));
+    assert_ready_ok!(pool.poll_ready());
+    let _fut1 = task::spawn(pool.call(()));
+
+    // the pool is loaded, and adds a service
+    assert_pending!(pool.poll_ready());
+    let (svc2_m, svc2) = mock::pair();
+    pin_mut!(svc2);
+    svc2.allow(1);
+    assert_request_eq!(handle, ()).send_response(load::Constant::new(svc2_m, 0));
+    assert_ready_ok!(pool.poll_ready());
+    let _fut2 = task::spawn(pool.call(()));
+
+    // the pool is loaded again, but was just resized
+    assert_pending!(pool.poll_ready());
+    assert_pending!(handle.as_mut().poll_request());
+
+    // once the cooldown has elapsed, another service is added
+    tokio::time::advance(Duration::from_secs(10)).await;
+    assert_pending!(pool.poll_ready());
+    assert_ready!(handle.as_mut().poll_request());
+}
+
+#[tokio::test]
+async fn load_based_sustained_load_adds_one_service() {
+    let (mock, handle) = mock::pair::<(), load::Constant<mock::Mock<(), &'static str>, u32>>();
+    pin_mut!(handle);
+
+    let pool = Builder::new()
+        .urgency(0.5)
+        .load_thresholds(1.0, 2.0)
+        .build_by_load(mock, ());
+    let mut pool = mock::Spawn::new(pool);
+    assert_pending!(pool.poll_ready());
+
+    // a single service is overloaded, so another one is added
+    let (svc1_m, _svc1) = mock::pair();
+    assert_request_eq!(handle, ()).send_response(load::Constant::new(svc1_m, 4));
+    assert_ready_ok!(pool.poll_ready());
+    let (svc2_m, _svc2) = mock::pair();
+    assert_request_eq!(handle, ()).send_response(load::Constant::new(svc2_m, 0));
+
+    // the load is sustained, but two services are enough to handle it
+    for _ in 0..10 {
+        assert_ready_ok!(pool.poll_ready());
+    }
+    assert_eq!(pool_keys(pool.get_ref()), vec![0, 1]);
+    assert_pending!(handle.as_mut().poll_request());
+}
+
+#[tokio::test]
+async fn events() {
+    let (mock, handle) = mock::pair::<(), load::Constant<mock::Mock<(), &'static str>, usize>>();
+    pin_mut!(handle);
+
+    let mut pool = Builder::new()
+        .urgency(1.0) // so any event will change the service count
+        .build(mock, ());
+    let mut events = pool.events(8);
+    let mut pool = mock::Spawn::new(pool);
+    assert_pending!(pool.poll_ready());
+    assert!(events.try_recv().is_err());
+
+    let (svc1_m, svc1) = mock::pair();
+    pin_mut!(svc1);
+    svc1.allow(1);
+    assert_request_eq!(handle, ()).send_response(load::Constant::new(svc1_m, 0));
+    assert_ready_ok!(pool.poll_ready());
+    assert_eq!(events.try_recv().unwrap(), Event::ScaledUp { services: 1 });
+
+    // svc1 is no longer ready after a request, so the pool is loaded
+    let mut fut = task::spawn(pool.call(()));
+    assert_request_eq!(svc1, ()).send_response("foo");
+    assert_eq!(assert_ready_ok!(fut.poll()), "foo");
+    assert_pending!(pool.poll_ready());
+    let (svc2_m, svc2) = mock::pair();
+    pin_mut!(svc2);
+    svc2.allow(1);
+    assert_request_eq!(handle, ()).send_response(load::Constant::new(svc2_m, 0));
+    assert_ready_ok!(pool.poll_ready());
+    assert_eq!(events.try_recv().unwrap(), Event::ScaledUp { services: 2 });
+    assert_eq!(pool.get_ref().len(), 2);
+
+    // the pool is then underutilized
+    assert_ready_ok!(pool.poll_ready());
+    assert_eq!(
+        events.try_recv().unwrap(),
+        Event::ScaledDown { services: 1 }
+    );
+    assert_ready_ok!(pool.poll_ready());
+    assert_eq!(pool.get_ref().len(), 1);
+    assert!(events.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn make_failed_event() {
+    let (mock, handle) = mock::pair::<(), load::Constant<mock::Mock<(), &'static str>, usize>>();
+    pin_mut!(handle);
+
+    let mut pool = Pool::new(mock, ());
+    let mut events = pool.events(8);
+    let mut pool = mock::Spawn::new(pool);
+    assert_pending!(pool.poll_ready());
+
+    assert_request_eq!(handle, ()).send_error("boom");
+    assert!(pool.poll_ready().is_ready());
+    assert_eq!(
+        events.try_recv().unwrap(),
+        Event::MakeFailed { services: 0 }
+    );
+    assert!(pool.get_ref().is_empty());
+}
+
+#[tokio::test]
+async fn died_event() {
+    let (mock, handle) = mock::pair::<(), load::Constant<mock::Mock<(), &'static str>, usize>>();
+    pin_mut!(handle);
+
+    let mut pool = Builder::new()
+        .underutilized_below(0.0) // so no Ready will remove a service
+        .build(mock, ());
+    let mut events = pool.events(8);
+    let mut pool = mock::Spawn::new(pool);
+    assert_pending!(pool.poll_ready());
+
+    let (svc1_m, svc1) = mock::pair();
+    pin_mut!(svc1);
+    svc1.allow(1);
+    assert_request_eq!(handle, ()).send_response(load::Constant::new(svc1_m, 0));
+    assert_ready_ok!(pool.poll_ready());
+    assert_eq!(events.try_recv().unwrap(), Event::ScaledUp { services: 1 });
+
+    // the balancer drops svc1 once it fails
+    svc1.send_error("ouch");
+    assert_pending!(pool.poll_ready());
+    assert_pending!(pool.poll_ready());
+    assert_eq!(events.try_recv().unwrap(), Event::Died { services: 0 });
+    assert!(pool.get_ref().is_empty());
+}
+
+#[tokio::test]
+async fn events_are_dropped_when_full() {
+    let (mock, handle) = mock::pair::<(), load::Constant<mock::Mock<(), &'static str>, usize>>();
+    pin_mut!(handle);
+
+    let mut pool = Builder::new()
+        .underutilized_below(0.0) // so no Ready will remove a service
+        .build(mock, ());
+    let mut events = pool.events(1);
+    let mut pool = mock::Spawn::new(pool);
+    assert_pending!(pool.poll_ready());
+
+    let (svc1_m, svc1) = mock::pair();
+    pin_mut!(svc1);
+    svc1.allow(1);
+    assert_request_eq!(handle, ()).send_response(load::Constant::new(svc1_m, 0));
+    assert_ready_ok!(pool.poll_ready());
+
+    // the receiver is full, so svc1 dying is not reported
+    svc1.send_error("ouch");
+    assert_pending!(pool.poll_ready());
+    assert_pending!(pool.poll_ready());
+    assert!(pool.get_ref().is_empty());
+    assert_eq!(events.try_recv().unwrap(), Event::ScaledUp { services: 1 });
+    assert!(events.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn events_with_no_capacity() {
+    let (mock, handle) = mock::pair::<(), load::Constant<mock::Mock<(), &'static str>, usize>>();
+    pin_mut!(handle);
+
+    let mut pool = Builder::new().build(mock, ());
+    // a receiver without capacity still buffers a single event
+    let mut events = pool.events(0);
+    let mut pool = mock::Spawn::new(pool);
+    assert_pending!(pool.poll_ready());
+
+    let (svc1_m, _svc1) = mock::pair();
+    assert_request_eq!(handle, ()).send_response(load::Constant::new(svc1_m, 0));
+    assert_ready_ok!(pool.poll_ready());
+    assert_eq!(events.try_recv().unwrap(), Event::ScaledUp { services: 1 });
+    assert!(events.try_recv().is_err());
+}
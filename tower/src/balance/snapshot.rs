@@ -0,0 +1,51 @@
+//! Point-in-time views of the services tracked by a balancer.
+//!
+//! A [`p2c::Balance`](super::p2c::Balance) can report each of its endpoints with
+//! [`Balance::snapshot`](super::p2c::Balance::snapshot), which is useful for exposing how traffic
+//! is being distributed, e.g. from an admin endpoint.
+
+/// Whether an endpoint can currently be selected by a balancer.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum State {
+    /// The service is ready, and may be selected to handle a request.
+    Ready,
+    /// The service is being driven to readiness, and cannot be selected.
+    Pending,
+}
+
+/// A snapshot of a single endpoint tracked by a balancer.
+#[derive(Clone, Debug)]
+pub struct Endpoint<K, M> {
+    pub(crate) key: K,
+    pub(crate) state: State,
+    pub(crate) load: Option<M>,
+    pub(crate) selections: u64,
+}
+
+impl<K, M> Endpoint<K, M> {
+    /// Returns the endpoint's discovery key.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Returns whether the endpoint was ready or pending.
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Returns the endpoint's load when the snapshot was taken.
+    ///
+    /// The load of a pending service cannot be inspected while it is being driven to readiness,
+    /// so this is `None` for pending endpoints.
+    pub fn load(&self) -> Option<&M> {
+        self.load.as_ref()
+    }
+
+    /// Returns the number of requests the balancer has dispatched to the endpoint since it was
+    /// discovered.
+    ///
+    /// Comparing the counts of successive snapshots shows how recent traffic was distributed.
+    pub fn selections(&self) -> u64 {
+        self.selections
+    }
+}
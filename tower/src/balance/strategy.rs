@@ -0,0 +1,257 @@
+//! Strategies for choosing which ready service a balancer sends a request to.
+//!
+//! A [`p2c::Balance`](super::p2c::Balance) keeps discovered services in a
+//! [`ReadyCache`], and asks its [`Strategy`] to pick one of the ready services whenever a request
+//! needs to be dispatched. Power of two choices is used by default, and the other strategies in
+//! this module can be swapped in with [`Balance::with_strategy`](super::p2c::Balance::with_strategy):
+//!
+//! - [`PowerOfTwoChoices`] samples two ready services at random, and picks the less loaded one.
+//! - [`LeastLoaded`] compares the load of every ready service, and picks the least loaded one.
+//! - [`RoundRobin`] cycles through the ready services, ignoring their load.
+//! - [`Random`] picks a ready service uniformly at random, ignoring its load.
+
+use crate::load::Load;
+use crate::ready_cache::ReadyCache;
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use tracing::trace;
+
+/// Chooses a ready service to dispatch a request to.
+pub trait Strategy<K: Eq + Hash, S, Req> {
+    /// Returns the index of one of the ready services in `services`, or `None` if there are no
+    /// ready services.
+    ///
+    /// The returned index must be less than [`ReadyCache::ready_len`].
+    fn select(&mut self, services: &ReadyCache<K, S, Req>) -> Option<usize>;
+}
+
+/// Samples two ready services at random, and picks the less loaded one.
+///
+/// See the [`p2c`](super::p2c) module for details.
+pub struct PowerOfTwoChoices {
+    rng: SmallRng,
+}
+
+/// Compares the load of every ready service, and picks the least loaded one.
+///
+/// This finds the least loaded service exactly, at the cost of comparing all ready services for
+/// every request. Since load measurements are inexact, this also tends to send bursts of requests
+/// to the same service, which [`PowerOfTwoChoices`] avoids.
+#[derive(Clone, Debug, Default)]
+pub struct LeastLoaded {
+    _p: (),
+}
+
+/// Cycles through the ready services, ignoring their load.
+///
+/// The ready service which was least recently selected is chosen, so services which are not ready
+/// when their turn comes are chosen as soon as they become ready again.
+#[derive(Clone, Debug)]
+pub struct RoundRobin<K> {
+    selected: HashMap<K, u64>,
+    next: u64,
+}
+
+/// Picks a ready service uniformly at random, ignoring its load.
+pub struct Random {
+    rng: SmallRng,
+}
+
+// ===== impl PowerOfTwoChoices =====
+
+impl PowerOfTwoChoices {
+    /// Constructs a strategy that uses operating system entropy.
+    pub fn new() -> Self {
+        PowerOfTwoChoices {
+            rng: SmallRng::from_entropy(),
+        }
+    }
+
+    /// Constructs a strategy seeded with the provided random number generator.
+    pub fn from_rng<R: Rng>(rng: R) -> Result<Self, rand::Error> {
+        let rng = SmallRng::from_rng(rng)?;
+        Ok(PowerOfTwoChoices { rng })
+    }
+}
+
+impl Default for PowerOfTwoChoices {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for PowerOfTwoChoices {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PowerOfTwoChoices").finish()
+    }
+}
+
+impl<K, S, Req> Strategy<K, S, Req> for PowerOfTwoChoices
+where
+    K: Eq + Hash,
+    S: Load,
+    S::Metric: fmt::Debug,
+{
+    fn select(&mut self, services: &ReadyCache<K, S, Req>) -> Option<usize> {
+        match services.ready_len() {
+            0 => None,
+            1 => Some(0),
+            len => {
+                // Get two distinct random indexes (in a random order) and
+                // compare the loads of the service at each index.
+                let idxs = rand::seq::index::sample(&mut self.rng, len, 2);
+
+                let aidx = idxs.index(0);
+                let bidx = idxs.index(1);
+                debug_assert_ne!(aidx, bidx, "random indices must be distinct");
+
+                let aload = ready_index_load(services, aidx);
+                let bload = ready_index_load(services, bidx);
+                let chosen = if aload <= bload { aidx } else { bidx };
+
+                trace!(
+                    a.index = aidx,
+                    a.load = ?aload,
+                    b.index = bidx,
+                    b.load = ?bload,
+                    chosen = if chosen == aidx { "a" } else { "b" },
+                    "p2c",
+                );
+                Some(chosen)
+            }
+        }
+    }
+}
+
+// ===== impl LeastLoaded =====
+
+impl LeastLoaded {
+    /// Constructs a least-loaded strategy.
+    pub fn new() -> Self {
+        LeastLoaded { _p: () }
+    }
+}
+
+impl<K, S, Req> Strategy<K, S, Req> for LeastLoaded
+where
+    K: Eq + Hash,
+    S: Load,
+    S::Metric: fmt::Debug,
+{
+    fn select(&mut self, services: &ReadyCache<K, S, Req>) -> Option<usize> {
+        let mut chosen: Option<(usize, S::Metric)> = None;
+        for idx in 0..services.ready_len() {
+            let load = ready_index_load(services, idx);
+            let is_less = match chosen {
+                Some((_, ref least)) => load < *least,
+                None => true,
+            };
+            if is_less {
+                chosen = Some((idx, load));
+            }
+        }
+
+        let (idx, load) = chosen?;
+        trace!(index = idx, load = ?load, "least loaded");
+        Some(idx)
+    }
+}
+
+// ===== impl RoundRobin =====
+
+impl<K: Eq + Hash> RoundRobin<K> {
+    /// Constructs a round-robin strategy.
+    pub fn new() -> Self {
+        RoundRobin {
+            selected: HashMap::new(),
+            next: 1,
+        }
+    }
+}
+
+impl<K: Eq + Hash> Default for RoundRobin<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, S, Req> Strategy<K, S, Req> for RoundRobin<K>
+where
+    K: Clone + Eq + Hash,
+{
+    fn select(&mut self, services: &ReadyCache<K, S, Req>) -> Option<usize> {
+        // Forget services which are no longer in the cache.
+        if self.selected.len() > 2 * services.len() {
+            self.selected.retain(|key, _| {
+                services.get_ready(key).is_some() || services.pending_contains(key)
+            });
+        }
+
+        // Services which have never been selected are chosen first.
+        let (idx, key) = (0..services.ready_len())
+            .map(|idx| {
+                let (key, _) = services.get_ready_index(idx).expect("invalid index");
+                (idx, key)
+            })
+            .min_by_key(|(_, key)| self.selected.get(*key).cloned().unwrap_or(0))?;
+
+        self.selected.insert(key.clone(), self.next);
+        self.next += 1;
+        trace!(index = idx, "round robin");
+        Some(idx)
+    }
+}
+
+// ===== impl Random =====
+
+impl Random {
+    /// Constructs a strategy that uses operating system entropy.
+    pub fn new() -> Self {
+        Random {
+            rng: SmallRng::from_entropy(),
+        }
+    }
+
+    /// Constructs a strategy seeded with the provided random number generator.
+    pub fn from_rng<R: Rng>(rng: R) -> Result<Self, rand::Error> {
+        let rng = SmallRng::from_rng(rng)?;
+        Ok(Random { rng })
+    }
+}
+
+impl Default for Random {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Random {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Random").finish()
+    }
+}
+
+impl<K: Eq + Hash, S, Req> Strategy<K, S, Req> for Random {
+    fn select(&mut self, services: &ReadyCache<K, S, Req>) -> Option<usize> {
+        match services.ready_len() {
+            0 => None,
+            len => {
+                let idx = self.rng.gen_range(0, len);
+                trace!(index = idx, "random");
+                Some(idx)
+            }
+        }
+    }
+}
+
+/// Accesses a ready endpoint by index and returns its current load.
+fn ready_index_load<K, S, Req>(services: &ReadyCache<K, S, Req>, index: usize) -> S::Metric
+where
+    K: Eq + Hash,
+    S: Load,
+{
+    let (_, svc) = services.get_ready_index(index).expect("invalid index");
+    svc.load()
+}
@@ -0,0 +1,192 @@
+use super::{Change, Discover};
+use futures_core::{ready, Stream};
+use pin_project::pin_project;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt,
+    future::Future,
+    hash::Hash,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::time::{delay_until, Delay, Instant};
+use tracing::{debug, trace};
+
+/// Delays the changes discovered by a `Discover` until their service has stopped flapping.
+///
+/// A change is held back until no other change has been discovered for the same key for a
+/// `period`. Only the latest change for a key is yielded, so a service that is removed and
+/// inserted again in quick succession is simply replaced, and a service that is inserted and
+/// removed again in quick succession is never yielded at all.
+///
+/// Metadata updates aren't delayed, unless a change for the same key is being held back, in
+/// which case the latest metadata is yielded right after that change. Metadata discovered while
+/// the removal of its service is held back is ignored, and doesn't apply to a service that is
+/// inserted for the same key later on.
+///
+/// Created by [`DiscoverExt::debounce`](super::DiscoverExt::debounce).
+#[pin_project]
+pub struct Debounce<D>
+where
+    D: Discover,
+{
+    #[pin]
+    discover: D,
+    discover_done: bool,
+    period: Duration,
+
+    pending: HashMap<D::Key, Pending<D>>,
+    // Keys whose service has been yielded, and not yet removed.
+    active: HashSet<D::Key>,
+    // Changes that are yielded without delay.
+    ready: VecDeque<Change<D::Key, D::Service, D::Meta>>,
+    delay: Option<Delay>,
+}
+
+/// The latest change for a key that has not been yielded yet.
+struct Pending<D: Discover> {
+    change: Change<D::Key, D::Service, D::Meta>,
+    meta: Option<D::Meta>,
+    deadline: Instant,
+}
+
+impl<D> Debounce<D>
+where
+    D: Discover,
+{
+    /// Delays the changes discovered by `discover` until their key has been quiet for `period`.
+    pub fn new(discover: D, period: Duration) -> Self {
+        Debounce {
+            discover,
+            discover_done: false,
+            period,
+            pending: HashMap::new(),
+            active: HashSet::new(),
+            ready: VecDeque::new(),
+            delay: None,
+        }
+    }
+}
+
+impl<D> fmt::Debug for Debounce<D>
+where
+    D: Discover + fmt::Debug,
+    D::Key: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Debounce")
+            .field("discover", &self.discover)
+            .field("period", &self.period)
+            .field("pending", &self.pending.keys().collect::<Vec<_>>())
+            .field("active", &self.active)
+            .finish()
+    }
+}
+
+impl<D> Stream for Debounce<D>
+where
+    D: Discover,
+    D::Key: Hash + Clone,
+{
+    type Item = Result<Change<D::Key, D::Service, D::Meta>, D::Error>;
+
+    /// Yields the next discovery change set.
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            // Record every change that has been discovered, replacing any
+            // earlier change for the same key.
+            while !*this.discover_done {
+                match this.discover.as_mut().poll_discover(cx) {
+                    Poll::Ready(Some(Ok(Change::Update(key, meta)))) => {
+                        match this.pending.get_mut(&key) {
+                            Some(pending) => match pending.change {
+                                Change::Insert(..) => pending.meta = Some(meta),
+                                // The key has no service to apply the metadata to.
+                                _ => debug!("ignoring metadata of a removed service"),
+                            },
+                            None => this.ready.push_back(Change::Update(key, meta)),
+                        }
+                    }
+                    Poll::Ready(Some(Ok(change))) => {
+                        let key = match change {
+                            Change::Insert(ref key, _) | Change::Remove(ref key) => key.clone(),
+                            Change::Update(..) => unreachable!("updates are not delayed"),
+                        };
+                        // Metadata only applies until its service is removed.
+                        let meta = match change {
+                            Change::Insert(..) => {
+                                this.pending.remove(&key).and_then(|pending| pending.meta)
+                            }
+                            _ => None,
+                        };
+                        let deadline = Instant::now() + *this.period;
+                        this.pending.insert(
+                            key,
+                            Pending {
+                                change,
+                                meta,
+                                deadline,
+                            },
+                        );
+                    }
+                    Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                    Poll::Ready(None) => *this.discover_done = true,
+                    Poll::Pending => break,
+                }
+            }
+            if let Some(change) = this.ready.pop_front() {
+                return Poll::Ready(Some(Ok(change)));
+            }
+
+            // Yield the change whose key has been quiet for the longest, once
+            // it has been quiet for long enough.
+            let next = this
+                .pending
+                .iter()
+                .min_by_key(|(_, p)| p.deadline)
+                .map(|(k, p)| (k.clone(), p.deadline));
+            let (key, deadline) = match next {
+                Some(next) => next,
+                None if *this.discover_done => return Poll::Ready(None),
+                None => return Poll::Pending,
+            };
+
+            if deadline > Instant::now() {
+                let delay = match this.delay {
+                    Some(delay) => {
+                        delay.reset(deadline);
+                        delay
+                    }
+                    None => this.delay.get_or_insert(delay_until(deadline)),
+                };
+                ready!(Pin::new(delay).poll(cx));
+                continue;
+            }
+
+            let pending = this
+                .pending
+                .remove(&key)
+                .expect("pending change must exist");
+            match pending.change {
+                Change::Insert(key, svc) => {
+                    trace!("insert");
+                    this.active.insert(key.clone());
+                    if let Some(meta) = pending.meta {
+                        this.ready.push_back(Change::Update(key.clone(), meta));
+                    }
+                    return Poll::Ready(Some(Ok(Change::Insert(key, svc))));
+                }
+                Change::Remove(key) => {
+                    // A service that was never yielded isn't removed.
+                    if this.active.remove(&key) {
+                        trace!("remove");
+                        return Poll::Ready(Some(Ok(Change::Remove(key))));
+                    }
+                }
+                Change::Update(..) => unreachable!("updates are not delayed"),
+            }
+        }
+    }
+}
@@ -0,0 +1,336 @@
+//! Graceful removal of discovered services.
+//!
+//! When a [`Discover`] removes a service, balancers drop it right away, even if requests it was
+//! sent are still in flight. For connection-oriented services, dropping the service may close the
+//! connection and abort those requests.
+//!
+//! [`Drain`] wraps every discovered service in a [`Drained`] service that tracks its in-flight
+//! requests. When a `Drained` service that still has requests in flight is dropped, the inner
+//! service is handed back to the `Drain`, which keeps it alive until all of its responses have
+//! completed or a drain timeout has elapsed. Since it has been dropped by its balancer, it
+//! receives no new requests in the meantime.
+//!
+//! Draining services are only released while the `Drain` is polled, which a balancer does
+//! whenever it is polled for readiness. Dropping the `Drain` drops all draining services.
+
+use super::{Change, Discover};
+use futures_core::{ready, Stream};
+use futures_util::task::AtomicWaker;
+use pin_project::pin_project;
+use std::{
+    fmt,
+    future::Future,
+    mem,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex, Weak,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::time::{delay_until, Delay, Instant};
+use tower_service::Service;
+use tracing::{debug, trace};
+
+/// Wraps a `D`-typed stream of discovered services, keeping removed services alive until their
+/// in-flight requests complete.
+///
+/// See the [module-level documentation](index.html) for details.
+#[pin_project]
+pub struct Drain<D>
+where
+    D: Discover,
+{
+    #[pin]
+    discover: D,
+    timeout: Duration,
+    shared: Arc<Shared<D::Service>>,
+    delay: Option<Delay>,
+}
+
+/// A discovered service whose in-flight requests are tracked by a [`Drain`].
+pub struct Drained<S> {
+    service: Option<S>,
+    in_flight: Arc<InFlight>,
+    timeout: Duration,
+    shared: Weak<Shared<S>>,
+}
+
+/// Response future from [`Drained`] services.
+#[pin_project]
+#[derive(Debug)]
+pub struct ResponseFuture<F> {
+    #[pin]
+    inner: F,
+    _guard: Guard,
+}
+
+/// State shared between a [`Drain`] and the services it discovered.
+struct Shared<S> {
+    draining: Mutex<Vec<Draining<S>>>,
+    waker: Arc<AtomicWaker>,
+}
+
+/// A removed service that is waiting for its in-flight requests to complete.
+struct Draining<S> {
+    /// Only held to keep the service alive.
+    _service: S,
+    in_flight: Arc<InFlight>,
+    deadline: Instant,
+}
+
+/// The in-flight requests of a discovered service.
+#[derive(Debug, Default)]
+struct InFlight {
+    requests: AtomicUsize,
+    /// Set once the service has been handed to the `Drain`.
+    draining: AtomicBool,
+}
+
+/// Tracks an in-flight request, waking the [`Drain`] once the last request of a draining service
+/// completes.
+#[derive(Debug)]
+struct Guard {
+    in_flight: Arc<InFlight>,
+    waker: Arc<AtomicWaker>,
+}
+
+// ===== impl Drain =====
+
+impl<D> Drain<D>
+where
+    D: Discover,
+{
+    /// Wraps a `Discover`, keeping each removed service alive for up to `timeout` while it has
+    /// requests in flight.
+    pub fn new(discover: D, timeout: Duration) -> Self {
+        Drain {
+            discover,
+            timeout,
+            shared: Arc::new(Shared {
+                draining: Mutex::new(Vec::new()),
+                waker: Arc::new(AtomicWaker::new()),
+            }),
+            delay: None,
+        }
+    }
+
+    /// Returns the number of removed services that are still draining.
+    pub fn draining(&self) -> usize {
+        match self.shared.draining.lock() {
+            Ok(draining) => draining.len(),
+            Err(poisoned) => poisoned.into_inner().len(),
+        }
+    }
+}
+
+impl<D> fmt::Debug for Drain<D>
+where
+    D: Discover + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Drain")
+            .field("discover", &self.discover)
+            .field("timeout", &self.timeout)
+            .field("draining", &self.draining())
+            .finish()
+    }
+}
+
+impl<D> Stream for Drain<D>
+where
+    D: Discover,
+{
+    type Item = Result<Change<D::Key, Drained<D::Service>, D::Meta>, D::Error>;
+
+    /// Yields the next discovery change set.
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        use self::Change::*;
+
+        let this = self.project();
+
+        // Release the services which have finished draining, and arrange to
+        // be woken when the next one times out.
+        this.shared.waker.register(cx.waker());
+        while let Some(deadline) = this.shared.release() {
+            let delay = match this.delay {
+                Some(delay) => {
+                    delay.reset(deadline);
+                    delay
+                }
+                None => this.delay.get_or_insert(delay_until(deadline)),
+            };
+            if Pin::new(delay).poll(cx).is_pending() {
+                break;
+            }
+        }
+
+        let change = match ready!(this.discover.poll_discover(cx)).transpose()? {
+            None => return Poll::Ready(None),
+            Some(Insert(k, svc)) => {
+                let svc = Drained {
+                    service: Some(svc),
+                    in_flight: Arc::new(InFlight::default()),
+                    timeout: *this.timeout,
+                    shared: Arc::downgrade(this.shared),
+                };
+                Insert(k, svc)
+            }
+            Some(Remove(k)) => Remove(k),
+            Some(Update(k, meta)) => Update(k, meta),
+        };
+
+        Poll::Ready(Some(Ok(change)))
+    }
+}
+
+// ===== impl Shared =====
+
+impl<S> Shared<S> {
+    /// Drops the services which have no requests in flight or have timed out, returning the
+    /// earliest deadline of the services which are still draining.
+    fn release(&self) -> Option<Instant> {
+        let now = Instant::now();
+        let (released, next) = {
+            let mut draining = match self.draining.lock() {
+                Ok(draining) => draining,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+            let (released, retained) = mem::take(&mut *draining)
+                .into_iter()
+                .partition::<Vec<_>, _>(|d| {
+                    d.in_flight.requests.load(Ordering::SeqCst) == 0 || d.deadline <= now
+                });
+            *draining = retained;
+            (released, draining.iter().map(|d| d.deadline).min())
+        };
+
+        // The released services are dropped without holding the lock, in case
+        // dropping them panics.
+        if !released.is_empty() {
+            debug!(released = released.len(), "drained services");
+        }
+        drop(released);
+        next
+    }
+}
+
+// ===== impl Drained =====
+
+impl<S> Drained<S> {
+    /// Returns the number of requests the service is currently handling.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.requests.load(Ordering::SeqCst)
+    }
+
+    /// Get a reference to the inner service
+    pub fn get_ref(&self) -> &S {
+        self.service.as_ref().expect("service dropped")
+    }
+
+    /// Get a mutable reference to the inner service
+    pub fn get_mut(&mut self) -> &mut S {
+        self.service.as_mut().expect("service dropped")
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for Drained<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Drained")
+            .field("service", &self.service)
+            .field("in_flight", &self.in_flight())
+            .finish()
+    }
+}
+
+impl<S, Request> Service<Request> for Drained<S>
+where
+    S: Service<Request>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let waker = match self.shared.upgrade() {
+            Some(shared) => shared.waker.clone(),
+            None => Arc::new(AtomicWaker::new()),
+        };
+        self.in_flight.requests.fetch_add(1, Ordering::SeqCst);
+        let guard = Guard {
+            in_flight: self.in_flight.clone(),
+            waker,
+        };
+        ResponseFuture {
+            inner: self.get_mut().call(req),
+            _guard: guard,
+        }
+    }
+}
+
+#[cfg(feature = "load")]
+impl<S: crate::load::Load> crate::load::Load for Drained<S> {
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.get_ref().load()
+    }
+}
+
+impl<S> Drop for Drained<S> {
+    fn drop(&mut self) {
+        let service = match self.service.take() {
+            Some(service) => service,
+            None => return,
+        };
+        if self.in_flight() == 0 {
+            return;
+        }
+        let shared = match self.shared.upgrade() {
+            Some(shared) => shared,
+            None => return,
+        };
+
+        trace!(in_flight = self.in_flight(), "draining service");
+        self.in_flight.draining.store(true, Ordering::SeqCst);
+        let draining = Draining {
+            _service: service,
+            in_flight: self.in_flight.clone(),
+            deadline: Instant::now() + self.timeout,
+        };
+        match shared.draining.lock() {
+            Ok(mut list) => list.push(draining),
+            Err(poisoned) => poisoned.into_inner().push(draining),
+        }
+        shared.waker.wake();
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F: Future> Future for ResponseFuture<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.project().inner.poll(cx)
+    }
+}
+
+// ===== impl Guard =====
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        // Requests that complete before the service is dropped don't concern the `Drain`. If the
+        // service is handed to the `Drain` concurrently, either this sees the flag, or the `Drain`
+        // sees that the request has completed once it is woken by `Drained::drop`.
+        let last = self.in_flight.requests.fetch_sub(1, Ordering::SeqCst) == 1;
+        if last && self.in_flight.draining.load(Ordering::SeqCst) {
+            self.waker.wake();
+        }
+    }
+}
@@ -0,0 +1,269 @@
+//! Service discovery driven by application code at runtime.
+//!
+//! [`Dynamic`] is a [`Discover`](super::Discover) whose services are inserted, removed and
+//! replaced through a [`Handle`], e.g. from a task that watches a configuration source:
+//!
+//! ```rust
+//! # use tower::discover::dynamic::{Batch, Dynamic};
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let (discover, handle) = Dynamic::<&str, u32>::new();
+//! handle.insert("a", 1)?;
+//! handle.insert("b", 2)?;
+//!
+//! // Later, swap one service for another in a single update.
+//! let mut batch = Batch::new();
+//! batch.remove("a").insert("c", 3);
+//! handle.send(batch)?;
+//! # drop(discover);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Metadata can be attached to the services of a `Dynamic` created with
+//! [`Dynamic::with_metadata`], and updated without replacing them with [`Handle::update`].
+//!
+//! The `Dynamic` ends once every `Handle` has been dropped.
+
+use super::{error::Never, Change};
+use futures_core::{ready, Stream};
+use pin_project::pin_project;
+use std::{
+    collections::VecDeque,
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::sync::mpsc;
+use tracing::trace;
+
+/// Yields the changes sent by its [`Handle`]s.
+///
+/// See the [module-level documentation](index.html) for details.
+#[pin_project]
+pub struct Dynamic<K, S, M = ()> {
+    rx: mpsc::UnboundedReceiver<Vec<Change<K, S, M>>>,
+    changes: VecDeque<Change<K, S, M>>,
+}
+
+/// Updates the services of a [`Dynamic`].
+///
+/// Handles can be cloned to update the same `Dynamic` from several places.
+pub struct Handle<K, S, M = ()> {
+    tx: mpsc::UnboundedSender<Vec<Change<K, S, M>>>,
+}
+
+/// A set of changes that are applied together.
+///
+/// The changes of a batch are yielded back to back, so a balancer that polls its `Discover` until
+/// it is pending, such as [`p2c::Balance`](crate::balance::p2c::Balance), applies them all before
+/// it next selects a service.
+pub struct Batch<K, S, M = ()> {
+    changes: Vec<Change<K, S, M>>,
+}
+
+/// An error returned when updating a [`Dynamic`] that has been dropped.
+pub struct Closed {
+    _p: (),
+}
+
+// ===== impl Dynamic =====
+
+impl<K, S> Dynamic<K, S> {
+    /// Creates a `Dynamic` with no services, and a [`Handle`] to update it.
+    pub fn new() -> (Self, Handle<K, S>) {
+        Self::with_metadata()
+    }
+}
+
+impl<K, S, M> Dynamic<K, S, M> {
+    /// Creates a `Dynamic` with no services, whose services have metadata of type `M`, and a
+    /// [`Handle`] to update it.
+    pub fn with_metadata() -> (Self, Handle<K, S, M>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let discover = Dynamic {
+            rx,
+            changes: VecDeque::new(),
+        };
+        (discover, Handle { tx })
+    }
+}
+
+impl<K, S, M> fmt::Debug for Dynamic<K, S, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Dynamic")
+            .field("pending_changes", &self.changes.len())
+            .finish()
+    }
+}
+
+impl<K, S, M> Stream for Dynamic<K, S, M> {
+    type Item = Result<Change<K, S, M>, Never>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        loop {
+            if let Some(change) = this.changes.pop_front() {
+                return Poll::Ready(Some(Ok(change)));
+            }
+
+            match ready!(this.rx.poll_recv(cx)) {
+                Some(batch) => {
+                    trace!(changes = batch.len(), "received batch");
+                    this.changes.extend(batch);
+                }
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+// ===== impl Handle =====
+
+impl<K, S, M> Handle<K, S, M> {
+    /// Inserts a service identified by `key`.
+    ///
+    /// If a service with the same key already exists, balancers replace it with `service`. Use
+    /// [`Handle::replace`] to remove the existing service explicitly.
+    pub fn insert(&self, key: K, service: S) -> Result<(), Closed> {
+        self.send(Batch {
+            changes: vec![Change::Insert(key, service)],
+        })
+    }
+
+    /// Removes the service identified by `key`.
+    pub fn remove(&self, key: K) -> Result<(), Closed> {
+        self.send(Batch {
+            changes: vec![Change::Remove(key)],
+        })
+    }
+
+    /// Replaces the service identified by `key` with `service`, by removing it and inserting
+    /// `service` in a single [`Batch`].
+    ///
+    /// Since the existing service is removed, its metadata is cleared.
+    pub fn replace(&self, key: K, service: S) -> Result<(), Closed>
+    where
+        K: Clone,
+    {
+        let mut batch = Batch::new();
+        batch.replace(key, service);
+        self.send(batch)
+    }
+
+    /// Updates the metadata of the service identified by `key`, without replacing the service.
+    ///
+    /// The metadata is kept if the service is replaced with [`Handle::insert`], until it is
+    /// removed. [`Handle::replace`] removes the service, so it clears the metadata.
+    pub fn update(&self, key: K, meta: M) -> Result<(), Closed> {
+        self.send(Batch {
+            changes: vec![Change::Update(key, meta)],
+        })
+    }
+
+    /// Applies all the changes of `batch` together.
+    pub fn send(&self, batch: Batch<K, S, M>) -> Result<(), Closed> {
+        if batch.changes.is_empty() {
+            return Ok(());
+        }
+        self.tx.send(batch.changes).map_err(|_| Closed::new())
+    }
+}
+
+impl<K, S, M> Clone for Handle<K, S, M> {
+    fn clone(&self) -> Self {
+        Handle {
+            tx: self.tx.clone(),
+        }
+    }
+}
+
+impl<K, S, M> fmt::Debug for Handle<K, S, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Handle").finish()
+    }
+}
+
+// ===== impl Batch =====
+
+impl<K, S, M> Batch<K, S, M> {
+    /// Creates an empty batch.
+    pub fn new() -> Self {
+        Batch {
+            changes: Vec::new(),
+        }
+    }
+
+    /// Inserts a service identified by `key`. See [`Handle::insert`].
+    pub fn insert(&mut self, key: K, service: S) -> &mut Self {
+        self.changes.push(Change::Insert(key, service));
+        self
+    }
+
+    /// Removes the service identified by `key`. See [`Handle::remove`].
+    pub fn remove(&mut self, key: K) -> &mut Self {
+        self.changes.push(Change::Remove(key));
+        self
+    }
+
+    /// Replaces the service identified by `key` with `service`. See [`Handle::replace`].
+    pub fn replace(&mut self, key: K, service: S) -> &mut Self
+    where
+        K: Clone,
+    {
+        self.changes.push(Change::Remove(key.clone()));
+        self.changes.push(Change::Insert(key, service));
+        self
+    }
+
+    /// Updates the metadata of the service identified by `key`. See [`Handle::update`].
+    pub fn update(&mut self, key: K, meta: M) -> &mut Self {
+        self.changes.push(Change::Update(key, meta));
+        self
+    }
+
+    /// Returns the number of changes in the batch.
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    /// Returns whether or not the batch is empty.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl<K, S, M> Default for Batch<K, S, M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, S, M> fmt::Debug for Batch<K, S, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Batch")
+            .field("changes", &self.changes.len())
+            .finish()
+    }
+}
+
+// ===== impl Closed =====
+
+impl Closed {
+    fn new() -> Self {
+        Closed { _p: () }
+    }
+}
+
+impl fmt::Debug for Closed {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_tuple("Closed").finish()
+    }
+}
+
+impl fmt::Display for Closed {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str("dynamic discover was dropped")
+    }
+}
+
+impl std::error::Error for Closed {}
@@ -0,0 +1,58 @@
+use super::{Change, Discover};
+use futures_core::{ready, Stream};
+use pin_project::pin_project;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Only yields the services discovered by a `Discover` whose keys match a predicate.
+///
+/// Created by [`DiscoverExt::filter_key`](super::DiscoverExt::filter_key).
+#[pin_project]
+#[derive(Clone, Debug)]
+pub struct FilterKey<D, F> {
+    #[pin]
+    discover: D,
+    predicate: F,
+}
+
+impl<D, F> FilterKey<D, F> {
+    /// Only yields the services discovered by `discover` whose keys match `predicate`.
+    ///
+    /// Both insertions and removals are filtered, so `predicate` must return the same result for
+    /// a key every time.
+    pub fn new(discover: D, predicate: F) -> Self {
+        FilterKey {
+            discover,
+            predicate,
+        }
+    }
+}
+
+impl<D, F> Stream for FilterKey<D, F>
+where
+    D: Discover,
+    F: FnMut(&D::Key) -> bool,
+{
+    type Item = Result<Change<D::Key, D::Service, D::Meta>, D::Error>;
+
+    /// Yields the next discovery change set.
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            let change = match ready!(this.discover.as_mut().poll_discover(cx)).transpose()? {
+                None => return Poll::Ready(None),
+                Some(change) => change,
+            };
+            let key = match change {
+                Change::Insert(ref key, _)
+                | Change::Remove(ref key)
+                | Change::Update(ref key, _) => key,
+            };
+            if (this.predicate)(key) {
+                return Poll::Ready(Some(Ok(change)));
+            }
+        }
+    }
+}
@@ -0,0 +1,354 @@
+use super::{Change, Discover};
+use futures_core::{ready, Stream};
+use futures_util::stream::FuturesUnordered;
+use pin_project::{pin_project, project};
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    hash::Hash,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::time::{delay_for, interval, Delay, Interval};
+use tower_service::Service;
+use tracing::{debug, trace};
+
+/// Actively checks the health of discovered services, and only yields the healthy ones.
+///
+/// Every discovered service is sent a health-check request as soon as it is discovered, and then
+/// once every interval. A service is only inserted once its health checks succeed, and it is
+/// removed again once its health checks fail repeatedly. A health check succeeds if the service
+/// responds to the request with `Ok` before a timeout elapses.
+///
+/// Health checks are sent through a clone of each discovered service, while another clone is
+/// yielded. The services should therefore share their underlying transport between clones.
+///
+/// When a healthy service is replaced, the replaced service is kept until its replacement is
+/// healthy, and the replacement is then yielded as a single insert. If the replacement fails its
+/// health checks instead, the replaced service is removed.
+///
+/// The latest metadata of each service is kept, and yielded again whenever it becomes healthy.
+#[pin_project]
+pub struct HealthCheck<D, Req>
+where
+    D: Discover,
+    D::Service: Service<Req>,
+{
+    #[pin]
+    discover: D,
+    discover_done: bool,
+
+    request: Req,
+    interval: Interval,
+    timeout: Duration,
+    healthy_threshold: usize,
+    unhealthy_threshold: usize,
+
+    endpoints: HashMap<D::Key, Endpoint<D::Service, D::Meta>>,
+    checks: FuturesUnordered<Check<D::Key, D::Service, Req>>,
+    changes: VecDeque<Change<D::Key, D::Service, D::Meta>>,
+    generation: u64,
+}
+
+/// The health of a single discovered service.
+#[derive(Debug)]
+struct Endpoint<S, M> {
+    service: S,
+    meta: Option<M>,
+    generation: u64,
+    checking: bool,
+    healthy: bool,
+    /// Whether a service is currently yielded for this key. This may be a service that this one
+    /// replaced, until this one is healthy.
+    yielded: bool,
+    successes: usize,
+    failures: usize,
+}
+
+/// A single health check of a service.
+#[pin_project]
+struct Check<K, S, Req>
+where
+    S: Service<Req>,
+{
+    key: Option<K>,
+    generation: u64,
+    #[pin]
+    timeout: Delay,
+    #[pin]
+    state: State<S, Req, S::Future>,
+}
+
+#[pin_project]
+enum State<S, Req, F> {
+    NotReady(S, Option<Req>),
+    Called(#[pin] F),
+}
+
+// ===== impl HealthCheck =====
+
+impl<D, Req> HealthCheck<D, Req>
+where
+    D: Discover,
+    D::Service: Service<Req>,
+{
+    /// Checks the health of every service discovered by `discover` once every `interval`, by
+    /// sending it `request`.
+    ///
+    /// By default, health checks time out after `interval`, a service is inserted after its first
+    /// successful health check, and removed after three consecutive failures.
+    pub fn new(discover: D, request: Req, interval: Duration) -> Self {
+        HealthCheck {
+            discover,
+            discover_done: false,
+            request,
+            interval: self::interval(interval),
+            timeout: interval,
+            healthy_threshold: 1,
+            unhealthy_threshold: 3,
+            endpoints: HashMap::new(),
+            checks: FuturesUnordered::new(),
+            changes: VecDeque::new(),
+            generation: 0,
+        }
+    }
+
+    /// How long to wait for a service to respond to a health check before considering it failed.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// How many consecutive health checks must succeed before an unhealthy service is inserted.
+    ///
+    /// The default value is 1.
+    pub fn healthy_threshold(mut self, threshold: usize) -> Self {
+        self.healthy_threshold = threshold.max(1);
+        self
+    }
+
+    /// How many consecutive health checks must fail before a healthy service is removed.
+    ///
+    /// The default value is 3.
+    pub fn unhealthy_threshold(mut self, threshold: usize) -> Self {
+        self.unhealthy_threshold = threshold.max(1);
+        self
+    }
+
+    /// Returns the number of discovered services that are currently healthy.
+    ///
+    /// A replaced service that is kept until its replacement is healthy is counted.
+    pub fn healthy(&self) -> usize {
+        self.endpoints.values().filter(|e| e.yielded).count()
+    }
+}
+
+impl<D, Req> std::fmt::Debug for HealthCheck<D, Req>
+where
+    D: Discover + std::fmt::Debug,
+    D::Key: std::fmt::Debug,
+    D::Service: Service<Req> + std::fmt::Debug,
+    D::Meta: std::fmt::Debug,
+    Req: std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HealthCheck")
+            .field("discover", &self.discover)
+            .field("request", &self.request)
+            .field("timeout", &self.timeout)
+            .field("healthy_threshold", &self.healthy_threshold)
+            .field("unhealthy_threshold", &self.unhealthy_threshold)
+            .field("endpoints", &self.endpoints)
+            .finish()
+    }
+}
+
+impl<D, Req> Stream for HealthCheck<D, Req>
+where
+    D: Discover,
+    D::Key: Hash + Clone,
+    D::Service: Service<Req> + Clone,
+    D::Meta: Clone,
+    Req: Clone,
+{
+    type Item = Result<Change<D::Key, D::Service, D::Meta>, D::Error>;
+
+    /// Yields the next discovery change set.
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            if let Some(change) = this.changes.pop_front() {
+                return Poll::Ready(Some(Ok(change)));
+            }
+
+            let mut progress = false;
+
+            if !*this.discover_done {
+                match this.discover.as_mut().poll_discover(cx) {
+                    Poll::Pending => {}
+                    Poll::Ready(None) => {
+                        *this.discover_done = true;
+                        progress = true;
+                    }
+                    Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                    Poll::Ready(Some(Ok(Change::Insert(key, service)))) => {
+                        trace!("insert");
+                        *this.generation += 1;
+                        // A replacement keeps the metadata of the replaced service, which
+                        // remains in use until the replacement is healthy.
+                        let (meta, yielded) = match this.endpoints.get_mut(&key) {
+                            Some(old) => (old.meta.take(), old.yielded),
+                            None => (None, false),
+                        };
+                        let endpoint = Endpoint {
+                            service,
+                            meta,
+                            generation: *this.generation,
+                            checking: true,
+                            healthy: false,
+                            yielded,
+                            successes: 0,
+                            failures: 0,
+                        };
+                        this.checks.push(Check::new(
+                            key.clone(),
+                            &endpoint,
+                            this.request.clone(),
+                            *this.timeout,
+                        ));
+                        this.endpoints.insert(key, endpoint);
+                        progress = true;
+                    }
+                    Poll::Ready(Some(Ok(Change::Remove(key)))) => {
+                        trace!("remove");
+                        if let Some(old) = this.endpoints.remove(&key) {
+                            if old.yielded {
+                                this.changes.push_back(Change::Remove(key));
+                            }
+                        }
+                        progress = true;
+                    }
+                    Poll::Ready(Some(Ok(Change::Update(key, meta)))) => {
+                        if let Some(endpoint) = this.endpoints.get_mut(&key) {
+                            trace!("update");
+                            if endpoint.yielded {
+                                this.changes.push_back(Change::Update(key, meta.clone()));
+                            }
+                            endpoint.meta = Some(meta);
+                        }
+                        progress = true;
+                    }
+                }
+            }
+
+            if this.interval.poll_tick(cx).is_ready() {
+                for (key, endpoint) in this.endpoints.iter_mut() {
+                    if !endpoint.checking {
+                        endpoint.checking = true;
+                        this.checks.push(Check::new(
+                            key.clone(),
+                            endpoint,
+                            this.request.clone(),
+                            *this.timeout,
+                        ));
+                    }
+                }
+                progress = true;
+            }
+
+            if let Poll::Ready(Some((key, generation, healthy))) =
+                Pin::new(&mut *this.checks).poll_next(cx)
+            {
+                progress = true;
+                let endpoint = match this.endpoints.get_mut(&key) {
+                    // The service was removed or replaced while it was being checked.
+                    Some(endpoint) if endpoint.generation == generation => endpoint,
+                    _ => continue,
+                };
+                endpoint.checking = false;
+
+                if healthy {
+                    endpoint.successes += 1;
+                    endpoint.failures = 0;
+                    if !endpoint.healthy && endpoint.successes >= *this.healthy_threshold {
+                        debug!("endpoint became healthy");
+                        endpoint.healthy = true;
+                        endpoint.yielded = true;
+                        let service = endpoint.service.clone();
+                        this.changes.push_back(Change::Insert(key.clone(), service));
+                        if let Some(meta) = endpoint.meta.clone() {
+                            this.changes.push_back(Change::Update(key, meta));
+                        }
+                    }
+                } else {
+                    endpoint.failures += 1;
+                    endpoint.successes = 0;
+                    if endpoint.yielded && endpoint.failures >= *this.unhealthy_threshold {
+                        debug!("endpoint became unhealthy");
+                        endpoint.healthy = false;
+                        endpoint.yielded = false;
+                        this.changes.push_back(Change::Remove(key));
+                    }
+                }
+            }
+
+            if !progress {
+                if *this.discover_done && this.endpoints.is_empty() {
+                    return Poll::Ready(None);
+                }
+                return Poll::Pending;
+            }
+        }
+    }
+}
+
+// ===== impl Check =====
+
+impl<K, S, Req> Check<K, S, Req>
+where
+    S: Service<Req> + Clone,
+{
+    fn new<M>(key: K, endpoint: &Endpoint<S, M>, request: Req, timeout: Duration) -> Self {
+        Check {
+            key: Some(key),
+            generation: endpoint.generation,
+            timeout: delay_for(timeout),
+            state: State::NotReady(endpoint.service.clone(), Some(request)),
+        }
+    }
+}
+
+impl<K, S, Req> Future for Check<K, S, Req>
+where
+    S: Service<Req>,
+{
+    type Output = (K, u64, bool);
+
+    #[project]
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+
+        if this.timeout.poll(cx).is_ready() {
+            trace!("health check timed out");
+            let key = this.key.take().expect("polled after completion");
+            return Poll::Ready((key, *this.generation, false));
+        }
+
+        let healthy = loop {
+            #[project]
+            let future = match this.state.as_mut().project() {
+                State::NotReady(service, request) => match ready!(service.poll_ready(cx)) {
+                    Ok(()) => service.call(request.take().expect("polled after completion")),
+                    Err(_) => break false,
+                },
+                State::Called(future) => break ready!(future.poll(cx)).is_ok(),
+            };
+            this.state.set(State::Called(future));
+        };
+
+        trace!(healthy, "health check completed");
+        let key = this.key.take().expect("polled after completion");
+        Poll::Ready((key, *this.generation, healthy))
+    }
+}
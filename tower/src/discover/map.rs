@@ -0,0 +1,92 @@
+use super::{Change, Discover};
+use futures_core::{ready, Stream};
+use pin_project::pin_project;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tower_layer::Layer;
+
+/// Wraps every service discovered by a `Discover` with a [`Layer`].
+///
+/// Created by [`DiscoverExt::map_service`](super::DiscoverExt::map_service).
+#[pin_project]
+#[derive(Clone, Debug)]
+pub struct MapService<D, L> {
+    #[pin]
+    discover: D,
+    layer: L,
+}
+
+/// Maps the keys of the services discovered by a `Discover`.
+///
+/// Created by [`DiscoverExt::map_key`](super::DiscoverExt::map_key).
+#[pin_project]
+#[derive(Clone, Debug)]
+pub struct MapKey<D, F> {
+    #[pin]
+    discover: D,
+    f: F,
+}
+
+// ===== impl MapService =====
+
+impl<D, L> MapService<D, L> {
+    /// Wraps every service discovered by `discover` with `layer`.
+    pub fn new(discover: D, layer: L) -> Self {
+        MapService { discover, layer }
+    }
+}
+
+impl<D, L> Stream for MapService<D, L>
+where
+    D: Discover,
+    L: Layer<D::Service>,
+{
+    type Item = Result<Change<D::Key, L::Service, D::Meta>, D::Error>;
+
+    /// Yields the next discovery change set.
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let change = match ready!(this.discover.poll_discover(cx)).transpose()? {
+            None => return Poll::Ready(None),
+            Some(Change::Insert(k, svc)) => Change::Insert(k, this.layer.layer(svc)),
+            Some(Change::Remove(k)) => Change::Remove(k),
+            Some(Change::Update(k, meta)) => Change::Update(k, meta),
+        };
+        Poll::Ready(Some(Ok(change)))
+    }
+}
+
+// ===== impl MapKey =====
+
+impl<D, F> MapKey<D, F> {
+    /// Maps the keys of the services discovered by `discover` with `f`.
+    ///
+    /// `f` must map a key to the same new key every time, and must not map distinct active keys
+    /// to the same new key.
+    pub fn new(discover: D, f: F) -> Self {
+        MapKey { discover, f }
+    }
+}
+
+impl<D, F, K> Stream for MapKey<D, F>
+where
+    D: Discover,
+    F: FnMut(D::Key) -> K,
+    K: Eq,
+{
+    type Item = Result<Change<K, D::Service, D::Meta>, D::Error>;
+
+    /// Yields the next discovery change set.
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let change = match ready!(this.discover.poll_discover(cx)).transpose()? {
+            None => return Poll::Ready(None),
+            Some(Change::Insert(k, svc)) => Change::Insert((this.f)(k), svc),
+            Some(Change::Remove(k)) => Change::Remove((this.f)(k)),
+            Some(Change::Update(k, meta)) => Change::Update((this.f)(k), meta),
+        };
+        Poll::Ready(Some(Ok(change)))
+    }
+}
@@ -0,0 +1,83 @@
+use super::{Change, Discover};
+use futures_core::Stream;
+use pin_project::pin_project;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Yields the services discovered by two `Discover`s.
+///
+/// The keys of the two `Discover`s must not overlap. If they might, use
+/// [`DiscoverExt::map_key`](super::DiscoverExt::map_key) to place them in distinct namespaces
+/// first.
+///
+/// The `Merge` ends once both `Discover`s have ended. Errors from either are yielded.
+///
+/// Created by [`DiscoverExt::merge`](super::DiscoverExt::merge).
+#[pin_project]
+#[derive(Clone, Debug)]
+pub struct Merge<A, B> {
+    #[pin]
+    first: A,
+    first_done: bool,
+    #[pin]
+    second: B,
+    second_done: bool,
+    // Alternates which `Discover` is polled first, so neither starves the other.
+    second_first: bool,
+}
+
+impl<A, B> Merge<A, B> {
+    /// Yields the services discovered by both `first` and `second`.
+    pub fn new(first: A, second: B) -> Self {
+        Merge {
+            first,
+            first_done: false,
+            second,
+            second_done: false,
+            second_first: false,
+        }
+    }
+}
+
+impl<A, B> Stream for Merge<A, B>
+where
+    A: Discover,
+    A::Error: Into<crate::BoxError>,
+    B: Discover<Key = A::Key, Service = A::Service, Meta = A::Meta>,
+    B::Error: Into<crate::BoxError>,
+{
+    type Item = Result<Change<A::Key, A::Service, A::Meta>, crate::BoxError>;
+
+    /// Yields the next discovery change set.
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        *this.second_first = !*this.second_first;
+        for second in [*this.second_first, !*this.second_first].iter() {
+            let polled = if *second {
+                if *this.second_done {
+                    continue;
+                }
+                this.second.as_mut().poll_discover(cx).map_err(Into::into)
+            } else {
+                if *this.first_done {
+                    continue;
+                }
+                this.first.as_mut().poll_discover(cx).map_err(Into::into)
+            };
+            match polled {
+                Poll::Ready(None) if *second => *this.second_done = true,
+                Poll::Ready(None) => *this.first_done = true,
+                Poll::Ready(Some(change)) => return Poll::Ready(Some(change)),
+                Poll::Pending => {}
+            }
+        }
+
+        if *this.first_done && *this.second_done {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+}
@@ -9,6 +9,20 @@
 //! services. If that service later goes away, a `Change::Remove` is yielded with that service's
 //! identifier. From that point forward, the identifier may be re-used.
 //!
+//! A `Discover` may also yield metadata for its services, such as their weight, zone or tags, as
+//! a `Change::Update`. Updating a service's metadata doesn't replace the service. Consumers that
+//! use the metadata, such as [`p2c::Balance`](crate::balance::p2c::Balance), keep the latest
+//! metadata of each service until a `Change::Remove` is yielded for it, and ignore the metadata of
+//! services they don't know. `Discover`s that don't provide metadata use `()`.
+//!
+//! Services can be weighted by their metadata with
+//! [`WeightedDiscover`](crate::load::WeightedDiscover), and
+//! [`locality::Balance`](crate::balance::locality::Balance) groups services by the locality in
+//! their metadata.
+//!
+//! [`DiscoverExt`] provides adapters that transform the services and keys yielded by a `Discover`,
+//! filter them, merge two `Discover`s, debounce flapping services, or select a subset of them.
+//!
 //! # Examples
 //!
 //! ```rust
@@ -26,22 +40,53 @@
 //!                 // the service with identifier `key` has gone away
 //!                 # let _ = (key);
 //!             }
+//!             Change::Update(key, meta) => {
+//!                 // the metadata of the service with identifier `key` changed
+//!                 # let _ = (key, meta);
+//!             }
 //!         }
 //!     }
 //! }
 //! ```
 
+mod debounce;
+pub mod drain;
+pub mod dynamic;
 mod error;
+mod filter;
+mod health;
 mod list;
+mod map;
+mod merge;
+#[cfg(feature = "make")]
+mod resolve;
+pub mod subset;
+#[cfg(feature = "make")]
+pub mod watch;
 
+pub use self::debounce::Debounce;
+pub use self::drain::Drain;
+pub use self::dynamic::Dynamic;
+pub use self::filter::FilterKey;
+pub use self::health::HealthCheck;
 pub use self::list::ServiceList;
+pub use self::map::{MapKey, MapService};
+pub use self::merge::Merge;
+#[cfg(feature = "make")]
+pub use self::resolve::Resolve;
+pub use self::subset::{Subset, SubsetDiscover};
+#[cfg(feature = "make")]
+pub use self::watch::WatchFile;
 
 use crate::sealed::Sealed;
 use futures_core::TryStream;
 use std::{
+    hash::Hash,
     pin::Pin,
     task::{Context, Poll},
+    time::Duration,
 };
+use tower_layer::Layer;
 
 /// A dynamically changing set of related services.
 ///
@@ -59,6 +104,9 @@ pub trait Discover: Sealed<Change<(), ()>> {
     /// The type of `Service` yielded by this `Discover`.
     type Service;
 
+    /// The metadata yielded for each service, or `()` if there is none.
+    type Meta;
+
     /// Error produced during discovery
     type Error;
 
@@ -66,23 +114,27 @@ pub trait Discover: Sealed<Change<(), ()>> {
     fn poll_discover(
         self: Pin<&mut Self>,
         cx: &mut Context<'_>,
-    ) -> Poll<Option<Result<Change<Self::Key, Self::Service>, Self::Error>>>;
+    ) -> PollChange<Self::Key, Self::Service, Self::Meta, Self::Error>;
 }
 
-impl<K, S, E, D: ?Sized> Sealed<Change<(), ()>> for D
+/// The next change yielded by a [`Discover`], if any.
+pub type PollChange<K, S, M, E> = Poll<Option<Result<Change<K, S, M>, E>>>;
+
+impl<K, S, M, E, D: ?Sized> Sealed<Change<(), ()>> for D
 where
-    D: TryStream<Ok = Change<K, S>, Error = E>,
+    D: TryStream<Ok = Change<K, S, M>, Error = E>,
     K: Eq,
 {
 }
 
-impl<K, S, E, D: ?Sized> Discover for D
+impl<K, S, M, E, D: ?Sized> Discover for D
 where
-    D: TryStream<Ok = Change<K, S>, Error = E>,
+    D: TryStream<Ok = Change<K, S, M>, Error = E>,
     K: Eq,
 {
     type Key = K;
     type Service = S;
+    type Meta = M;
     type Error = E;
 
     fn poll_discover(
@@ -93,11 +145,89 @@ where
     }
 }
 
+/// An extension trait for `Discover`s that provides a variety of convenient adapters.
+pub trait DiscoverExt: Discover {
+    /// Wraps every discovered service with `layer`.
+    ///
+    /// This generalizes adapters such as
+    /// [`PendingRequestsDiscover`](crate::load::PendingRequestsDiscover) to any [`Layer`].
+    fn map_service<L>(self, layer: L) -> MapService<Self, L>
+    where
+        Self: Sized,
+        L: Layer<Self::Service>,
+    {
+        MapService::new(self, layer)
+    }
+
+    /// Maps the key of every discovered service with `f`.
+    ///
+    /// See [`MapKey::new`] for the requirements on `f`.
+    fn map_key<F, K>(self, f: F) -> MapKey<Self, F>
+    where
+        Self: Sized,
+        F: FnMut(Self::Key) -> K,
+        K: Eq,
+    {
+        MapKey::new(self, f)
+    }
+
+    /// Only yields the discovered services whose keys match `predicate`.
+    ///
+    /// See [`FilterKey::new`] for the requirements on `predicate`.
+    fn filter_key<F>(self, predicate: F) -> FilterKey<Self, F>
+    where
+        Self: Sized,
+        F: FnMut(&Self::Key) -> bool,
+    {
+        FilterKey::new(self, predicate)
+    }
+
+    /// Yields the services discovered by both `self` and `other`, whose keys must not overlap.
+    fn merge<D>(self, other: D) -> Merge<Self, D>
+    where
+        Self: Sized,
+        D: Discover<Key = Self::Key, Service = Self::Service, Meta = Self::Meta>,
+    {
+        Merge::new(self, other)
+    }
+
+    /// Delays every discovered change until no other change for the same key has been discovered
+    /// for `period`.
+    fn debounce(self, period: Duration) -> Debounce<Self>
+    where
+        Self: Sized,
+        Self::Key: Hash + Clone,
+    {
+        Debounce::new(self, period)
+    }
+
+    /// Yields a stable subset of up to `size` of the discovered services, selected based on
+    /// `client_id`.
+    ///
+    /// See [`SubsetDiscover`] for details.
+    fn subset<I>(self, client_id: I, size: usize) -> SubsetDiscover<Self>
+    where
+        Self: Sized,
+        Self::Key: Hash + Clone,
+        I: Hash,
+    {
+        SubsetDiscover::new(self, client_id, size)
+    }
+}
+
+impl<D: Discover + ?Sized> DiscoverExt for D {}
+
 /// A change in the service set.
 #[derive(Debug)]
-pub enum Change<K, V> {
+pub enum Change<K, V, M = ()> {
     /// A new service identified by key `K` was identified.
     Insert(K, V),
     /// The service identified by key `K` disappeared.
     Remove(K),
+    /// The metadata of the service identified by key `K` changed.
+    ///
+    /// The service itself is unchanged. The metadata applies to the service currently identified
+    /// by `K`, including a service that replaces it, until it is removed. An update for a key
+    /// without a service is ignored.
+    Update(K, M),
 }
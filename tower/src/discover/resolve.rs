@@ -0,0 +1,243 @@
+use super::Change;
+use crate::make::MakeService;
+use futures_core::{ready, Stream};
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use pin_project::pin_project;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt,
+    future::Future,
+    hash::Hash,
+    marker::PhantomData,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::time::{interval_at, Instant, Interval};
+use tower_service::Service;
+use tracing::{debug, trace};
+
+/// Discovers services by periodically resolving the full set of targets.
+///
+/// Every `interval`, `Resolve` calls its resolver, a `Service<()>` that returns every current
+/// target, such as the addresses returned by a DNS lookup. The result is compared with the
+/// previous one: a service is made with the `MakeService` for each new target, and a
+/// `Change::Insert` is yielded once it is ready, while a `Change::Remove` is yielded for each
+/// target that is gone. Services are identified by their target.
+///
+/// A failed resolution or a failure to make a service is yielded as a discovery error, and the
+/// set of services is otherwise left unchanged. A target whose service could not be made is
+/// retried at the next resolution.
+#[pin_project]
+pub struct Resolve<R, M, T, Req>
+where
+    R: Service<()>,
+    M: MakeService<T, Req>,
+{
+    resolver: R,
+    #[pin]
+    resolving: Option<R::Future>,
+    resolve_due: bool,
+    interval: Interval,
+
+    maker: M,
+    to_make: VecDeque<T>,
+    making: FuturesUnordered<Making<T, M::Future>>,
+
+    targets: HashMap<T, State>,
+    changes: VecDeque<Change<T, M::Service>>,
+    _req: PhantomData<fn(Req)>,
+}
+
+/// Whether a target's service has been yielded.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum State {
+    Making,
+    Ready,
+}
+
+/// Makes the service of a target.
+#[pin_project]
+struct Making<T, F> {
+    target: Option<T>,
+    #[pin]
+    future: F,
+}
+
+impl<R, M, T, Req> Resolve<R, M, T, Req>
+where
+    R: Service<()>,
+    M: MakeService<T, Req>,
+{
+    /// Resolves targets with `resolver` every `interval`, starting immediately, and makes their
+    /// services with `make_service`.
+    pub fn new(resolver: R, make_service: M, interval: Duration) -> Self {
+        Resolve {
+            resolver,
+            resolving: None,
+            resolve_due: true,
+            interval: interval_at(Instant::now() + interval, interval),
+            maker: make_service,
+            to_make: VecDeque::new(),
+            making: FuturesUnordered::new(),
+            targets: HashMap::new(),
+            changes: VecDeque::new(),
+            _req: PhantomData,
+        }
+    }
+}
+
+impl<R, M, T, Req> fmt::Debug for Resolve<R, M, T, Req>
+where
+    R: Service<()> + fmt::Debug,
+    M: MakeService<T, Req> + fmt::Debug,
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Resolve")
+            .field("resolver", &self.resolver)
+            .field("resolving", &self.resolving.is_some())
+            .field("maker", &self.maker)
+            .field("to_make", &self.to_make)
+            .field("making", &self.making.len())
+            .field("targets", &self.targets)
+            .finish()
+    }
+}
+
+impl<R, M, T, Req> Stream for Resolve<R, M, T, Req>
+where
+    R: Service<()>,
+    R::Response: IntoIterator<Item = T>,
+    R::Error: Into<crate::BoxError>,
+    M: MakeService<T, Req>,
+    M::MakeError: Into<crate::BoxError>,
+    T: Hash + Eq + Clone,
+{
+    type Item = Result<Change<T, M::Service>, crate::BoxError>;
+
+    /// Yields the next discovery change set.
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            if let Some(change) = this.changes.pop_front() {
+                return Poll::Ready(Some(Ok(change)));
+            }
+
+            // Start making the services of new targets.
+            if !this.to_make.is_empty() {
+                match this.maker.poll_ready(cx) {
+                    Poll::Ready(Ok(())) => {
+                        let target = this.to_make.pop_front().expect("target must exist");
+                        let future = this.maker.make_service(target.clone());
+                        this.making.push(Making {
+                            target: Some(target),
+                            future,
+                        });
+                        continue;
+                    }
+                    Poll::Ready(Err(e)) => return Poll::Ready(Some(Err(e.into()))),
+                    Poll::Pending => {}
+                }
+            }
+
+            if let Poll::Ready(Some((target, res))) = this.making.poll_next_unpin(cx) {
+                // The target may have been removed while its service was
+                // being made, in which case the service is dropped.
+                if this.targets.get(&target) != Some(&State::Making) {
+                    trace!("dropping service of removed target");
+                    continue;
+                }
+                match res {
+                    Ok(svc) => {
+                        trace!("insert");
+                        this.targets.insert(target.clone(), State::Ready);
+                        this.changes.push_back(Change::Insert(target, svc));
+                    }
+                    Err(e) => {
+                        debug!("failed to make service");
+                        // Forget the target, so it is retried at the next
+                        // resolution.
+                        this.targets.remove(&target);
+                        return Poll::Ready(Some(Err(e.into())));
+                    }
+                }
+                continue;
+            }
+
+            if let Some(fut) = this.resolving.as_mut().as_pin_mut() {
+                let res = ready!(fut.poll(cx));
+                this.resolving.set(None);
+                match res {
+                    Ok(resolved) => {
+                        update(resolved, this.targets, this.to_make, this.changes);
+                        continue;
+                    }
+                    Err(e) => {
+                        debug!("failed to resolve targets");
+                        return Poll::Ready(Some(Err(e.into())));
+                    }
+                }
+            }
+
+            if !*this.resolve_due {
+                ready!(this.interval.poll_tick(cx));
+                *this.resolve_due = true;
+            }
+            if let Err(e) = ready!(this.resolver.poll_ready(cx)) {
+                return Poll::Ready(Some(Err(e.into())));
+            }
+            trace!("resolving targets");
+            *this.resolve_due = false;
+            this.resolving.set(Some(this.resolver.call(())));
+        }
+    }
+}
+
+/// Compares the `resolved` targets with the known ones, queueing new targets to be made and
+/// removals for targets that are gone.
+fn update<T, S, I>(
+    resolved: I,
+    targets: &mut HashMap<T, State>,
+    to_make: &mut VecDeque<T>,
+    changes: &mut VecDeque<Change<T, S>>,
+) where
+    I: IntoIterator<Item = T>,
+    T: Hash + Eq + Clone,
+{
+    let resolved: HashSet<T> = resolved.into_iter().collect();
+
+    let removed: Vec<T> = targets
+        .keys()
+        .filter(|t| !resolved.contains(t))
+        .cloned()
+        .collect();
+    for target in removed {
+        trace!("remove");
+        if targets.remove(&target) == Some(State::Ready) {
+            changes.push_back(Change::Remove(target));
+        }
+    }
+    to_make.retain(|t| resolved.contains(t));
+
+    for target in resolved {
+        if !targets.contains_key(&target) {
+            targets.insert(target.clone(), State::Making);
+            to_make.push_back(target);
+        }
+    }
+}
+
+impl<T, F, S, E> Future for Making<T, F>
+where
+    F: Future<Output = Result<S, E>>,
+{
+    type Output = (T, Result<S, E>);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let res = ready!(this.future.poll(cx));
+        let target = this.target.take().expect("polled after ready");
+        Poll::Ready((target, res))
+    }
+}
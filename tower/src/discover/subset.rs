@@ -0,0 +1,327 @@
+//! Deterministic subsetting of services.
+//!
+//! When a large number of clients balance requests across a large number of services, connecting
+//! every client to every service is wasteful. Instead, each client can select a stable subset of
+//! the services, and pass them to a balancer such as
+//! [`p2c::Balance`](crate::balance::p2c::Balance).
+//!
+//! The subset is chosen deterministically from a client identifier using [rendezvous hashing]:
+//! every service is ranked by a hash of the client identifier and its key, and the `size` highest
+//! ranked services are selected. Clients with different identifiers are thus spread evenly across
+//! the services, and a given client always selects the same services. As services come and go,
+//! the subset changes minimally: a new service only replaces the lowest ranked selected service
+//! if it ranks higher, and a removed service is only replaced if it was selected.
+//!
+//! [`Subset`] wraps the resolver of a [`Resolve`](super::Resolve), so that services are only made
+//! for the selected targets. [`SubsetDiscover`] selects a subset of the services yielded by any
+//! [`Discover`], which must then make a service for every target.
+//!
+//! [rendezvous hashing]: https://en.wikipedia.org/wiki/Rendezvous_hashing
+
+use super::{Change, Discover};
+use crate::hash::hash;
+use futures_core::{ready, Stream};
+use pin_project::pin_project;
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    fmt,
+    future::Future,
+    hash::Hash,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tower_service::Service;
+use tracing::{debug, trace};
+
+/// Selects a stable subset of the targets returned by a resolver.
+///
+/// Wrapping the resolver of a [`Resolve`](super::Resolve) in a `Subset` means that services are
+/// only made for, and connect to, the selected targets. See the [module-level
+/// documentation](self) for how the targets are selected.
+#[derive(Clone, Debug)]
+pub struct Subset<R> {
+    resolver: R,
+    seed: u64,
+    size: usize,
+}
+
+/// Response future from [`Subset`] resolvers.
+#[pin_project]
+#[derive(Debug)]
+pub struct ResponseFuture<F> {
+    #[pin]
+    inner: F,
+    seed: u64,
+    size: usize,
+}
+
+impl<R> Subset<R> {
+    /// Selects up to `size` of the targets returned by `resolver`, based on `client_id`.
+    pub fn new<I: Hash>(resolver: R, client_id: I, size: usize) -> Self {
+        Subset {
+            resolver,
+            seed: hash(&client_id),
+            size,
+        }
+    }
+}
+
+impl<R, T> Service<()> for Subset<R>
+where
+    R: Service<()>,
+    R::Response: IntoIterator<Item = T>,
+    T: Hash + Eq,
+{
+    type Response = Vec<T>;
+    type Error = R::Error;
+    type Future = ResponseFuture<R::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.resolver.poll_ready(cx)
+    }
+
+    fn call(&mut self, _: ()) -> Self::Future {
+        ResponseFuture {
+            inner: self.resolver.call(()),
+            seed: self.seed,
+            size: self.size,
+        }
+    }
+}
+
+impl<F, I, T, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<I, E>>,
+    I: IntoIterator<Item = T>,
+    T: Hash + Eq,
+{
+    type Output = Result<Vec<T>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let resolved = ready!(this.inner.poll(cx))?;
+
+        let seed = *this.seed;
+        let mut ranked: Vec<(u64, T)> = resolved
+            .into_iter()
+            .map(|target| (rank(seed, &target), target))
+            .collect();
+        ranked.sort_unstable_by(|(a, _), (b, _)| b.cmp(a));
+        ranked.dedup();
+        trace!(
+            resolved = ranked.len(),
+            size = *this.size,
+            "selecting subset"
+        );
+        ranked.truncate(*this.size);
+        Poll::Ready(Ok(ranked.into_iter().map(|(_, target)| target).collect()))
+    }
+}
+
+/// Yields a stable subset of the services discovered by a `Discover`.
+///
+/// See the [module-level documentation](self) for how the services are selected. Every discovered
+/// service is kept, so that it can be selected later on, and a clone of it is yielded when it is
+/// selected. Prefer [`Subset`] when services are made from resolved targets, so that services are
+/// only made for the selected targets.
+///
+/// The metadata of the selected services is yielded as well. Metadata for keys without a service
+/// is ignored.
+///
+/// Created by [`DiscoverExt::subset`](super::DiscoverExt::subset).
+#[pin_project]
+pub struct SubsetDiscover<D>
+where
+    D: Discover,
+{
+    #[pin]
+    discover: D,
+    discover_done: bool,
+
+    seed: u64,
+    size: usize,
+
+    endpoints: HashMap<D::Key, Endpoint<D::Service, D::Meta>>,
+    // The keys of the selected and unselected services, by rank.
+    selected: BTreeMap<Rank, D::Key>,
+    unselected: BTreeMap<Rank, D::Key>,
+    // Orders services whose ranks collide.
+    next_seq: u64,
+    changes: VecDeque<Change<D::Key, D::Service, D::Meta>>,
+}
+
+/// The rank of a service, followed by the order in which it was discovered.
+type Rank = (u64, u64);
+
+struct Endpoint<S, M> {
+    service: S,
+    meta: Option<M>,
+    rank: Rank,
+}
+
+impl<D> SubsetDiscover<D>
+where
+    D: Discover,
+{
+    /// Selects up to `size` of the services discovered by `discover`, based on `client_id`.
+    pub fn new<I: Hash>(discover: D, client_id: I, size: usize) -> Self {
+        SubsetDiscover {
+            discover,
+            discover_done: false,
+            seed: hash(&client_id),
+            size,
+            endpoints: HashMap::new(),
+            selected: BTreeMap::new(),
+            unselected: BTreeMap::new(),
+            next_seq: 0,
+            changes: VecDeque::new(),
+        }
+    }
+
+    /// Returns the number of services in the subset.
+    pub fn len(&self) -> usize {
+        self.selected.len()
+    }
+
+    /// Returns whether or not the subset is empty.
+    pub fn is_empty(&self) -> bool {
+        self.selected.is_empty()
+    }
+}
+
+impl<D> fmt::Debug for SubsetDiscover<D>
+where
+    D: Discover + fmt::Debug,
+    D::Key: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SubsetDiscover")
+            .field("discover", &self.discover)
+            .field("size", &self.size)
+            .field("selected", &self.selected.values().collect::<Vec<_>>())
+            .field("unselected", &self.unselected.len())
+            .finish()
+    }
+}
+
+impl<D> Stream for SubsetDiscover<D>
+where
+    D: Discover,
+    D::Key: Hash + Clone,
+    D::Service: Clone,
+    D::Meta: Clone,
+{
+    type Item = Result<Change<D::Key, D::Service, D::Meta>, D::Error>;
+
+    /// Yields the next discovery change set.
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            let this = self.as_mut().project();
+            if let Some(change) = this.changes.pop_front() {
+                return Poll::Ready(Some(Ok(change)));
+            }
+            if *this.discover_done {
+                return Poll::Ready(None);
+            }
+
+            match ready!(this.discover.poll_discover(cx)).transpose()? {
+                None => *this.discover_done = true,
+                Some(Change::Insert(key, service)) => {
+                    if let Some(endpoint) = this.endpoints.get_mut(&key) {
+                        // The service was replaced, and keeps its place in
+                        // (or out of) the subset.
+                        if this.selected.contains_key(&endpoint.rank) {
+                            trace!("replace in subset");
+                            let change = Change::Insert(key, service.clone());
+                            this.changes.push_back(change);
+                        }
+                        endpoint.service = service;
+                        continue;
+                    }
+
+                    let rank = (rank(*this.seed, &key), *this.next_seq);
+                    *this.next_seq += 1;
+                    this.unselected.insert(rank, key.clone());
+                    let endpoint = Endpoint {
+                        service,
+                        meta: None,
+                        rank,
+                    };
+                    this.endpoints.insert(key, endpoint);
+                    self.as_mut().rebalance();
+                }
+                Some(Change::Remove(key)) => {
+                    let endpoint = match this.endpoints.remove(&key) {
+                        Some(endpoint) => endpoint,
+                        None => continue,
+                    };
+                    if this.selected.remove(&endpoint.rank).is_some() {
+                        trace!("remove from subset");
+                        this.changes.push_back(Change::Remove(key));
+                    } else {
+                        this.unselected.remove(&endpoint.rank);
+                    }
+                    self.as_mut().rebalance();
+                }
+                Some(Change::Update(key, meta)) => match this.endpoints.get_mut(&key) {
+                    Some(endpoint) => {
+                        if this.selected.contains_key(&endpoint.rank) {
+                            this.changes.push_back(Change::Update(key, meta.clone()));
+                        }
+                        endpoint.meta = Some(meta);
+                    }
+                    None => debug!("ignoring metadata of an unknown service"),
+                },
+            }
+        }
+    }
+}
+
+impl<D> SubsetDiscover<D>
+where
+    D: Discover,
+    D::Key: Hash + Clone,
+    D::Service: Clone,
+    D::Meta: Clone,
+{
+    /// Selects the highest ranked services, until the subset is full and no unselected service
+    /// ranks higher than a selected one.
+    fn rebalance(self: Pin<&mut Self>) {
+        let this = self.project();
+        loop {
+            let highest = match this.unselected.keys().next_back() {
+                Some(highest) => *highest,
+                None => return,
+            };
+            if this.selected.len() >= *this.size {
+                let lowest = match this.selected.keys().next() {
+                    Some(lowest) if *lowest < highest => *lowest,
+                    _ => return,
+                };
+                trace!("displace from subset");
+                let key = this.selected.remove(&lowest).expect("selected");
+                this.unselected.insert(lowest, key.clone());
+                this.changes.push_back(Change::Remove(key));
+            }
+
+            trace!("insert into subset");
+            let key = this.unselected.remove(&highest).expect("unselected");
+            let endpoint = &this.endpoints[&key];
+            this.changes
+                .push_back(Change::Insert(key.clone(), endpoint.service.clone()));
+            if let Some(meta) = &endpoint.meta {
+                this.changes
+                    .push_back(Change::Update(key.clone(), meta.clone()));
+            }
+            this.selected.insert(highest, key);
+        }
+    }
+}
+
+/// Ranks a target for the client with the given seed.
+///
+/// Ranks are hashed with a stable hash, so that clients built with different toolchains select
+/// the same subsets.
+fn rank<T: Hash>(seed: u64, target: &T) -> u64 {
+    hash(&(seed, target))
+}
@@ -0,0 +1,291 @@
+//! Service discovery from a file of endpoints.
+//!
+//! [`WatchFile`] reads a list of endpoints from a file, and reloads it whenever the file is
+//! modified. By default, the file lists one endpoint per line, and blank lines and lines starting
+//! with `#` are ignored:
+//!
+//! ```text
+//! # primary
+//! 10.0.0.1:8080
+//! 10.0.0.2:8080
+//! ```
+//!
+//! Other formats, such as JSON or TOML, can be read by providing a parser with
+//! [`WatchFile::with_parser`].
+
+use super::{Change, Resolve};
+use crate::make::MakeService;
+use futures_core::{ready, Stream};
+use pin_project::pin_project;
+use std::{
+    fmt, fs,
+    future::Future,
+    io,
+    path::{Path, PathBuf},
+    pin::Pin,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, SystemTime},
+};
+use tokio::task::{spawn_blocking, JoinHandle};
+use tower_service::Service;
+use tracing::trace;
+
+/// Discovers services from the endpoints listed in a file.
+///
+/// The file is checked every `interval`, starting immediately, and read again whenever its
+/// modification time has changed. Its endpoints are compared with the previous ones: a service is
+/// made with the `MakeService` for each new endpoint, and a `Change::Remove` is yielded for each
+/// endpoint that is gone. Services are identified by their endpoint.
+///
+/// If the file cannot be read or parsed, the error is yielded as a discovery error and the
+/// services discovered from the last valid version of the file are kept.
+///
+/// The file is read on Tokio's blocking thread pool, so `WatchFile` must be polled within a Tokio
+/// runtime.
+///
+/// See the [module-level documentation](index.html) for details.
+#[pin_project]
+pub struct WatchFile<P, M, T, Req>
+where
+    P: Fn(&str) -> Result<Vec<T>, crate::BoxError>,
+    M: MakeService<T, Req>,
+    T: Clone,
+{
+    #[pin]
+    inner: Resolve<ReadFile<P, T>, M, T, Req>,
+}
+
+/// Parses every line of a file as an endpoint.
+type ParseLines<T> = fn(&str) -> Result<Vec<T>, crate::BoxError>;
+
+/// Reads and parses the endpoints of a file, if it has been modified.
+struct ReadFile<P, T> {
+    path: Arc<PathBuf>,
+    parse: Arc<P>,
+    cache: Arc<Mutex<Cache<T>>>,
+}
+
+/// The modification time of the file when it was last read, and its last valid endpoints.
+#[derive(Debug)]
+struct Cache<T> {
+    modified: Option<SystemTime>,
+    endpoints: Vec<T>,
+}
+
+/// Reads a file on the blocking thread pool, and then parses it.
+struct ReadFuture<P, T> {
+    read: JoinHandle<io::Result<Read>>,
+    parse: Arc<P>,
+    cache: Arc<Mutex<Cache<T>>>,
+}
+
+/// The result of reading a file that may not have been modified.
+enum Read {
+    Unmodified,
+    Modified(Option<SystemTime>, String),
+}
+
+/// An error returned when a line of a watched file is not a valid endpoint.
+#[derive(Debug)]
+pub struct ParseError {
+    line: usize,
+    source: crate::BoxError,
+}
+
+impl<M, T, Req> WatchFile<ParseLines<T>, M, T, Req>
+where
+    M: MakeService<T, Req>,
+    T: FromStr + Clone,
+    T::Err: Into<crate::BoxError>,
+{
+    /// Discovers the endpoints listed one per line in the file at `path`, checking it for changes
+    /// every `interval`, and makes their services with `make_service`.
+    pub fn new<F: Into<PathBuf>>(path: F, make_service: M, interval: Duration) -> Self {
+        Self::with_parser(path, parse_lines::<T>, make_service, interval)
+    }
+}
+
+impl<P, M, T, Req> WatchFile<P, M, T, Req>
+where
+    P: Fn(&str) -> Result<Vec<T>, crate::BoxError>,
+    M: MakeService<T, Req>,
+    T: Clone,
+{
+    /// Discovers the endpoints read from the file at `path` by `parser`, checking it for changes
+    /// every `interval`, and makes their services with `make_service`.
+    ///
+    /// `parser` is called with the contents of the file, and returns all of its endpoints.
+    pub fn with_parser<F>(path: F, parser: P, make_service: M, interval: Duration) -> Self
+    where
+        F: Into<PathBuf>,
+    {
+        let read = ReadFile {
+            path: Arc::new(path.into()),
+            parse: Arc::new(parser),
+            cache: Arc::new(Mutex::new(Cache {
+                modified: None,
+                endpoints: Vec::new(),
+            })),
+        };
+        WatchFile {
+            inner: Resolve::new(read, make_service, interval),
+        }
+    }
+}
+
+impl<P, M, T, Req> fmt::Debug for WatchFile<P, M, T, Req>
+where
+    P: Fn(&str) -> Result<Vec<T>, crate::BoxError>,
+    M: MakeService<T, Req> + fmt::Debug,
+    T: Clone + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WatchFile")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl<P, M, T, Req> Stream for WatchFile<P, M, T, Req>
+where
+    P: Fn(&str) -> Result<Vec<T>, crate::BoxError>,
+    M: MakeService<T, Req>,
+    M::MakeError: Into<crate::BoxError>,
+    T: std::hash::Hash + Eq + Clone,
+{
+    type Item = Result<Change<T, M::Service>, crate::BoxError>;
+
+    /// Yields the next discovery change set.
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.project().inner.poll_next(cx)
+    }
+}
+
+// ===== impl ReadFile =====
+
+impl<P, T> Service<()> for ReadFile<P, T>
+where
+    P: Fn(&str) -> Result<Vec<T>, crate::BoxError>,
+    T: Clone,
+{
+    type Response = Vec<T>;
+    type Error = crate::BoxError;
+    type Future = ReadFuture<P, T>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _: ()) -> Self::Future {
+        let path = self.path.clone();
+        let modified = lock(&self.cache).modified;
+        ReadFuture {
+            read: spawn_blocking(move || read_modified(&path, modified)),
+            parse: self.parse.clone(),
+            cache: self.cache.clone(),
+        }
+    }
+}
+
+impl<P, T: fmt::Debug> fmt::Debug for ReadFile<P, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReadFile")
+            .field("path", &self.path)
+            .field("cache", &self.cache)
+            .finish()
+    }
+}
+
+/// Reads the file at `path`, unless its modification time is still `modified`.
+fn read_modified(path: &Path, modified: Option<SystemTime>) -> io::Result<Read> {
+    // If the modification time is unavailable, the file is always read.
+    let current = fs::metadata(path).and_then(|m| m.modified()).ok();
+    if current.is_some() && current == modified {
+        return Ok(Read::Unmodified);
+    }
+
+    trace!(?path, "reading endpoints");
+    let contents = fs::read_to_string(path)?;
+    Ok(Read::Modified(current, contents))
+}
+
+/// Locks the cache, even if a parser panicked while it was locked.
+fn lock<T>(cache: &Mutex<Cache<T>>) -> std::sync::MutexGuard<'_, Cache<T>> {
+    match cache.lock() {
+        Ok(cache) => cache,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+// ===== impl ReadFuture =====
+
+impl<P, T> Future for ReadFuture<P, T>
+where
+    P: Fn(&str) -> Result<Vec<T>, crate::BoxError>,
+    T: Clone,
+{
+    type Output = Result<Vec<T>, crate::BoxError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let read = ready!(Pin::new(&mut self.read).poll(cx))??;
+        let mut cache = lock(&self.cache);
+        let contents = match read {
+            Read::Unmodified => return Poll::Ready(Ok(cache.endpoints.clone())),
+            Read::Modified(modified, contents) => {
+                // An invalid file is only reported once, and the last valid
+                // endpoints are used until it is modified again.
+                cache.modified = modified;
+                contents
+            }
+        };
+        let endpoints = (self.parse)(&contents)?;
+        cache.endpoints = endpoints;
+        Poll::Ready(Ok(cache.endpoints.clone()))
+    }
+}
+
+/// Parses every non-empty line that isn't a comment as an endpoint.
+fn parse_lines<T>(contents: &str) -> Result<Vec<T>, crate::BoxError>
+where
+    T: FromStr,
+    T::Err: Into<crate::BoxError>,
+{
+    contents
+        .lines()
+        .enumerate()
+        .map(|(idx, line)| (idx + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(line, entry)| {
+            entry.parse().map_err(|e: T::Err| {
+                ParseError {
+                    line,
+                    source: e.into(),
+                }
+                .into()
+            })
+        })
+        .collect()
+}
+
+// ===== impl ParseError =====
+
+impl ParseError {
+    /// Returns the number of the invalid line, starting at 1.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid endpoint on line {}: {}", self.line, self.source)
+    }
+}
+
+impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&*self.source)
+    }
+}
@@ -0,0 +1,38 @@
+//! A hash that is stable across builds.
+
+use std::hash::{Hash, Hasher};
+
+/// Hashes `value` with 64-bit FNV-1a, followed by the MurmurHash3 finalizer so that similar values
+/// are spread across the whole range of hashes.
+///
+/// Unlike `DefaultHasher`, whose algorithm may change between Rust releases, this always hashes a
+/// value the same way, so that clients built with different toolchains place keys on a hash ring,
+/// and select subsets, alike.
+pub(crate) fn hash<T: Hash>(value: &T) -> u64 {
+    let mut hasher = Fnv1a(FNV_OFFSET_BASIS);
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+struct Fnv1a(u64);
+
+impl Hasher for Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        let mut h = self.0;
+        h ^= h >> 33;
+        h = h.wrapping_mul(0xff51_afd7_ed55_8ccd);
+        h ^= h >> 33;
+        h = h.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+        h ^ (h >> 33)
+    }
+}
@@ -0,0 +1,149 @@
+use crate::retry::budget::Budget;
+use futures_util::ready;
+use pin_project::pin_project;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tower_service::Service;
+use tracing::trace;
+
+/// Limits on how many hedge requests may be issued, shared by all the parts of a `Hedge`.
+#[derive(Debug)]
+pub(super) struct Limits {
+    budget: Option<Arc<Budget>>,
+    max_in_flight: Option<usize>,
+    in_flight: AtomicUsize,
+}
+
+/// InFlight is a middleware that releases a hedge slot once the hedge request
+/// it was acquired for completes (or is dropped).
+#[derive(Clone, Debug)]
+pub struct InFlight<S> {
+    limits: Arc<Limits>,
+    service: S,
+}
+
+#[pin_project]
+#[derive(Debug)]
+pub struct ResponseFuture<F> {
+    #[pin]
+    inner: F,
+    _slot: Slot,
+}
+
+#[derive(Debug)]
+struct Slot(Arc<Limits>);
+
+impl Limits {
+    pub(super) fn new(budget: Option<Arc<Budget>>, max_in_flight: Option<usize>) -> Self {
+        Limits {
+            budget,
+            max_in_flight,
+            in_flight: AtomicUsize::new(0),
+        }
+    }
+
+    /// Called for every original request.
+    pub(super) fn deposit(&self) {
+        if let Some(ref budget) = self.budget {
+            budget.deposit();
+        }
+    }
+
+    /// Tries to acquire a slot for a hedge request.
+    ///
+    /// If this returns `true`, the slot must be released by the `InFlight` middleware once the
+    /// hedge request completes.
+    pub(super) fn try_acquire(&self) -> bool {
+        if let Some(max) = self.max_in_flight {
+            let mut current = self.in_flight.load(Ordering::Acquire);
+            loop {
+                if current >= max {
+                    trace!("too many hedge requests in flight");
+                    return false;
+                }
+                match self.in_flight.compare_exchange(
+                    current,
+                    current + 1,
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                ) {
+                    Ok(_) => break,
+                    Err(actual) => current = actual,
+                }
+            }
+        } else {
+            self.in_flight.fetch_add(1, Ordering::AcqRel);
+        }
+
+        if let Some(ref budget) = self.budget {
+            if budget.withdraw().is_err() {
+                trace!("hedge budget overdrawn");
+                self.release();
+                return false;
+            }
+        }
+
+        true
+    }
+
+    fn release(&self) {
+        self.in_flight.fetch_sub(1, Ordering::AcqRel);
+    }
+
+    /// The number of hedge requests currently in flight.
+    pub(super) fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Acquire)
+    }
+}
+
+impl<S> InFlight<S> {
+    pub(super) fn new(limits: Arc<Limits>, service: S) -> Self {
+        InFlight { limits, service }
+    }
+}
+
+impl<S, Request> Service<Request> for InFlight<S>
+where
+    S: Service<Request>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        // NOTE: this service is only called once `Limits::try_acquire` has succeeded, which
+        // happens in the same poll as this call (see `PolicyPredicate`).
+        ResponseFuture {
+            inner: self.service.call(request),
+            _slot: Slot(self.limits.clone()),
+        }
+    }
+}
+
+impl<F, T, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<T, E>>,
+{
+    type Output = Result<T, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Poll::Ready(ready!(self.project().inner.poll(cx)))
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.release();
+    }
+}
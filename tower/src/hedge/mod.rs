@@ -9,37 +9,65 @@
 )]
 
 use crate::filter::Filter;
+use crate::retry::budget::Budget;
 use futures_util::future;
 use pin_project::pin_project;
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 use std::time::Duration;
 use std::{
     pin::Pin,
     task::{Context, Poll},
 };
-use tracing::error;
 
 mod delay;
 mod latency;
+mod limit;
 mod rotating_histogram;
 mod select;
 
 use delay::Delay;
 use latency::Latency;
-use rotating_histogram::RotatingHistogram;
+use limit::{InFlight, Limits};
 use select::Select;
 
-type Histo = Arc<Mutex<RotatingHistogram>>;
-type Service<S, P> = select::Select<
-    SelectPolicy<P>,
-    Latency<Histo, S>,
-    Delay<DelayPolicy, Filter<Latency<Histo, S>, PolicyPredicate<P>>>,
+pub use crate::retry::replay;
+pub use rotating_histogram::RotatingHistogram;
+
+type Service<S, P, L> = select::Select<
+    SelectPolicy<P, L>,
+    Latency<Arc<L>, S>,
+    Delay<DelayPolicy<L>, Filter<InFlight<Latency<Arc<L>, S>>, PolicyPredicate<P>>>,
 >;
 /// A middleware that pre-emptively retries requests which have been outstanding
 /// for longer than a given latency percentile.  If either of the original
 /// future or the retry future completes, that value is used.
+///
+/// A [`Builder`] can be used to issue more than one retry, each at a
+/// successive latency percentile.
+///
+/// Request latencies are recorded in a [`Latencies`] implementation, which is
+/// a [`RotatingHistogram`] by default.
 #[derive(Debug)]
-pub struct Hedge<S, P>(Service<S, P>);
+pub struct Hedge<S, P, L = RotatingHistogram> {
+    inner: Service<S, P, L>,
+    limits: Arc<Limits>,
+    latencies: Arc<L>,
+    latency_percentiles: Vec<f32>,
+}
+
+/// Configures and builds [`Hedge`] middleware.
+///
+/// Besides the settings taken by [`Hedge::new`], the builder can limit how many hedge requests
+/// are issued. This prevents hedging from multiplying the load on the inner service when it
+/// becomes slow across the board, such as during a latency incident.
+#[derive(Debug, Clone)]
+pub struct Builder {
+    min_data_points: u64,
+    latency_percentiles: Vec<f32>,
+    period: Duration,
+    budget: Option<Arc<Budget>>,
+    max_in_flight: Option<usize>,
+}
 
 /// The Future returned by the hedge Service.
 #[pin_project]
@@ -52,6 +80,25 @@ where
     inner: S::Future,
 }
 
+/// Records request latencies, and reads latency percentiles back to decide
+/// when requests are hedged.
+///
+/// Implementations must use interior mutability, since latencies are recorded
+/// concurrently by all of the requests issued through a [`Hedge`]. A single
+/// implementation may be shared between many `Hedge` instances (see
+/// [`Builder::build_with_latencies`]), or read by other components, such as a
+/// metrics exporter.
+pub trait Latencies {
+    /// Record the latency of a completed request.
+    fn record(&self, latency: Duration);
+    /// Returns the latency at the given quantile (between 0 and 1) of the
+    /// recently recorded latencies.
+    fn value_at_quantile(&self, quantile: f64) -> Duration;
+    /// Returns the number of recently recorded latencies that
+    /// `value_at_quantile` is computed from.
+    fn data_points(&self) -> u64;
+}
+
 /// A policy which describes which requests can be cloned and then whether those
 /// requests should be retried.
 pub trait Policy<Request> {
@@ -67,120 +114,292 @@ pub trait Policy<Request> {
 
 #[doc(hidden)]
 #[derive(Clone, Debug)]
-pub struct PolicyPredicate<P>(P);
+pub struct PolicyPredicate<P> {
+    policy: P,
+    limits: Arc<Limits>,
+}
 #[doc(hidden)]
 #[derive(Debug)]
-pub struct DelayPolicy {
-    histo: Histo,
+pub struct DelayPolicy<L> {
+    histo: Arc<L>,
     latency_percentile: f32,
 }
 #[doc(hidden)]
 #[derive(Debug)]
-pub struct SelectPolicy<P> {
+pub struct SelectPolicy<P, L> {
     policy: P,
-    histo: Histo,
+    histo: Arc<L>,
     min_data_points: u64,
 }
 
-impl<S, P> Hedge<S, P> {
-    /// Create a new hedge middleware.
-    pub fn new<Request>(
+impl Default for Builder {
+    fn default() -> Self {
+        Builder {
+            min_data_points: 10,
+            latency_percentiles: vec![0.9],
+            period: Duration::from_secs(60),
+            budget: None,
+            max_in_flight: None,
+        }
+    }
+}
+
+impl Builder {
+    /// Create a new builder with default values for all settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The minimum number of latencies that must have been recorded in the previous period
+    /// before hedge requests are issued.
+    ///
+    /// The default value is 10.
+    pub fn min_data_points(&mut self, min_data_points: u64) -> &mut Self {
+        self.min_data_points = min_data_points;
+        self
+    }
+
+    /// The latency percentile after which a hedge request is issued.
+    ///
+    /// The default value is 0.9, meaning that requests which are slower than 90% of the requests
+    /// in the previous period are hedged.
+    pub fn latency_percentile(&mut self, latency_percentile: f32) -> &mut Self {
+        self.latency_percentiles(&[latency_percentile])
+    }
+
+    /// Issue one hedge request at each of the given latency percentiles.
+    ///
+    /// For example, `&[0.9, 0.99]` issues a first hedge request once a request is slower than 90%
+    /// of the requests in the previous period, and a second one once it is slower than 99% of
+    /// them. Whichever of the original request and its hedges completes first is used, and the
+    /// others are canceled.
+    ///
+    /// An empty slice disables hedging.
+    pub fn latency_percentiles(&mut self, latency_percentiles: &[f32]) -> &mut Self {
+        self.latency_percentiles = latency_percentiles.to_vec();
+        self
+    }
+
+    /// The period over which latencies are recorded.
+    ///
+    /// This only applies to the default [`RotatingHistogram`], and is ignored
+    /// by [`Builder::build_with_latencies`].
+    ///
+    /// The default value is 60 seconds.
+    pub fn period(&mut self, period: Duration) -> &mut Self {
+        self.period = period;
+        self
+    }
+
+    /// Only issue a hedge request if it can be withdrawn from `budget`.
+    ///
+    /// A deposit is made into the budget for every original request, so for example
+    /// `Budget::new(ttl, min_per_sec, 0.1)` allows at most 10% of requests to be hedged (plus the
+    /// `min_per_sec` reserve). The budget may be shared between many `Hedge` instances.
+    ///
+    /// No budget is used by default.
+    pub fn budget(&mut self, budget: Option<Arc<Budget>>) -> &mut Self {
+        self.budget = budget;
+        self
+    }
+
+    /// The maximum number of hedge requests that may be outstanding at once.
+    ///
+    /// Once the limit is reached, requests wait for their original response rather than being
+    /// hedged, until an outstanding hedge request completes.
+    ///
+    /// No maximum limit is imposed by default.
+    pub fn max_in_flight(&mut self, limit: Option<usize>) -> &mut Self {
+        self.max_in_flight = limit;
+        self
+    }
+
+    /// See [`Hedge::new`].
+    pub fn build<S, P, Request>(&self, service: S, policy: P) -> Hedge<S, P>
+    where
+        S: tower_service::Service<Request> + Clone,
+        S::Error: Into<crate::BoxError>,
+        P: Policy<Request> + Clone,
+    {
+        let histo = Arc::new(RotatingHistogram::new(self.period));
+        self.build_with_latencies(service, policy, histo)
+    }
+
+    /// See [`Hedge::new_with_mock_latencies`].
+    pub fn build_with_mock_latencies<S, P, Request>(
+        &self,
         service: S,
         policy: P,
-        min_data_points: u64,
-        latency_percentile: f32,
-        period: Duration,
+        latencies_ms: &[u64],
     ) -> Hedge<S, P>
     where
         S: tower_service::Service<Request> + Clone,
         S::Error: Into<crate::BoxError>,
         P: Policy<Request> + Clone,
     {
-        let histo = Arc::new(Mutex::new(RotatingHistogram::new(period)));
-        Self::new_with_histo(service, policy, min_data_points, latency_percentile, histo)
+        let histo = Arc::new(RotatingHistogram::new(self.period));
+        histo.prepopulate(latencies_ms);
+        self.build_with_latencies(service, policy, histo)
     }
 
-    /// A hedge middleware with a prepopulated latency histogram.  This is usedful
-    /// for integration tests.
-    pub fn new_with_mock_latencies<Request>(
+    /// Build a hedge middleware which records latencies in, and reads
+    /// percentiles from, the given [`Latencies`].
+    pub fn build_with_latencies<S, P, L, Request>(
+        &self,
+        service: S,
+        policy: P,
+        histo: Arc<L>,
+    ) -> Hedge<S, P, L>
+    where
+        S: tower_service::Service<Request> + Clone,
+        S::Error: Into<crate::BoxError>,
+        P: Policy<Request> + Clone,
+        L: Latencies,
+    {
+        let limits = Arc::new(Limits::new(self.budget.clone(), self.max_in_flight));
+
+        // Wrap the underlying service in a middleware that records the
+        // latencies in a rotating histogram.
+        let recorded_a = Latency::new(histo.clone(), service.clone());
+
+        let hedges = self
+            .latency_percentiles
+            .iter()
+            .map(|&latency_percentile| {
+                // Wrap a clone of the underlying service in the same way for
+                // each hedge, releasing the hedge request's slot once it
+                // completes.
+                let recorded_b = Latency::new(histo.clone(), service.clone());
+                let tracked = InFlight::new(limits.clone(), recorded_b);
+
+                // Check policy and limits to see if the hedge request should be issued.
+                let filtered = Filter::new(
+                    tracked,
+                    PolicyPredicate {
+                        policy: policy.clone(),
+                        limits: limits.clone(),
+                    },
+                );
+
+                // Delay the hedge request by a percentile of the recorded request
+                // latency histogram.
+                let delay_policy = DelayPolicy {
+                    histo: histo.clone(),
+                    latency_percentile,
+                };
+                Delay::new(delay_policy, filtered)
+            })
+            .collect();
+
+        // If the request is retryable, issue the original request and one
+        // hedge request per percentile -- each delayed by its latency
+        // percentile.  Use the first result to complete.
+        let select_policy = SelectPolicy {
+            policy,
+            histo: histo.clone(),
+            min_data_points: self.min_data_points,
+        };
+        Hedge {
+            inner: Select::new(select_policy, recorded_a, hedges),
+            limits,
+            latencies: histo,
+            latency_percentiles: self.latency_percentiles.clone(),
+        }
+    }
+}
+
+impl<S, P> Hedge<S, P> {
+    /// Create a new hedge middleware.
+    ///
+    /// To limit how many hedge requests are issued, use a [`Builder`].
+    pub fn new<Request>(
         service: S,
         policy: P,
         min_data_points: u64,
         latency_percentile: f32,
         period: Duration,
-        latencies_ms: &[u64],
     ) -> Hedge<S, P>
     where
         S: tower_service::Service<Request> + Clone,
         S::Error: Into<crate::BoxError>,
         P: Policy<Request> + Clone,
     {
-        let histo = Arc::new(Mutex::new(RotatingHistogram::new(period)));
-        {
-            let mut locked = histo.lock().unwrap();
-            for latency in latencies_ms.iter() {
-                locked.read().record(*latency).unwrap();
-            }
-        }
-        Self::new_with_histo(service, policy, min_data_points, latency_percentile, histo)
+        Builder::new()
+            .min_data_points(min_data_points)
+            .latency_percentile(latency_percentile)
+            .period(period)
+            .build(service, policy)
     }
 
-    fn new_with_histo<Request>(
+    /// A hedge middleware with a prepopulated latency histogram.  This is usedful
+    /// for integration tests.
+    pub fn new_with_mock_latencies<Request>(
         service: S,
         policy: P,
         min_data_points: u64,
         latency_percentile: f32,
-        histo: Histo,
+        period: Duration,
+        latencies_ms: &[u64],
     ) -> Hedge<S, P>
     where
         S: tower_service::Service<Request> + Clone,
         S::Error: Into<crate::BoxError>,
         P: Policy<Request> + Clone,
     {
-        // Clone the underlying service and wrap both copies in a middleware that
-        // records the latencies in a rotating histogram.
-        let recorded_a = Latency::new(histo.clone(), service.clone());
-        let recorded_b = Latency::new(histo.clone(), service);
+        Builder::new()
+            .min_data_points(min_data_points)
+            .latency_percentile(latency_percentile)
+            .period(period)
+            .build_with_mock_latencies(service, policy, latencies_ms)
+    }
+}
 
-        // Check policy to see if the hedge request should be issued.
-        let filtered = Filter::new(recorded_b, PolicyPredicate(policy.clone()));
+impl<S, P, L> Hedge<S, P, L> {
+    /// Returns the number of hedge requests currently in flight.
+    pub fn hedges_in_flight(&self) -> usize {
+        self.limits.in_flight()
+    }
 
-        // Delay the second request by a percentile of the recorded request latency
-        // histogram.
-        let delay_policy = DelayPolicy {
-            histo: histo.clone(),
-            latency_percentile,
-        };
-        let delayed = Delay::new(delay_policy, filtered);
+    /// Returns the latencies recorded by this middleware.
+    pub fn latencies(&self) -> &Arc<L> {
+        &self.latencies
+    }
 
-        // If the request is retryable, issue two requests -- the second one delayed
-        // by a latency percentile.  Use the first result to complete.
-        let select_policy = SelectPolicy {
-            policy,
-            histo,
-            min_data_points,
-        };
-        Hedge(Select::new(select_policy, recorded_a, delayed))
+    /// Returns how long requests issued now would wait before each of their
+    /// hedge requests is issued.
+    ///
+    /// Note that no hedge requests are issued at all until enough latencies
+    /// have been recorded (see [`Builder::min_data_points`]).
+    pub fn hedge_delays(&self) -> Vec<Duration>
+    where
+        L: Latencies,
+    {
+        self.latency_percentiles
+            .iter()
+            .map(|&percentile| self.latencies.value_at_quantile(percentile.into()))
+            .collect()
     }
 }
 
-impl<S, P, Request> tower_service::Service<Request> for Hedge<S, P>
+impl<S, P, L, Request> tower_service::Service<Request> for Hedge<S, P, L>
 where
     S: tower_service::Service<Request> + Clone,
     S::Error: Into<crate::BoxError>,
     P: Policy<Request> + Clone,
+    L: Latencies,
 {
     type Response = S::Response;
     type Error = crate::BoxError;
-    type Future = Future<Service<S, P>, Request>;
+    type Future = Future<Service<S, P, L>, Request>;
 
     fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        self.0.poll_ready(cx)
+        self.inner.poll_ready(cx)
     }
 
     fn call(&mut self, request: Request) -> Self::Future {
+        self.limits.deposit();
         Future {
-            inner: self.0.call(request),
+            inner: self.inner.call(request),
         }
     }
 }
@@ -197,24 +416,9 @@ where
     }
 }
 
-// TODO: Remove when Duration::as_millis() becomes stable.
-const NANOS_PER_MILLI: u32 = 1_000_000;
-const MILLIS_PER_SEC: u64 = 1_000;
-fn millis(duration: Duration) -> u64 {
-    // Round up.
-    let millis = (duration.subsec_nanos() + NANOS_PER_MILLI - 1) / NANOS_PER_MILLI;
-    duration
-        .as_secs()
-        .saturating_mul(MILLIS_PER_SEC)
-        .saturating_add(u64::from(millis))
-}
-
-impl latency::Record for Histo {
+impl<L: Latencies> latency::Record for Arc<L> {
     fn record(&mut self, latency: Duration) {
-        let mut locked = self.lock().unwrap();
-        locked.write().record(millis(latency)).unwrap_or_else(|e| {
-            error!("Failed to write to hedge histogram: {:?}", e);
-        })
+        Latencies::record(&**self, latency)
     }
 }
 
@@ -228,7 +432,10 @@ where
     >;
 
     fn check(&mut self, request: &Request) -> Self::Future {
-        if self.0.can_retry(request) {
+        // NOTE: `InFlight` releases the slot acquired here, so `try_acquire` must only be called
+        // if the predicate passes. `Filter` calls the inner service in the same poll as the
+        // predicate resolves, so the slot cannot be leaked.
+        if self.policy.can_retry(request) && self.limits.try_acquire() {
             future::Either::Left(future::ready(Ok(())))
         } else {
             // If the hedge retry should not be issued, we simply want to wait
@@ -240,26 +447,22 @@ where
     }
 }
 
-impl<Request> delay::Policy<Request> for DelayPolicy {
+impl<L: Latencies, Request> delay::Policy<Request> for DelayPolicy<L> {
     fn delay(&self, _req: &Request) -> Duration {
-        let mut locked = self.histo.lock().unwrap();
-        let millis = locked
-            .read()
-            .value_at_quantile(self.latency_percentile.into());
-        Duration::from_millis(millis)
+        self.histo.value_at_quantile(self.latency_percentile.into())
     }
 }
 
-impl<P, Request> select::Policy<Request> for SelectPolicy<P>
+impl<P, L, Request> select::Policy<Request> for SelectPolicy<P, L>
 where
     P: Policy<Request>,
+    L: Latencies,
 {
     fn clone_request(&self, req: &Request) -> Option<Request> {
         self.policy.clone_request(req).filter(|_| {
-            let mut locked = self.histo.lock().unwrap();
             // Do not attempt a retry if there are insufficiently many data
             // points in the histogram.
-            locked.read().len() >= self.min_data_points
+            self.histo.data_points() >= self.min_data_points
         })
     }
 }
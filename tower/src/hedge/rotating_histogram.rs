@@ -1,15 +1,25 @@
+use super::Latencies;
 use hdrhistogram::Histogram;
+use std::sync::Mutex;
 use std::time::Duration;
 use tokio::time::Instant;
-use tracing::trace;
+use tracing::{error, trace};
 
-/// This represents a "rotating" histogram which stores two histogram, one which
-/// should be read and one which should be written to.  Every period, the read
-/// histogram is discarded and replaced by the write histogram.  The idea here
-/// is that the read histogram should always contain a full period (the previous
-/// period) of write operations.
+/// The default [`Latencies`] implementation, which records latencies (in
+/// milliseconds, up to 10 seconds) in a "rotating" histogram.
+///
+/// The rotating histogram stores two histograms, one which should be read and
+/// one which should be written to.  Every period, the read histogram is
+/// discarded and replaced by the write histogram.  The idea here is that the
+/// read histogram should always contain a full period (the previous period) of
+/// write operations.
 #[derive(Debug)]
 pub struct RotatingHistogram {
+    inner: Mutex<Rotating>,
+}
+
+#[derive(Debug)]
+struct Rotating {
     read: Histogram<u64>,
     write: Histogram<u64>,
     last_rotation: Instant,
@@ -17,8 +27,46 @@ pub struct RotatingHistogram {
 }
 
 impl RotatingHistogram {
+    /// Create a histogram which reads latencies recorded over the previous
+    /// `period`.
     pub fn new(period: Duration) -> RotatingHistogram {
         RotatingHistogram {
+            inner: Mutex::new(Rotating::new(period)),
+        }
+    }
+
+    /// Records latencies directly into the read histogram, so that they are
+    /// visible before the first rotation.
+    pub(super) fn prepopulate(&self, latencies_ms: &[u64]) {
+        let mut locked = self.inner.lock().unwrap();
+        for latency in latencies_ms.iter() {
+            locked.read().record(*latency).unwrap();
+        }
+    }
+}
+
+impl Latencies for RotatingHistogram {
+    fn record(&self, latency: Duration) {
+        let mut locked = self.inner.lock().unwrap();
+        locked.write().record(millis(latency)).unwrap_or_else(|e| {
+            error!("Failed to write to hedge histogram: {:?}", e);
+        })
+    }
+
+    fn value_at_quantile(&self, quantile: f64) -> Duration {
+        let mut locked = self.inner.lock().unwrap();
+        Duration::from_millis(locked.read().value_at_quantile(quantile))
+    }
+
+    fn data_points(&self) -> u64 {
+        let mut locked = self.inner.lock().unwrap();
+        locked.read().len()
+    }
+}
+
+impl Rotating {
+    fn new(period: Duration) -> Rotating {
+        Rotating {
             read: Histogram::<u64>::new_with_bounds(1, 10_000, 3)
                 .expect("Invalid histogram params"),
             write: Histogram::<u64>::new_with_bounds(1, 10_000, 3)
@@ -28,12 +76,12 @@ impl RotatingHistogram {
         }
     }
 
-    pub fn read(&mut self) -> &mut Histogram<u64> {
+    fn read(&mut self) -> &mut Histogram<u64> {
         self.maybe_rotate();
         &mut self.read
     }
 
-    pub fn write(&mut self) -> &mut Histogram<u64> {
+    fn write(&mut self) -> &mut Histogram<u64> {
         self.maybe_rotate();
         &mut self.write
     }
@@ -71,3 +119,15 @@ fn nanos(duration: Duration) -> u64 {
         .saturating_mul(NANOS_PER_SEC)
         .saturating_add(u64::from(duration.subsec_nanos()))
 }
+
+// TODO: Remove when Duration::as_millis() becomes stable.
+const NANOS_PER_MILLI: u32 = 1_000_000;
+const MILLIS_PER_SEC: u64 = 1_000;
+fn millis(duration: Duration) -> u64 {
+    // Round up.
+    let millis = (duration.subsec_nanos() + NANOS_PER_MILLI - 1) / NANOS_PER_MILLI;
+    duration
+        .as_secs()
+        .saturating_mul(MILLIS_PER_SEC)
+        .saturating_add(u64::from(millis))
+}
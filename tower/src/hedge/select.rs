@@ -1,3 +1,5 @@
+use crate::retry::replay::LimitExceeded;
+use futures_util::stream::{FuturesUnordered, Stream};
 use pin_project::pin_project;
 use std::{
     future::Future,
@@ -5,22 +7,24 @@ use std::{
     task::{Context, Poll},
 };
 use tower_service::Service;
+use tracing::trace;
 
 /// A policy which decides which requests can be cloned and sent to the B
-/// service.
+/// services.
 pub trait Policy<Request> {
     fn clone_request(&self, req: &Request) -> Option<Request>;
 }
 
-/// Select is a middleware which attempts to clone the request and sends the
-/// original request to the A service and, if the request was able to be cloned,
-/// the cloned request to the B service.  Both resulting futures will be polled
-/// and whichever future completes first will be used as the result.
+/// Select is a middleware which sends the original request to the A service
+/// and, for each of the B services, attempts to clone the request and send the
+/// clone to that B service.  All resulting futures will be polled and
+/// whichever future completes first will be used as the result; the others are
+/// dropped.
 #[derive(Debug)]
 pub struct Select<P, A, B> {
     policy: P,
     a: A,
-    b: B,
+    bs: Vec<B>,
 }
 
 #[pin_project]
@@ -28,12 +32,11 @@ pub struct Select<P, A, B> {
 pub struct ResponseFuture<AF, BF> {
     #[pin]
     a_fut: AF,
-    #[pin]
-    b_fut: Option<BF>,
+    b_futs: FuturesUnordered<BF>,
 }
 
 impl<P, A, B> Select<P, A, B> {
-    pub fn new<Request>(policy: P, a: A, b: B) -> Self
+    pub fn new<Request>(policy: P, a: A, bs: Vec<B>) -> Self
     where
         P: Policy<Request>,
         A: Service<Request>,
@@ -41,7 +44,7 @@ impl<P, A, B> Select<P, A, B> {
         B: Service<Request, Response = A::Response>,
         B::Error: Into<crate::BoxError>,
     {
-        Select { policy, a, b }
+        Select { policy, a, bs }
     }
 }
 
@@ -58,23 +61,36 @@ where
     type Future = ResponseFuture<A::Future, B::Future>;
 
     fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        match (self.a.poll_ready(cx), self.b.poll_ready(cx)) {
-            (Poll::Ready(Ok(())), Poll::Ready(Ok(()))) => Poll::Ready(Ok(())),
-            (Poll::Ready(Err(e)), _) => Poll::Ready(Err(e.into())),
-            (_, Poll::Ready(Err(e))) => Poll::Ready(Err(e.into())),
-            _ => Poll::Pending,
+        let mut ready = match self.a.poll_ready(cx) {
+            Poll::Ready(Ok(())) => true,
+            Poll::Ready(Err(e)) => return Poll::Ready(Err(e.into())),
+            Poll::Pending => false,
+        };
+        for b in &mut self.bs {
+            match b.poll_ready(cx) {
+                Poll::Ready(Ok(())) => {}
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e.into())),
+                Poll::Pending => ready = false,
+            }
+        }
+        if ready {
+            Poll::Ready(Ok(()))
+        } else {
+            Poll::Pending
         }
     }
 
     fn call(&mut self, request: Request) -> Self::Future {
-        let b_fut = if let Some(cloned_req) = self.policy.clone_request(&request) {
-            Some(self.b.call(cloned_req))
-        } else {
-            None
-        };
+        let b_futs = FuturesUnordered::new();
+        for b in &mut self.bs {
+            match self.policy.clone_request(&request) {
+                Some(cloned_req) => b_futs.push(b.call(cloned_req)),
+                None => break,
+            }
+        }
         ResponseFuture {
             a_fut: self.a.call(request),
-            b_fut,
+            b_futs,
         }
     }
 }
@@ -91,14 +107,41 @@ where
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let this = self.project();
 
-        if let Poll::Ready(r) = this.a_fut.poll(cx) {
-            return Poll::Ready(Ok(r.map_err(Into::into)?));
-        }
-        if let Some(b_fut) = this.b_fut.as_pin_mut() {
-            if let Poll::Ready(r) = b_fut.poll(cx) {
-                return Poll::Ready(Ok(r.map_err(Into::into)?));
+        let result = if let Poll::Ready(r) = this.a_fut.poll(cx) {
+            r.map_err(Into::into)
+        } else {
+            loop {
+                match Pin::new(&mut *this.b_futs).poll_next(cx) {
+                    Poll::Ready(Some(r)) => match r.map_err(Into::into) {
+                        // A copy of the request that could not be replayed
+                        // only aborts its own leg, so the original request's
+                        // result is used instead.
+                        Err(e) if is_replay_limit(&*e) => {
+                            trace!("dropping hedge request that exceeded the replay limit");
+                        }
+                        r => break r,
+                    },
+                    _ => return Poll::Pending,
+                }
             }
+        };
+
+        // Cancel the requests that lost the race.
+        *this.b_futs = FuturesUnordered::new();
+        Poll::Ready(result)
+    }
+}
+
+/// Returns `true` if `error` was caused by a [`ReplayBody`](crate::retry::replay::ReplayBody) that
+/// could not be replayed.
+fn is_replay_limit(mut error: &(dyn std::error::Error + 'static)) -> bool {
+    loop {
+        if error.is::<LimitExceeded>() {
+            return true;
+        }
+        match error.source() {
+            Some(source) => error = source,
+            None => return false,
         }
-        return Poll::Pending;
     }
 }
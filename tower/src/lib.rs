@@ -63,6 +63,8 @@ pub mod timeout;
 pub mod util;
 
 pub mod builder;
+#[cfg(feature = "discover")]
+mod hash;
 pub mod layer;
 
 #[cfg(feature = "util")]
@@ -60,7 +60,7 @@ where
 /// Proxies `Discover` such that all changes are wrapped with a constant load.
 #[cfg(feature = "discover")]
 impl<D: Discover + Unpin, M: Copy> Stream for Constant<D, M> {
-    type Item = Result<Change<D::Key, Constant<D::Service, M>>, D::Error>;
+    type Item = Result<Change<D::Key, Constant<D::Service, M>, D::Meta>, D::Error>;
 
     /// Yields the next discovery change set.
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
@@ -71,6 +71,7 @@ impl<D: Discover + Unpin, M: Copy> Stream for Constant<D, M> {
             None => return Poll::Ready(None),
             Some(Insert(k, svc)) => Insert(k, Constant::new(svc, *this.load)),
             Some(Remove(k)) => Remove(k),
+            Some(Update(k, meta)) => Update(k, meta),
         };
 
         Poll::Ready(Some(Ok(change)))
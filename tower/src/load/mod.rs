@@ -7,6 +7,10 @@
 //!  - [`PendingRequests`] — Measures load by tracking the number of in-flight requests.
 //!  - [`PeakEwma`] — Measures load using a moving average of the peak latency for the service.
 //!
+//! The load measured by any of these can be scaled by the relative capacity of each service by
+//! wrapping it in [`Weighted`], and newly started services can be warmed up gradually by wrapping
+//! them in [`SlowStart`].
+//!
 //! In general, you will want to use one of these when using the types in [`tower::balance`] which
 //! balance services depending on their load. Which load metric to use depends on your exact
 //! use-case, but the ones above should get you quite far!
@@ -59,16 +63,23 @@ pub mod completion;
 mod constant;
 pub mod peak_ewma;
 pub mod pending_requests;
+pub mod slow_start;
+pub mod weight;
 
 pub use self::{
     completion::{CompleteOnResponse, TrackCompletion},
     constant::Constant,
     peak_ewma::PeakEwma,
     pending_requests::PendingRequests,
+    slow_start::SlowStart,
+    weight::{HasWeight, Weight, Weighted},
 };
 
 #[cfg(feature = "discover")]
-pub use self::{peak_ewma::PeakEwmaDiscover, pending_requests::PendingRequestsDiscover};
+pub use self::{
+    peak_ewma::PeakEwmaDiscover, pending_requests::PendingRequestsDiscover,
+    slow_start::SlowStartDiscover, weight::WeightedDiscover,
+};
 
 /// Types that implement this trait can give an estimate of how loaded they are.
 ///
@@ -10,7 +10,9 @@ use pin_project::pin_project;
 use std::pin::Pin;
 
 use super::completion::{CompleteOnResponse, TrackCompletion, TrackCompletionFuture};
+use super::weight::Weight;
 use super::Load;
+use std::ops;
 use std::task::{Context, Poll};
 use std::{
     sync::{Arc, Mutex},
@@ -188,13 +190,14 @@ where
     D: Discover,
     C: Clone,
 {
-    type Item = Result<Change<D::Key, PeakEwma<D::Service, C>>, D::Error>;
+    type Item = Result<Change<D::Key, PeakEwma<D::Service, C>, D::Meta>, D::Error>;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         let this = self.project();
         let change = match ready!(this.discover.poll_discover(cx)).transpose()? {
             None => return Poll::Ready(None),
             Some(Change::Remove(k)) => Change::Remove(k),
+            Some(Change::Update(k, meta)) => Change::Update(k, meta),
             Some(Change::Insert(k, svc)) => {
                 let peak_ewma = PeakEwma::new(
                     svc,
@@ -293,6 +296,20 @@ impl Drop for Handle {
 
 // ===== impl Cost =====
 
+impl ops::Div<Weight> for Cost {
+    type Output = f64;
+
+    fn div(self, weight: Weight) -> f64 {
+        self.0 / weight
+    }
+}
+
+impl From<Cost> for f64 {
+    fn from(cost: Cost) -> f64 {
+        cost.0
+    }
+}
+
 // Utility that converts durations to nanos in f64.
 //
 // Due to a lossy transformation, the maximum value that can be represented is ~585 years,
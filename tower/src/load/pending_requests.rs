@@ -10,7 +10,9 @@ use pin_project::pin_project;
 use std::pin::Pin;
 
 use super::completion::{CompleteOnResponse, TrackCompletion, TrackCompletionFuture};
+use super::weight::Weight;
 use super::Load;
+use std::ops;
 use std::sync::Arc;
 use std::task::{Context, Poll};
 use tower_service::Service;
@@ -117,7 +119,7 @@ where
     D: Discover,
     C: Clone,
 {
-    type Item = Result<Change<D::Key, PendingRequests<D::Service, C>>, D::Error>;
+    type Item = Result<Change<D::Key, PendingRequests<D::Service, C>, D::Meta>, D::Error>;
 
     /// Yields the next discovery change set.
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
@@ -128,12 +130,29 @@ where
             None => return Poll::Ready(None),
             Some(Insert(k, svc)) => Insert(k, PendingRequests::new(svc, this.completion.clone())),
             Some(Remove(k)) => Remove(k),
+            Some(Update(k, meta)) => Update(k, meta),
         };
 
         Poll::Ready(Some(Ok(change)))
     }
 }
 
+// ===== impl Count =====
+
+impl ops::Div<Weight> for Count {
+    type Output = f64;
+
+    fn div(self, weight: Weight) -> f64 {
+        self.0 / weight
+    }
+}
+
+impl From<Count> for f64 {
+    fn from(count: Count) -> f64 {
+        count.0 as f64
+    }
+}
+
 // ==== RefCount ====
 
 impl RefCount {
@@ -0,0 +1,298 @@
+//! A `Load` implementation that ramps up the weight of newly discovered services.
+//!
+//! A service that was just started is often much slower than its peers until its caches are warm
+//! (or its JIT compiler has caught up), but load estimators have no history for it, so balancers
+//! such as [`p2c`](crate::balance::p2c) consider it at least as attractive as any other service.
+//!
+//! Wrapping a service in [`SlowStart`] divides its load by a [`Weight`] that grows linearly from
+//! an initial weight to 1 over a warm-up window, starting when the service is wrapped. The load is
+//! offset by one before it is divided (and after, so that a warm service's load is unchanged), so
+//! that an idle service is penalized as well: with a weight of 0.1, a load of 0 becomes 9. During
+//! the window the service looks more loaded than it is, so it receives a share of the traffic that
+//! grows as it warms up. Once the window has elapsed, its load is passed through unscaled.
+//!
+//! [`SlowStartDiscover`] wraps every service as it is discovered, so that only services inserted
+//! after the balancer was started are ramped up.
+
+#[cfg(feature = "discover")]
+use crate::discover::{Change, Discover};
+#[cfg(feature = "discover")]
+use futures_core::{ready, Stream};
+#[cfg(feature = "discover")]
+use pin_project::pin_project;
+#[cfg(feature = "discover")]
+use std::pin::Pin;
+
+use super::weight::Weight;
+use super::Load;
+use std::ops;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time::Instant;
+use tower_service::Service;
+
+/// The weight a service starts with, by default.
+const DEFAULT_INITIAL_WEIGHT: f64 = 0.1;
+
+/// Wraps a service so that its load is scaled down from an initial weight over a warm-up window.
+#[derive(Clone, Debug)]
+pub struct SlowStart<S> {
+    service: S,
+    ramp: Ramp,
+}
+
+/// Wraps a `D`-typed stream of discovered services with `SlowStart`.
+#[pin_project]
+#[derive(Debug)]
+#[cfg(feature = "discover")]
+pub struct SlowStartDiscover<D> {
+    #[pin]
+    discover: D,
+    window: Duration,
+    initial_weight: f64,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Ramp {
+    start: Instant,
+    window: Duration,
+    initial_weight: f64,
+}
+
+// ===== impl SlowStart =====
+
+impl<S> SlowStart<S> {
+    /// Wraps an `S`-typed service, warming it up over `window` starting now.
+    ///
+    /// The service starts with a weight of 0.1.
+    pub fn new(service: S, window: Duration) -> Self {
+        SlowStart {
+            service,
+            ramp: Ramp {
+                start: Instant::now(),
+                window,
+                initial_weight: DEFAULT_INITIAL_WEIGHT,
+            },
+        }
+    }
+
+    /// Sets the weight the service starts with.
+    ///
+    /// # Panics
+    ///
+    /// If `weight` is not greater than 0 and at most 1.
+    pub fn initial_weight(mut self, weight: f64) -> Self {
+        self.ramp.initial_weight = check_initial_weight(weight);
+        self
+    }
+
+    /// Returns the weight the offset load of this service is currently divided by.
+    pub fn weight(&self) -> Weight {
+        self.ramp.weight()
+    }
+
+    /// Returns whether the warm-up window has elapsed.
+    pub fn is_warm(&self) -> bool {
+        self.ramp.start.elapsed() >= self.ramp.window
+    }
+
+    /// Get a reference to the inner service
+    pub fn get_ref(&self) -> &S {
+        &self.service
+    }
+
+    /// Consume `self`, returning the inner service
+    pub fn into_inner(self) -> S {
+        self.service
+    }
+}
+
+impl<L> Load for SlowStart<L>
+where
+    L: Load,
+    L::Metric: ops::Div<Weight>,
+    <L::Metric as ops::Div<Weight>>::Output:
+        ops::Add<f64, Output = <L::Metric as ops::Div<Weight>>::Output> + PartialOrd,
+{
+    type Metric = <L::Metric as ops::Div<Weight>>::Output;
+
+    fn load(&self) -> Self::Metric {
+        // (load + 1) / weight - 1, without requiring the inner metric to support addition.
+        let weight = self.ramp.weight();
+        self.service.load() / weight + (1.0 / weight.get() - 1.0)
+    }
+}
+
+impl<S, Request> Service<Request> for SlowStart<S>
+where
+    S: Service<Request>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        self.service.call(req)
+    }
+}
+
+// ===== impl SlowStartDiscover =====
+
+#[cfg(feature = "discover")]
+impl<D> SlowStartDiscover<D> {
+    /// Wraps a `Discover`, warming up each of its services over `window` from when it is
+    /// inserted.
+    ///
+    /// Services start with a weight of 0.1.
+    pub fn new(discover: D, window: Duration) -> Self
+    where
+        D: Discover,
+    {
+        Self {
+            discover,
+            window,
+            initial_weight: DEFAULT_INITIAL_WEIGHT,
+        }
+    }
+
+    /// Sets the weight each service starts with.
+    ///
+    /// # Panics
+    ///
+    /// If `weight` is not greater than 0 and at most 1.
+    pub fn initial_weight(mut self, weight: f64) -> Self {
+        self.initial_weight = check_initial_weight(weight);
+        self
+    }
+}
+
+#[cfg(feature = "discover")]
+impl<D> Stream for SlowStartDiscover<D>
+where
+    D: Discover,
+{
+    type Item = Result<Change<D::Key, SlowStart<D::Service>, D::Meta>, D::Error>;
+
+    /// Yields the next discovery change set.
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        use self::Change::*;
+
+        let this = self.project();
+        let change = match ready!(this.discover.poll_discover(cx)).transpose()? {
+            None => return Poll::Ready(None),
+            Some(Insert(k, svc)) => Insert(
+                k,
+                SlowStart::new(svc, *this.window).initial_weight(*this.initial_weight),
+            ),
+            Some(Remove(k)) => Remove(k),
+            Some(Update(k, meta)) => Update(k, meta),
+        };
+
+        Poll::Ready(Some(Ok(change)))
+    }
+}
+
+// ===== impl Ramp =====
+
+impl Ramp {
+    fn weight(&self) -> Weight {
+        let elapsed = self.start.elapsed();
+        if elapsed >= self.window {
+            return Weight::default();
+        }
+
+        let progress = elapsed.as_secs_f64() / self.window.as_secs_f64();
+        Weight::new(self.initial_weight + (1.0 - self.initial_weight) * progress)
+    }
+}
+
+fn check_initial_weight(weight: f64) -> f64 {
+    assert!(
+        weight > 0.0 && weight <= 1.0,
+        "initial weight must be greater than 0 and at most 1"
+    );
+    weight
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::load::Constant;
+    use tokio::time;
+
+    #[tokio::test]
+    async fn ramps_up_over_window() {
+        time::pause();
+
+        let svc =
+            SlowStart::new(Constant::new((), 10usize), Duration::from_secs(10)).initial_weight(0.2);
+        assert!(!svc.is_warm());
+        assert_eq!(svc.weight().get(), 0.2);
+        assert_eq!(svc.load(), 54.0);
+
+        time::advance(Duration::from_secs(5)).await;
+        assert!((svc.weight().get() - 0.6).abs() < 1e-9);
+
+        time::advance(Duration::from_secs(5)).await;
+        assert!(svc.is_warm());
+        assert_eq!(svc.weight(), Weight::default());
+        assert_eq!(svc.load(), 10.0);
+    }
+
+    #[tokio::test]
+    async fn idle_cold_service_is_penalized() {
+        time::pause();
+
+        let svc = SlowStart::new(Constant::new((), 0usize), Duration::from_secs(10));
+        assert_eq!(svc.load(), 9.0);
+
+        time::advance(Duration::from_secs(10)).await;
+        assert_eq!(svc.load(), 0.0);
+    }
+
+    #[tokio::test]
+    async fn zero_window_is_warm() {
+        let svc = SlowStart::new(Constant::new((), 10usize), Duration::from_secs(0));
+        assert!(svc.is_warm());
+        assert_eq!(svc.load(), 10.0);
+    }
+
+    #[cfg(feature = "discover")]
+    #[tokio::test]
+    async fn discover_warms_up_inserted_services() {
+        use crate::discover::Dynamic;
+        use tokio_test::{assert_ready, task};
+
+        time::pause();
+
+        let (disco, discover) = Dynamic::with_metadata();
+        let mut disco =
+            task::spawn(SlowStartDiscover::new(disco, Duration::from_secs(10)).initial_weight(0.5));
+
+        discover.insert("a", Constant::new((), 0usize)).unwrap();
+        let svc = match assert_ready!(disco.poll_next()) {
+            Some(Ok(Change::Insert("a", svc))) => svc,
+            _ => panic!("expected insert"),
+        };
+        assert!(!svc.is_warm());
+        assert_eq!(svc.weight().get(), 0.5);
+
+        time::advance(Duration::from_secs(10)).await;
+        assert!(svc.is_warm());
+
+        discover.update("a", 7).unwrap();
+        match assert_ready!(disco.poll_next()) {
+            Some(Ok(Change::Update("a", 7))) => {}
+            _ => panic!("expected update"),
+        }
+        discover.remove("a").unwrap();
+        match assert_ready!(disco.poll_next()) {
+            Some(Ok(Change::Remove("a"))) => {}
+            _ => panic!("expected remove"),
+        }
+    }
+}
@@ -0,0 +1,278 @@
+//! A `Load` implementation that scales the load of a service by a relative weight.
+//!
+//! Balancers such as [`p2c`](crate::balance::p2c) send requests to whichever service reports the
+//! least load. When the services are heterogeneous (for example, some instances have more cores
+//! than others), wrapping each one in [`Weighted`] divides its load by its [`Weight`], so that a
+//! service with twice the weight is considered equally loaded at twice the load, and so receives
+//! roughly twice the traffic.
+//!
+//! The weight of each service usually comes from service discovery, which can wrap each
+//! discovered service in [`Weighted`] as it is inserted. When the weights are yielded as
+//! discovery metadata that implements [`HasWeight`], [`WeightedDiscover`] does so, and updates
+//! the weight of each service whenever its metadata changes.
+
+#[cfg(feature = "discover")]
+use crate::discover::{Change, Discover};
+#[cfg(feature = "discover")]
+use futures_core::{ready, Stream};
+#[cfg(feature = "discover")]
+use pin_project::pin_project;
+#[cfg(feature = "discover")]
+use std::{collections::HashMap, hash::Hash, pin::Pin};
+#[cfg(feature = "discover")]
+use tracing::debug;
+
+use super::Load;
+use std::fmt;
+use std::ops;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+use std::task::{Context, Poll};
+use tower_service::Service;
+
+/// The relative capacity of a service.
+///
+/// Weights are relative to each other; a service with a weight of 2 is expected to handle twice
+/// the load of a service with a weight of 1. The default weight is 1.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub struct Weight(f64);
+
+/// Discovery metadata that carries the weight of a service.
+pub trait HasWeight {
+    /// Returns the weight of the service.
+    fn weight(&self) -> Weight;
+}
+
+/// Wraps a service so that its load is divided by its [`Weight`].
+///
+/// Clones of a `Weighted` service share its weight.
+#[derive(Clone, Debug)]
+pub struct Weighted<S> {
+    service: S,
+    weight: SharedWeight,
+}
+
+/// Wraps a `D`-typed stream of discovered services with `Weighted`, weighting each service by its
+/// latest metadata.
+///
+/// Services are inserted with the default weight, and their weight is updated in place, even
+/// while they are in use, whenever their metadata is updated. A replacement keeps the weight of
+/// the service it replaces. Metadata updates are passed through.
+#[pin_project]
+#[cfg(feature = "discover")]
+pub struct WeightedDiscover<D>
+where
+    D: Discover,
+{
+    #[pin]
+    discover: D,
+    weights: HashMap<D::Key, SharedWeight>,
+}
+
+/// A weight that can be updated while it is shared.
+#[derive(Clone)]
+struct SharedWeight(Arc<AtomicU64>);
+
+// ===== impl Weight =====
+
+impl Weight {
+    /// Create a new weight.
+    ///
+    /// # Panics
+    ///
+    /// If `weight` is not a positive, finite number.
+    pub fn new(weight: f64) -> Self {
+        assert!(
+            weight > 0.0 && weight.is_finite(),
+            "weight must be positive and finite"
+        );
+        Weight(weight)
+    }
+
+    /// Returns the weight as a number.
+    pub fn get(self) -> f64 {
+        self.0
+    }
+}
+
+impl Default for Weight {
+    fn default() -> Self {
+        Weight(1.0)
+    }
+}
+
+impl HasWeight for Weight {
+    fn weight(&self) -> Weight {
+        *self
+    }
+}
+
+impl ops::Div<Weight> for f64 {
+    type Output = f64;
+
+    fn div(self, weight: Weight) -> f64 {
+        self / weight.0
+    }
+}
+
+impl ops::Div<Weight> for usize {
+    type Output = f64;
+
+    fn div(self, weight: Weight) -> f64 {
+        self as f64 / weight.0
+    }
+}
+
+// ===== impl Weighted =====
+
+impl<S> Weighted<S> {
+    /// Wraps an `S`-typed service with the given weight.
+    pub fn new(service: S, weight: Weight) -> Self {
+        Weighted {
+            service,
+            weight: SharedWeight::new(weight),
+        }
+    }
+
+    /// Returns the weight of this service.
+    pub fn weight(&self) -> Weight {
+        self.weight.get()
+    }
+
+    /// Get a reference to the inner service
+    pub fn get_ref(&self) -> &S {
+        &self.service
+    }
+
+    /// Consume `self`, returning the inner service
+    pub fn into_inner(self) -> S {
+        self.service
+    }
+}
+
+impl<L> Load for Weighted<L>
+where
+    L: Load,
+    L::Metric: ops::Div<Weight>,
+    <L::Metric as ops::Div<Weight>>::Output: PartialOrd,
+{
+    type Metric = <L::Metric as ops::Div<Weight>>::Output;
+
+    fn load(&self) -> Self::Metric {
+        self.service.load() / self.weight.get()
+    }
+}
+
+impl<S, Request> Service<Request> for Weighted<S>
+where
+    S: Service<Request>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        self.service.call(req)
+    }
+}
+
+// ===== impl WeightedDiscover =====
+
+#[cfg(feature = "discover")]
+impl<D> WeightedDiscover<D>
+where
+    D: Discover,
+{
+    /// Wraps a `Discover`, weighting each of its services by its metadata.
+    pub fn new(discover: D) -> Self {
+        WeightedDiscover {
+            discover,
+            weights: HashMap::new(),
+        }
+    }
+}
+
+#[cfg(feature = "discover")]
+impl<D> fmt::Debug for WeightedDiscover<D>
+where
+    D: Discover + fmt::Debug,
+    D::Key: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WeightedDiscover")
+            .field("discover", &self.discover)
+            .field("weights", &self.weights)
+            .finish()
+    }
+}
+
+#[cfg(feature = "discover")]
+impl<D> Stream for WeightedDiscover<D>
+where
+    D: Discover,
+    D::Key: Hash + Clone,
+    D::Meta: HasWeight,
+{
+    type Item = Result<Change<D::Key, Weighted<D::Service>, D::Meta>, D::Error>;
+
+    /// Yields the next discovery change set.
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        use self::Change::*;
+
+        let this = self.project();
+        let change = match ready!(this.discover.poll_discover(cx)).transpose()? {
+            None => return Poll::Ready(None),
+            Some(Insert(k, service)) => {
+                let weight = this.weights.entry(k.clone()).or_default().clone();
+                Insert(k, Weighted { service, weight })
+            }
+            Some(Remove(k)) => {
+                this.weights.remove(&k);
+                Remove(k)
+            }
+            Some(Update(k, meta)) => {
+                match this.weights.get(&k) {
+                    Some(weight) => weight.set(meta.weight()),
+                    None => debug!("ignoring the weight of an unknown service"),
+                }
+                Update(k, meta)
+            }
+        };
+
+        Poll::Ready(Some(Ok(change)))
+    }
+}
+
+// ===== impl SharedWeight =====
+
+impl SharedWeight {
+    fn new(weight: Weight) -> Self {
+        SharedWeight(Arc::new(AtomicU64::new(weight.0.to_bits())))
+    }
+
+    fn get(&self) -> Weight {
+        Weight(f64::from_bits(self.0.load(Ordering::Relaxed)))
+    }
+
+    fn set(&self, weight: Weight) {
+        self.0.store(weight.0.to_bits(), Ordering::Relaxed);
+    }
+}
+
+impl fmt::Debug for SharedWeight {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.get().fmt(f)
+    }
+}
+
+impl Default for SharedWeight {
+    fn default() -> Self {
+        SharedWeight::new(Weight::default())
+    }
+}
@@ -136,6 +136,11 @@ where
         self.pending_cancel_txs.contains_key(key)
     }
 
+    /// Returns an iterator over the keys of the services in the unready set.
+    pub fn pending_keys(&self) -> impl Iterator<Item = &K> {
+        self.pending_cancel_txs.keys()
+    }
+
     /// Obtains a reference to a service in the ready set by key.
     pub fn get_ready<Q: Hash + Equivalent<K>>(&self, key: &Q) -> Option<(usize, &K, &S)> {
         self.ready.get_full(key).map(|(i, k, v)| (i, k, &v.0))
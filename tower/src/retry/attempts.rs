@@ -0,0 +1,112 @@
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::{debug, debug_span, Span};
+
+/// Information about the attempts made for a single request.
+///
+/// This is passed to [`Policy::completed`](super::Policy::completed) once the request has
+/// completed.
+#[derive(Debug, Clone)]
+pub struct Attempts {
+    count: usize,
+    backoff: Duration,
+    classification: Option<Classification>,
+}
+
+/// Why no further attempts were made for a request.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Classification {
+    /// The policy decided that the final result should not be retried.
+    Accepted,
+    /// The final result could not be retried, since the request could not be cloned.
+    NotCloned,
+    /// The retry deadline elapsed before the request completed.
+    ///
+    /// This is only used by [`TimeoutRetry`](super::TimeoutRetry).
+    DeadlineExhausted,
+}
+
+/// Tracks the attempts of an in-flight request, and the `tracing` span of the current attempt.
+#[derive(Debug)]
+pub(crate) struct Tracker {
+    attempts: Attempts,
+    backoff_start: Option<Instant>,
+    span: Span,
+}
+
+// ===== impl Attempts =====
+
+impl Attempts {
+    /// The number of attempts made, including the original request.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// The number of times the request was retried.
+    pub fn retries(&self) -> usize {
+        self.count - 1
+    }
+
+    /// The total time spent waiting on the futures returned by
+    /// [`Policy::retry`](super::Policy::retry), such as backoff delays.
+    pub fn backoff(&self) -> Duration {
+        self.backoff
+    }
+
+    /// Why no further attempts were made, or `None` if the request has not completed yet.
+    pub fn classification(&self) -> Option<Classification> {
+        self.classification
+    }
+}
+
+// ===== impl Tracker =====
+
+impl Tracker {
+    pub(crate) fn new() -> Self {
+        Tracker {
+            attempts: Attempts {
+                count: 1,
+                backoff: Duration::from_secs(0),
+                classification: None,
+            },
+            backoff_start: None,
+            span: debug_span!("attempt", number = 1),
+        }
+    }
+
+    /// The span that the current attempt should be polled in.
+    pub(crate) fn span(&self) -> &Span {
+        &self.span
+    }
+
+    /// The policy asked to retry the result of the current attempt.
+    pub(crate) fn retrying(&mut self, is_err: bool) {
+        debug!(parent: &self.span, is_err, "retrying request");
+        self.backoff_start = Some(Instant::now());
+    }
+
+    /// The future returned by the policy completed, and another attempt is about to be made.
+    pub(crate) fn next_attempt(&mut self) {
+        if let Some(start) = self.backoff_start.take() {
+            self.attempts.backoff += start.elapsed();
+        }
+        self.attempts.count += 1;
+        self.span = debug_span!("attempt", number = self.attempts.count);
+    }
+
+    /// No further attempts will be made.
+    pub(crate) fn finish(&mut self, classification: Classification) -> &Attempts {
+        if let Some(start) = self.backoff_start.take() {
+            self.attempts.backoff += start.elapsed();
+        }
+        self.attempts.classification = Some(classification);
+        debug!(
+            parent: &self.span,
+            attempts = self.attempts.count,
+            backoff = ?self.attempts.backoff,
+            classification = ?classification,
+            "request completed"
+        );
+        &self.attempts
+    }
+}
@@ -0,0 +1,322 @@
+//! Exponential backoff for retrying "failed" requests.
+//!
+//! [`ExponentialBackoff`] describes how long to wait between attempts, and how many attempts may
+//! be made in total. Calling [`ExponentialBackoff::classify`] with a function that decides which
+//! results should be retried produces a [`BackoffPolicy`], which implements [`Policy`] and can be
+//! passed directly to [`Retry`](super::Retry) or [`RetryLayer`](super::RetryLayer).
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//! use tower::retry::{backoff::{ExponentialBackoff, Jitter}, RetryLayer};
+//!
+//! type Req = String;
+//! type Res = String;
+//! type Error = Box<dyn std::error::Error + Send + Sync>;
+//!
+//! let policy = ExponentialBackoff::new(Duration::from_millis(50))
+//!     .max_delay(Duration::from_secs(2))
+//!     .jitter(Jitter::Full)
+//!     .max_attempts(Some(4))
+//!     .classify(|_: &Req, result: Result<&Res, &Error>| result.is_err());
+//!
+//! let layer = RetryLayer::new(policy);
+//! # drop(layer);
+//! ```
+
+use super::{
+    budget::{Budget, BudgetedPolicy},
+    Policy,
+};
+use futures_core::ready;
+use pin_project::pin_project;
+use rand::Rng;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::time::{delay_for, Delay};
+
+/// How randomness is applied to the delays computed by an [`ExponentialBackoff`].
+///
+/// See [Exponential Backoff And Jitter] for a comparison of these strategies.
+///
+/// [Exponential Backoff And Jitter]: https://aws.amazon.com/blogs/architecture/exponential-backoff-and-jitter/
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Jitter {
+    /// Wait for exactly the computed exponential delay.
+    None,
+    /// Wait for a random duration between zero and the computed exponential delay.
+    Full,
+    /// Wait for half of the computed exponential delay, plus a random duration of up to the other
+    /// half.
+    Equal,
+    /// Wait for a random duration between the base delay and three times the previous delay.
+    ///
+    /// This does not use the exponential delay at all, though it is still capped by the maximum
+    /// delay.
+    Decorrelated,
+}
+
+/// Configuration for retrying with exponentially increasing delays between attempts.
+///
+/// The `n`th retry waits for `base * multiplier^(n - 1)`, capped at the maximum delay, with
+/// [`Jitter`] applied to the result.
+#[derive(Debug, Clone)]
+pub struct ExponentialBackoff {
+    base: Duration,
+    multiplier: f64,
+    max_delay: Duration,
+    jitter: Jitter,
+    max_attempts: Option<usize>,
+}
+
+impl ExponentialBackoff {
+    /// Create a new backoff configuration where the first retry waits for `base`.
+    ///
+    /// By default, the delay doubles on every retry up to a maximum of 10 seconds, [`Jitter::Full`]
+    /// is applied, and no limit is imposed on the number of attempts.
+    pub fn new(base: Duration) -> Self {
+        ExponentialBackoff {
+            base,
+            multiplier: 2.0,
+            max_delay: Duration::from_secs(10),
+            jitter: Jitter::Full,
+            max_attempts: None,
+        }
+    }
+
+    /// The factor the delay is multiplied by on every retry.
+    ///
+    /// Must be at least 1. The default value is 2.
+    pub fn multiplier(&mut self, multiplier: f64) -> &mut Self {
+        assert!(multiplier >= 1.0, "multiplier must be at least 1");
+        self.multiplier = multiplier;
+        self
+    }
+
+    /// The longest time to wait between two attempts.
+    ///
+    /// The default value is 10 seconds.
+    pub fn max_delay(&mut self, max_delay: Duration) -> &mut Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// How randomness is applied to the computed delays.
+    ///
+    /// The default is [`Jitter::Full`].
+    pub fn jitter(&mut self, jitter: Jitter) -> &mut Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// The maximum number of attempts made for a single request, including the original one.
+    ///
+    /// `Some(1)` disables retries entirely. No maximum limit is imposed by default.
+    pub fn max_attempts(&mut self, limit: Option<usize>) -> &mut Self {
+        self.max_attempts = limit;
+        self
+    }
+
+    /// Build a [`BackoffPolicy`] that retries every result for which `classify` returns `true`.
+    pub fn classify<F>(&self, classify: F) -> BackoffPolicy<F> {
+        BackoffPolicy {
+            backoff: self.clone(),
+            classify,
+            retries: 0,
+            previous: self.base,
+        }
+    }
+
+    /// Returns `true` if another attempt may be made after `attempts` attempts have failed.
+    pub fn can_retry(&self, attempts: usize) -> bool {
+        match self.max_attempts {
+            Some(max) => attempts < max,
+            None => true,
+        }
+    }
+
+    /// Compute how long to wait before making another attempt.
+    ///
+    /// `retries` is the number of retries that have already been made, and `previous` is the
+    /// delay that preceded the latest attempt (or the base delay if no retries have been made).
+    pub fn delay(&self, retries: usize, previous: Duration) -> Duration {
+        let max = self.max_delay.as_secs_f64();
+        let base = self.base.as_secs_f64().min(max);
+
+        let exp = base * self.multiplier.powi(retries.min(i32::MAX as usize) as i32);
+        let capped = if exp.is_finite() { exp.min(max) } else { max };
+
+        let mut rng = rand::thread_rng();
+        let secs = match self.jitter {
+            Jitter::None => capped,
+            Jitter::Full => capped * rng.gen::<f64>(),
+            Jitter::Equal => capped / 2.0 + capped / 2.0 * rng.gen::<f64>(),
+            Jitter::Decorrelated => {
+                let upper = (previous.as_secs_f64() * 3.0).min(max).max(base);
+                base + (upper - base) * rng.gen::<f64>()
+            }
+        };
+        Duration::from_secs_f64(secs)
+    }
+}
+
+/// A [`Policy`] that retries requests with exponentially increasing delays between attempts.
+///
+/// Which results are retried is decided by a classifier function, which is passed the original
+/// request and the result of the latest attempt, and returns `true` if the request should be
+/// retried. The delay is decided by the [`ExponentialBackoff`] the policy was built from.
+///
+/// Created with [`ExponentialBackoff::classify`].
+#[derive(Debug, Clone)]
+pub struct BackoffPolicy<F> {
+    backoff: ExponentialBackoff,
+    classify: F,
+    retries: usize,
+    previous: Duration,
+}
+
+impl<F> BackoffPolicy<F> {
+    /// Limit retries using the given [`Budget`].
+    ///
+    /// See [`BudgetedPolicy`] for details.
+    pub fn with_budget(self, budget: Arc<Budget>) -> BudgetedPolicy<Self> {
+        BudgetedPolicy::new(self, budget)
+    }
+
+    /// Returns the number of retries this policy has already made.
+    pub fn retries(&self) -> usize {
+        self.retries
+    }
+}
+
+impl<F, Req, Res, E> Policy<Req, Res, E> for BackoffPolicy<F>
+where
+    F: Fn(&Req, Result<&Res, &E>) -> bool + Clone,
+    Req: Clone,
+{
+    type Future = BackoffFuture<Self>;
+
+    fn retry(&self, req: &Req, result: Result<&Res, &E>) -> Option<Self::Future> {
+        if !(self.classify)(req, result) || !self.backoff.can_retry(self.retries + 1) {
+            return None;
+        }
+
+        let delay = self.backoff.delay(self.retries, self.previous);
+        let policy = BackoffPolicy {
+            backoff: self.backoff.clone(),
+            classify: self.classify.clone(),
+            retries: self.retries + 1,
+            previous: delay,
+        };
+        Some(BackoffFuture::new(delay, policy))
+    }
+
+    fn clone_request(&self, req: &Req) -> Option<Req> {
+        Some(req.clone())
+    }
+}
+
+/// A future that yields a retry policy after a backoff delay has elapsed.
+#[pin_project]
+#[derive(Debug)]
+pub struct BackoffFuture<P> {
+    #[pin]
+    delay: Delay,
+    policy: Option<P>,
+}
+
+impl<P> BackoffFuture<P> {
+    /// Create a future that yields `policy` once `delay` has elapsed.
+    pub fn new(delay: Duration, policy: P) -> Self {
+        BackoffFuture {
+            delay: delay_for(delay),
+            policy: Some(policy),
+        }
+    }
+}
+
+impl<P> Future for BackoffFuture<P> {
+    type Output = P;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        ready!(this.delay.poll(cx));
+        Poll::Ready(this.policy.take().expect("polled after completion"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn exponential() {
+        let mut backoff = ExponentialBackoff::new(ms(100));
+        backoff.max_delay(ms(1000)).jitter(Jitter::None);
+
+        let delays = (0..6)
+            .map(|retries| backoff.delay(retries, ms(0)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            delays,
+            vec![ms(100), ms(200), ms(400), ms(800), ms(1000), ms(1000)]
+        );
+    }
+
+    #[test]
+    fn multiplier() {
+        let mut backoff = ExponentialBackoff::new(ms(10));
+        backoff.multiplier(3.0).jitter(Jitter::None);
+
+        assert_eq!(backoff.delay(2, ms(0)), ms(90));
+    }
+
+    #[test]
+    fn does_not_overflow() {
+        let mut backoff = ExponentialBackoff::new(ms(100));
+        backoff.max_delay(ms(500)).jitter(Jitter::None);
+
+        assert_eq!(backoff.delay(usize::MAX, ms(0)), ms(500));
+    }
+
+    #[test]
+    fn jitter_bounds() {
+        let mut backoff = ExponentialBackoff::new(ms(100));
+        backoff.max_delay(ms(1000));
+
+        for _ in 0..100 {
+            backoff.jitter(Jitter::Full);
+            assert!(backoff.delay(2, ms(0)) <= ms(400));
+
+            backoff.jitter(Jitter::Equal);
+            let delay = backoff.delay(2, ms(0));
+            assert!(delay >= ms(200) && delay <= ms(400));
+
+            backoff.jitter(Jitter::Decorrelated);
+            let delay = backoff.delay(2, ms(200));
+            assert!(delay >= ms(100) && delay <= ms(600));
+            let delay = backoff.delay(2, ms(900));
+            assert!(delay >= ms(100) && delay <= ms(1000));
+        }
+    }
+
+    #[test]
+    fn max_attempts() {
+        let mut backoff = ExponentialBackoff::new(ms(100));
+        assert!(backoff.can_retry(1000));
+
+        backoff.max_attempts(Some(3));
+        assert!(backoff.can_retry(2));
+        assert!(!backoff.can_retry(3));
+    }
+}
@@ -1,11 +1,17 @@
 //! A retry "budget" for allowing only a certain amount of retries over time.
 
+use super::{Attempts, Policy};
+use futures_core::ready;
+use pin_project::pin_project;
 use std::{
     fmt,
+    future::Future,
+    pin::Pin,
     sync::{
         atomic::{AtomicIsize, Ordering},
-        Mutex,
+        Arc, Mutex,
     },
+    task::{Context, Poll},
     time::Duration,
 };
 use tokio::time::Instant;
@@ -27,6 +33,30 @@ pub struct Overdrawn {
     _inner: (),
 }
 
+/// A retry [`Policy`] that limits the retries of another policy using a [`Budget`].
+///
+/// A deposit is made into the budget for every original request, and a withdrawal is made before
+/// every retry the inner policy asks for. If the budget is [`Overdrawn`], the request is not
+/// retried, and the result of the latest attempt is returned instead.
+///
+/// Since the budget is shared through an `Arc`, cloning a `BudgetedPolicy` (or the
+/// [`Retry`](super::Retry) service that holds it) caps retries across all of the clones.
+#[derive(Debug, Clone)]
+pub struct BudgetedPolicy<P> {
+    inner: P,
+    budget: Arc<Budget>,
+    retrying: bool,
+}
+
+/// The `Future` returned by [`BudgetedPolicy::retry`](Policy::retry).
+#[pin_project]
+#[derive(Debug)]
+pub struct BudgetedFuture<F> {
+    #[pin]
+    inner: F,
+    budget: Option<Arc<Budget>>,
+}
+
 #[derive(Debug)]
 struct Bucket {
     generation: Mutex<Generation>,
@@ -149,6 +179,79 @@ impl fmt::Debug for Budget {
     }
 }
 
+// ===== impl BudgetedPolicy =====
+
+impl<P> BudgetedPolicy<P> {
+    /// Limit the retries made by `policy` using `budget`.
+    pub fn new(policy: P, budget: Arc<Budget>) -> Self {
+        BudgetedPolicy {
+            inner: policy,
+            budget,
+            retrying: false,
+        }
+    }
+
+    /// Get a reference to the inner policy.
+    pub fn get_ref(&self) -> &P {
+        &self.inner
+    }
+
+    /// Get a reference to the budget retries are withdrawn from.
+    pub fn budget(&self) -> &Arc<Budget> {
+        &self.budget
+    }
+}
+
+impl<P, Req, Res, E> Policy<Req, Res, E> for BudgetedPolicy<P>
+where
+    P: Policy<Req, Res, E>,
+{
+    type Future = BudgetedFuture<P::Future>;
+
+    fn retry(&self, req: &Req, result: Result<&Res, &E>) -> Option<Self::Future> {
+        let inner = self.inner.retry(req, result)?;
+
+        if self.budget.withdraw().is_err() {
+            tracing::trace!("retry budget overdrawn");
+            return None;
+        }
+
+        Some(BudgetedFuture {
+            inner,
+            budget: Some(self.budget.clone()),
+        })
+    }
+
+    fn clone_request(&self, req: &Req) -> Option<Req> {
+        if !self.retrying {
+            // `Retry` only clones the original request with a policy that has not retried yet.
+            self.budget.deposit();
+        }
+        self.inner.clone_request(req)
+    }
+
+    fn completed(&self, result: Result<&mut Res, &mut E>, attempts: &Attempts) {
+        self.inner.completed(result, attempts)
+    }
+}
+
+impl<F, P> Future for BudgetedFuture<F>
+where
+    F: Future<Output = P>,
+{
+    type Output = BudgetedPolicy<P>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let inner = ready!(this.inner.poll(cx));
+        Poll::Ready(BudgetedPolicy {
+            inner,
+            budget: this.budget.take().expect("polled after completion"),
+            retrying: true,
+        })
+    }
+}
+
 // ===== impl Bucket =====
 
 impl Bucket {
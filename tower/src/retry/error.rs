@@ -0,0 +1,47 @@
+//! Error types
+
+use std::{error, fmt};
+
+/// A single attempt did not complete within the attempt timeout.
+///
+/// This error is passed to the retry [`Policy`](super::Policy), which decides whether the request
+/// is attempted again. It is only returned to the caller if the policy declines to retry.
+#[derive(Debug, Default)]
+pub struct AttemptTimedOut(pub(super) ());
+
+/// The overall retry deadline elapsed before the request completed.
+///
+/// This error is never passed to the retry [`Policy`](super::Policy); once the deadline has
+/// elapsed, no further attempts are made.
+#[derive(Debug, Default)]
+pub struct DeadlineExhausted(pub(super) ());
+
+impl AttemptTimedOut {
+    /// Construct a new attempt timed out error
+    pub fn new() -> Self {
+        AttemptTimedOut(())
+    }
+}
+
+impl fmt::Display for AttemptTimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("request attempt timed out")
+    }
+}
+
+impl error::Error for AttemptTimedOut {}
+
+impl DeadlineExhausted {
+    /// Construct a new deadline exhausted error
+    pub fn new() -> Self {
+        DeadlineExhausted(())
+    }
+}
+
+impl fmt::Display for DeadlineExhausted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("retry deadline exhausted")
+    }
+}
+
+impl error::Error for DeadlineExhausted {}
@@ -1,11 +1,17 @@
 //! Future types
 
-use super::{Policy, Retry};
+use super::{
+    attempts::{Classification, Tracker},
+    error::AttemptTimedOut,
+    timeout::AttemptTimeout,
+    Policy, Retry,
+};
 use futures_core::ready;
 use pin_project::{pin_project, project};
 use std::future::Future;
 use std::pin::Pin;
 use std::task::{Context, Poll};
+use tokio::time::Delay;
 use tower_service::Service;
 
 /// The `Future` returned by a `Retry` service.
@@ -21,6 +27,31 @@ where
     retry: Retry<P, S>,
     #[pin]
     state: State<S::Future, P::Future>,
+    #[pin]
+    deadline: Option<Deadline<S::Error>>,
+    tracker: Tracker,
+}
+
+/// The `Future` returned by a `TimeoutRetry` service.
+pub type TimeoutResponseFuture<P, S, Request> = ResponseFuture<P, AttemptTimeout<S>, Request>;
+
+/// The overall deadline of a retried request, and the error it fails with once it elapses.
+#[pin_project]
+#[derive(Debug)]
+struct Deadline<E> {
+    #[pin]
+    delay: Delay,
+    error: fn() -> E,
+}
+
+/// The `Future` returned by an `AttemptTimeout` service.
+#[pin_project]
+#[derive(Debug)]
+pub struct AttemptFuture<F> {
+    #[pin]
+    future: F,
+    #[pin]
+    timeout: Option<Delay>,
 }
 
 #[pin_project]
@@ -43,11 +74,23 @@ where
         request: Option<Request>,
         retry: Retry<P, S>,
         future: S::Future,
+        tracker: Tracker,
     ) -> ResponseFuture<P, S, Request> {
         ResponseFuture {
             request,
             retry,
             state: State::Called(future),
+            deadline: None,
+            tracker,
+        }
+    }
+
+    /// Fails the request with `error` once `delay` elapses, including any time spent waiting
+    /// on the policy or for the service to become ready again.
+    pub(crate) fn deadline(self, delay: Delay, error: fn() -> S::Error) -> Self {
+        ResponseFuture {
+            deadline: Some(Deadline { delay, error }),
+            ..self
         }
     }
 }
@@ -59,26 +102,64 @@ where
 {
     type Output = Result<S::Response, S::Error>;
 
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if let Poll::Ready(result) = self.as_mut().poll_attempts(cx) {
+            return Poll::Ready(result);
+        }
+
+        let this = self.project();
+        let error = match this.deadline.as_pin_mut() {
+            Some(deadline) => {
+                let deadline = deadline.project();
+                ready!(deadline.delay.poll(cx));
+                *deadline.error
+            }
+            None => return Poll::Pending,
+        };
+        // The deadline elapsed, so no further attempts are made.
+        let mut result = Err(error());
+        let attempts = this.tracker.finish(Classification::DeadlineExhausted);
+        this.retry.policy.completed(result.as_mut(), attempts);
+        Poll::Ready(result)
+    }
+}
+
+impl<P, S, Request> ResponseFuture<P, S, Request>
+where
+    P: Policy<Request, S::Response, S::Error> + Clone,
+    S: Service<Request> + Clone,
+{
     #[project]
-    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+    fn poll_attempts(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<S::Response, S::Error>> {
         let mut this = self.project();
 
         loop {
             #[project]
             match this.state.as_mut().project() {
                 State::Called(future) => {
-                    let result = ready!(future.poll(cx));
-                    if let Some(ref req) = this.request {
+                    let mut result = {
+                        let _enter = this.tracker.span().enter();
+                        ready!(future.poll(cx))
+                    };
+                    let classification = if let Some(ref req) = this.request {
                         match this.retry.policy.retry(req, result.as_ref()) {
                             Some(checking) => {
+                                this.tracker.retrying(result.is_err());
                                 this.state.set(State::Checking(checking));
+                                continue;
                             }
-                            None => return Poll::Ready(result),
+                            None => Classification::Accepted,
                         }
                     } else {
                         // request wasn't cloned, so no way to retry it
-                        return Poll::Ready(result);
-                    }
+                        Classification::NotCloned
+                    };
+                    let attempts = this.tracker.finish(classification);
+                    this.retry.policy.completed(result.as_mut(), attempts);
+                    return Poll::Ready(result);
                 }
                 State::Checking(future) => {
                     this.retry
@@ -106,6 +187,8 @@ where
                         .take()
                         .expect("retrying requires cloned request");
                     *this.request = this.retry.policy.clone_request(&req);
+                    this.tracker.next_attempt();
+                    let _enter = this.tracker.span().enter();
                     this.state.set(State::Called(
                         this.retry.as_mut().project().service.call(req),
                     ));
@@ -114,3 +197,31 @@ where
         }
     }
 }
+
+impl<F> AttemptFuture<F> {
+    pub(crate) fn new(future: F, timeout: Option<Delay>) -> Self {
+        AttemptFuture { future, timeout }
+    }
+}
+
+impl<F, T, E> Future for AttemptFuture<F>
+where
+    F: Future<Output = Result<T, E>>,
+    E: Into<crate::BoxError>,
+{
+    type Output = Result<T, crate::BoxError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        if let Poll::Ready(result) = this.future.poll(cx) {
+            return Poll::Ready(result.map_err(Into::into));
+        }
+        match this.timeout.as_pin_mut() {
+            Some(timeout) => {
+                ready!(timeout.poll(cx));
+                Poll::Ready(Err(AttemptTimedOut(()).into()))
+            }
+            None => Poll::Pending,
+        }
+    }
+}
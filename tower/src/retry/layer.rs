@@ -1,4 +1,5 @@
-use super::Retry;
+use super::{Retry, TimeoutRetry};
+use std::time::Duration;
 use tower_layer::Layer;
 
 /// Retry requests based on a policy
@@ -25,3 +26,59 @@ where
         Retry::new(policy, service)
     }
 }
+
+/// Retry requests based on a policy, bounded by an attempt timeout and an overall deadline.
+///
+/// See [`TimeoutRetry`] for details.
+#[derive(Debug)]
+pub struct TimeoutRetryLayer<P> {
+    policy: P,
+    attempt_timeout: Option<Duration>,
+    deadline: Option<Duration>,
+}
+
+impl<P> TimeoutRetryLayer<P> {
+    /// Create a new `TimeoutRetryLayer` from a retry policy
+    pub fn new(policy: P) -> Self {
+        TimeoutRetryLayer {
+            policy,
+            attempt_timeout: None,
+            deadline: None,
+        }
+    }
+
+    /// Fail a single attempt if it does not complete within `timeout`.
+    pub fn attempt_timeout(self, timeout: Duration) -> Self {
+        TimeoutRetryLayer {
+            attempt_timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Fail the request if it does not complete within `deadline`, including all retries and the
+    /// time spent waiting between them.
+    pub fn deadline(self, deadline: Duration) -> Self {
+        TimeoutRetryLayer {
+            deadline: Some(deadline),
+            ..self
+        }
+    }
+}
+
+impl<P, S> Layer<S> for TimeoutRetryLayer<P>
+where
+    P: Clone,
+{
+    type Service = TimeoutRetry<P, S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        let mut retry = TimeoutRetry::new(self.policy.clone(), service);
+        if let Some(timeout) = self.attempt_timeout {
+            retry = retry.attempt_timeout(timeout);
+        }
+        if let Some(deadline) = self.deadline {
+            retry = retry.deadline(deadline);
+        }
+        retry
+    }
+}
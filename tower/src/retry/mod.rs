@@ -1,13 +1,21 @@
 //! Tower middleware for retrying "failed" requests.
 
+mod attempts;
+pub mod backoff;
 pub mod budget;
+pub mod error;
 pub mod future;
 mod layer;
 mod policy;
+pub mod replay;
+mod timeout;
 
-pub use self::layer::RetryLayer;
+pub use self::attempts::{Attempts, Classification};
+pub use self::layer::{RetryLayer, TimeoutRetryLayer};
 pub use self::policy::Policy;
+pub use self::timeout::{AttemptTimeout, TimeoutRetry};
 
+use self::attempts::Tracker;
 use self::future::ResponseFuture;
 use pin_project::pin_project;
 use std::task::{Context, Poll};
@@ -51,8 +59,12 @@ where
 
     fn call(&mut self, request: Request) -> Self::Future {
         let cloned = self.policy.clone_request(&request);
-        let future = self.service.call(request);
+        let tracker = Tracker::new();
+        let future = {
+            let _enter = tracker.span().enter();
+            self.service.call(request)
+        };
 
-        ResponseFuture::new(cloned, self.clone(), future)
+        ResponseFuture::new(cloned, self.clone(), future, tracker)
     }
 }
@@ -1,3 +1,4 @@
+use super::Attempts;
 use std::future::Future;
 
 /// A "retry policy" to classify if a request should be retried.
@@ -59,4 +60,17 @@ pub trait Policy<Req, Res, E>: Sized {
     ///
     /// If the request cannot be cloned, return `None`.
     fn clone_request(&self, req: &Req) -> Option<Req>;
+    /// Called once a request has completed, with information about the attempts that were made.
+    ///
+    /// This is passed the final result, so it may be used to record metrics (such as the number
+    /// of retries per request), or to attach the [`Attempts`] to the response or error. It is
+    /// called on the policy that was used for the final attempt.
+    ///
+    /// This is not called if the inner service fails while waiting for it to become ready for a
+    /// retry.
+    ///
+    /// The default implementation does nothing.
+    fn completed(&self, result: Result<&mut Res, &mut E>, attempts: &Attempts) {
+        let _ = (result, attempts);
+    }
 }
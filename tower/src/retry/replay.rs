@@ -0,0 +1,362 @@
+//! Replaying streaming request bodies.
+//!
+//! Both [`Policy::clone_request`](super::Policy::clone_request) and
+//! [`hedge::Policy::clone_request`](crate::hedge::Policy::clone_request) need a copy of the
+//! request before it is sent, which is not possible for requests that stream their body. A
+//! [`ReplayBody`] wraps such a body and buffers the chunks read from it, so that copies made with
+//! [`ReplayBody::try_clone`] can replay them.
+//!
+//! Only up to a limited number of bytes are buffered. Once more than that has been read, no
+//! further copies can be made, and a policy should decline to clone the request (which dispatches
+//! it without retrying or hedging).
+//!
+//! # Example
+//!
+//! A retry policy for requests with streaming bodies, which only retries if the whole body could
+//! be buffered:
+//!
+//! ```
+//! use futures_util::{future, stream};
+//! use tower::retry::{replay::ReplayBody, Policy};
+//!
+//! type Body = stream::Iter<std::vec::IntoIter<Result<Vec<u8>, std::io::Error>>>;
+//! type Req = ReplayBody<Body>;
+//! type Res = String;
+//!
+//! #[derive(Clone)]
+//! struct RetryOnce;
+//!
+//! impl<E> Policy<Req, Res, E> for RetryOnce {
+//!     type Future = future::Ready<Self>;
+//!
+//!     fn retry(&self, req: &Req, result: Result<&Res, &E>) -> Option<Self::Future> {
+//!         // The copy cannot be replayed if the original read more than the limit.
+//!         if result.is_err() && !req.is_capped() {
+//!             Some(future::ready(RetryOnce))
+//!         } else {
+//!             None
+//!         }
+//!     }
+//!
+//!     fn clone_request(&self, req: &Req) -> Option<Req> {
+//!         req.try_clone()
+//!     }
+//! }
+//! ```
+//!
+//! A [`hedge::Policy`](crate::hedge::Policy) should similarly check
+//! [`ReplayBody::is_capped`] in `can_retry`, so that a hedge request is not issued once the
+//! original request can no longer be replayed. If the limit is only exceeded after a hedge
+//! request was issued, a hedge request that fails with [`LimitExceeded`] is abandoned, and the
+//! result of the original request is used.
+
+use crate::BoxError;
+use futures_core::Stream;
+use std::{
+    error, fmt,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+use tracing::trace;
+
+/// A stream of chunks which buffers what is read from an inner stream, so that it can be replayed
+/// by copies made with [`try_clone`](ReplayBody::try_clone).
+///
+/// Copies may be read concurrently (as with hedged requests) or one after another (as with
+/// retried requests). Whichever copy is furthest ahead reads from the inner stream; the others
+/// read from the buffer.
+///
+/// If a copy falls behind by more than the buffer limit, it fails with a [`LimitExceeded`] error.
+pub struct ReplayBody<S: Stream> {
+    shared: Arc<Mutex<Shared<S>>>,
+    position: usize,
+    failed: bool,
+}
+
+struct Shared<S: Stream> {
+    inner: Pin<Box<S>>,
+    buffered: Vec<S::Item>,
+    buffered_bytes: usize,
+    // The number of chunks read from `inner`, including any which were not buffered.
+    read: usize,
+    limit: usize,
+    capped: bool,
+    complete: bool,
+    waiters: Vec<Waker>,
+}
+
+/// A [`ReplayBody`] could not be replayed, since more than its limit was read from it.
+#[derive(Debug, Default)]
+pub struct LimitExceeded(pub(super) ());
+
+// ===== impl ReplayBody =====
+
+impl<S: Stream> ReplayBody<S> {
+    /// Wrap `body`, buffering up to `limit` bytes of it.
+    pub fn new(body: S, limit: usize) -> Self {
+        ReplayBody {
+            shared: Arc::new(Mutex::new(Shared {
+                inner: Box::pin(body),
+                buffered: Vec::new(),
+                buffered_bytes: 0,
+                read: 0,
+                limit,
+                capped: false,
+                complete: false,
+                waiters: Vec::new(),
+            })),
+            position: 0,
+            failed: false,
+        }
+    }
+
+    /// Make a copy of this body which replays it from the start.
+    ///
+    /// Returns `None` if more than the limit has already been read, in which case the body can
+    /// no longer be replayed.
+    pub fn try_clone(&self) -> Option<Self> {
+        if self.is_capped() {
+            return None;
+        }
+        Some(ReplayBody {
+            shared: self.shared.clone(),
+            position: 0,
+            failed: false,
+        })
+    }
+
+    /// Returns `true` if more than the limit has been read, so that the body can no longer be
+    /// replayed.
+    pub fn is_capped(&self) -> bool {
+        self.shared.lock().unwrap().capped
+    }
+}
+
+impl<S, T, E> Stream for ReplayBody<S>
+where
+    S: Stream<Item = Result<T, E>>,
+    T: AsRef<[u8]> + Clone,
+    E: Into<BoxError>,
+{
+    type Item = Result<T, BoxError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        // `ReplayBody` is `Unpin`, since the inner stream is boxed.
+        let this = self.get_mut();
+        if this.failed {
+            return Poll::Ready(None);
+        }
+        let mut shared = this.shared.lock().unwrap();
+
+        if this.position < shared.read {
+            // Another copy has already read this chunk from the inner stream.
+            return match shared.buffered.get(this.position) {
+                Some(Ok(chunk)) if !shared.capped => {
+                    this.position += 1;
+                    Poll::Ready(Some(Ok(chunk.clone())))
+                }
+                _ => {
+                    this.failed = true;
+                    Poll::Ready(Some(Err(LimitExceeded::new().into())))
+                }
+            };
+        }
+
+        if shared.complete {
+            return Poll::Ready(None);
+        }
+
+        let item = match shared.inner.as_mut().poll_next(cx) {
+            Poll::Pending => {
+                // The inner stream only wakes the copy which polled it last, so the others are
+                // woken once a chunk is read.
+                if !shared.waiters.iter().any(|w| w.will_wake(cx.waker())) {
+                    shared.waiters.push(cx.waker().clone());
+                }
+                return Poll::Pending;
+            }
+            Poll::Ready(item) => item,
+        };
+        for waiter in shared.waiters.drain(..) {
+            waiter.wake();
+        }
+
+        match item {
+            None => {
+                shared.complete = true;
+                Poll::Ready(None)
+            }
+            Some(Err(e)) => {
+                // Errors cannot be replayed.
+                shared.capped = true;
+                shared.read += 1;
+                this.position += 1;
+                Poll::Ready(Some(Err(e.into())))
+            }
+            Some(Ok(chunk)) => {
+                shared.read += 1;
+                this.position += 1;
+                if !shared.capped {
+                    shared.buffered_bytes += chunk.as_ref().len();
+                    if shared.buffered_bytes > shared.limit {
+                        trace!(limit = shared.limit, "replay buffer limit exceeded");
+                        shared.capped = true;
+                        shared.buffered = Vec::new();
+                    } else {
+                        shared.buffered.push(Ok(chunk.clone()));
+                    }
+                }
+                Poll::Ready(Some(Ok(chunk)))
+            }
+        }
+    }
+}
+
+impl<S: Stream> Drop for ReplayBody<S> {
+    fn drop(&mut self) {
+        // If this copy was the last to poll the inner stream, it is the only
+        // one the inner stream would wake, so the waiting copies are woken to
+        // poll it themselves.
+        if let Ok(mut shared) = self.shared.lock() {
+            for waiter in shared.waiters.drain(..) {
+                waiter.wake();
+            }
+        }
+    }
+}
+
+impl<S: Stream> fmt::Debug for ReplayBody<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let shared = self.shared.lock().unwrap();
+        f.debug_struct("ReplayBody")
+            .field("position", &self.position)
+            .field("failed", &self.failed)
+            .field("buffered_bytes", &shared.buffered_bytes)
+            .field("limit", &shared.limit)
+            .field("capped", &shared.capped)
+            .field("complete", &shared.complete)
+            .finish()
+    }
+}
+
+// ===== impl LimitExceeded =====
+
+impl LimitExceeded {
+    /// Construct a new replay limit exceeded error
+    pub fn new() -> Self {
+        LimitExceeded(())
+    }
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("replay buffer limit exceeded")
+    }
+}
+
+impl error::Error for LimitExceeded {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::stream;
+    use tokio_test::{assert_pending, assert_ready, task};
+
+    type Chunks = stream::Iter<std::vec::IntoIter<Result<&'static str, BoxError>>>;
+
+    fn body(chunks: &[&'static str], limit: usize) -> ReplayBody<Chunks> {
+        let chunks = chunks.iter().map(|c| Ok(*c)).collect::<Vec<_>>();
+        ReplayBody::new(stream::iter(chunks), limit)
+    }
+
+    fn read_all<S>(body: S) -> Vec<Result<&'static str, String>>
+    where
+        S: Stream<Item = Result<&'static str, BoxError>>,
+    {
+        let mut body = task::spawn(body);
+        let mut chunks = Vec::new();
+        while let Some(chunk) = assert_ready!(body.poll_next()) {
+            chunks.push(chunk.map_err(|e| e.to_string()));
+        }
+        chunks
+    }
+
+    #[test]
+    fn replays_sequentially() {
+        let original = body(&["hello", " ", "world"], 16);
+        let copy = original.try_clone().unwrap();
+
+        assert_eq!(read_all(original), vec![Ok("hello"), Ok(" "), Ok("world")]);
+        assert_eq!(read_all(copy), vec![Ok("hello"), Ok(" "), Ok("world")]);
+    }
+
+    #[test]
+    fn replays_concurrently() {
+        let mut original = task::spawn(body(&["a", "b"], 16));
+        let mut copy = task::spawn(original.try_clone().unwrap());
+
+        assert_eq!(assert_ready!(original.poll_next()).unwrap().unwrap(), "a");
+        assert_eq!(assert_ready!(copy.poll_next()).unwrap().unwrap(), "a");
+        assert_eq!(assert_ready!(copy.poll_next()).unwrap().unwrap(), "b");
+        assert!(assert_ready!(copy.poll_next()).is_none());
+        assert_eq!(assert_ready!(original.poll_next()).unwrap().unwrap(), "b");
+        assert!(assert_ready!(original.poll_next()).is_none());
+    }
+
+    #[test]
+    fn wakes_waiting_copies() {
+        let (mut tx, rx) = tokio::sync::mpsc::channel::<Result<&'static str, BoxError>>(1);
+        let mut original = task::spawn(ReplayBody::new(rx, 16));
+        let mut copy = task::spawn(original.try_clone().unwrap());
+
+        assert_pending!(copy.poll_next());
+        assert_pending!(original.poll_next());
+        tx.try_send(Ok("a")).unwrap();
+
+        assert_eq!(assert_ready!(original.poll_next()).unwrap().unwrap(), "a");
+        assert!(copy.is_woken());
+        assert_eq!(assert_ready!(copy.poll_next()).unwrap().unwrap(), "a");
+    }
+
+    #[test]
+    fn wakes_waiting_copies_when_dropped() {
+        let (mut tx, rx) = tokio::sync::mpsc::channel::<Result<&'static str, BoxError>>(1);
+        let mut original = task::spawn(ReplayBody::new(rx, 16));
+        let mut copy = task::spawn(original.try_clone().unwrap());
+
+        // The original polls the inner stream last, so only it would be woken.
+        assert_pending!(copy.poll_next());
+        assert_pending!(original.poll_next());
+        drop(original);
+        assert!(copy.is_woken());
+
+        assert_pending!(copy.poll_next());
+        tx.try_send(Ok("a")).unwrap();
+        assert!(copy.is_woken());
+        assert_eq!(assert_ready!(copy.poll_next()).unwrap().unwrap(), "a");
+    }
+
+    #[test]
+    fn limit_exceeded() {
+        let mut original = task::spawn(body(&["hello", "world"], 8));
+        let copy = original.try_clone().unwrap();
+
+        assert_eq!(
+            assert_ready!(original.poll_next()).unwrap().unwrap(),
+            "hello"
+        );
+        assert!(original.try_clone().is_some());
+        assert_eq!(
+            assert_ready!(original.poll_next()).unwrap().unwrap(),
+            "world"
+        );
+        assert!(original.is_capped());
+        assert!(original.try_clone().is_none());
+
+        assert_eq!(
+            read_all(copy),
+            vec![Err("replay buffer limit exceeded".to_string())]
+        );
+    }
+}
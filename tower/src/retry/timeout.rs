@@ -0,0 +1,114 @@
+use super::{
+    error::DeadlineExhausted,
+    future::{AttemptFuture, TimeoutResponseFuture},
+    Policy, Retry,
+};
+use std::{
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::time::delay_for;
+use tower_service::Service;
+
+/// Configure retrying requests of "failed" responses, bounded by timeouts.
+///
+/// This behaves like [`Retry`](super::Retry), but in addition each attempt may be bounded by an
+/// attempt timeout, and the request as a whole (including any time spent waiting for the future
+/// returned by [`Policy::retry`]) may be bounded by a deadline.
+///
+/// Errors from the inner service are converted into [`BoxError`](crate::BoxError)s before they
+/// are passed to the `Policy`. An attempt that times out is passed to the `Policy` as an
+/// [`AttemptTimedOut`](super::error::AttemptTimedOut) error, so that it can decide whether to
+/// retry it. If the deadline elapses, the request fails with a
+/// [`DeadlineExhausted`](super::error::DeadlineExhausted) error, and no further attempts are made.
+#[derive(Clone, Debug)]
+pub struct TimeoutRetry<P, S> {
+    retry: Retry<P, AttemptTimeout<S>>,
+    deadline: Option<Duration>,
+}
+
+/// Bounds each attempt of a `TimeoutRetry` by its attempt timeout, and converts its errors into
+/// [`BoxError`](crate::BoxError)s.
+#[derive(Clone, Debug)]
+pub struct AttemptTimeout<S> {
+    service: S,
+    timeout: Option<Duration>,
+}
+
+// ===== impl TimeoutRetry =====
+
+impl<P, S> TimeoutRetry<P, S> {
+    /// Retry the inner service depending on this `Policy`.
+    ///
+    /// No timeouts are applied until they are configured with
+    /// [`attempt_timeout`](TimeoutRetry::attempt_timeout) and
+    /// [`deadline`](TimeoutRetry::deadline).
+    pub fn new(policy: P, service: S) -> Self {
+        let service = AttemptTimeout {
+            service,
+            timeout: None,
+        };
+        TimeoutRetry {
+            retry: Retry::new(policy, service),
+            deadline: None,
+        }
+    }
+
+    /// Fail a single attempt if it does not complete within `timeout`.
+    pub fn attempt_timeout(mut self, timeout: Duration) -> Self {
+        self.retry.service.timeout = Some(timeout);
+        self
+    }
+
+    /// Fail the request if it does not complete within `deadline`, including all retries and the
+    /// time spent waiting between them.
+    pub fn deadline(self, deadline: Duration) -> Self {
+        TimeoutRetry {
+            deadline: Some(deadline),
+            ..self
+        }
+    }
+}
+
+impl<P, S, Request> Service<Request> for TimeoutRetry<P, S>
+where
+    P: Policy<Request, S::Response, crate::BoxError> + Clone,
+    S: Service<Request> + Clone,
+    S::Error: Into<crate::BoxError>,
+{
+    type Response = S::Response;
+    type Error = crate::BoxError;
+    type Future = TimeoutResponseFuture<P, S, Request>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.retry.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let future = self.retry.call(request);
+        match self.deadline {
+            Some(deadline) => future.deadline(delay_for(deadline), || DeadlineExhausted(()).into()),
+            None => future,
+        }
+    }
+}
+
+// ===== impl AttemptTimeout =====
+
+impl<S, Request> Service<Request> for AttemptTimeout<S>
+where
+    S: Service<Request>,
+    S::Error: Into<crate::BoxError>,
+{
+    type Response = S::Response;
+    type Error = crate::BoxError;
+    type Future = AttemptFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        AttemptFuture::new(self.service.call(request), self.timeout.map(delay_for))
+    }
+}
@@ -40,21 +40,29 @@
 //!     }
 //! }
 //! ```
+//!
+//! Services can also be given metadata, such as the shards they hold, with
+//! [`Steer::with_metadata`]. The metadata of each service is passed to the [`Picker`], and can be
+//! updated with [`Steer::update`], for instance as it is discovered.
 use std::collections::VecDeque;
 use std::task::{Context, Poll};
 use tower_service::Service;
 
 /// This is how callers of [`Steer`] tell it which `Service` a `Req` corresponds to.
-pub trait Picker<S, Req> {
+///
+/// Closures that take the request and the services are pickers that ignore the metadata.
+pub trait Picker<S, Req, M = ()> {
     /// Return an index into the iterator of `Service` passed to [`Steer::new`].
-    fn pick(&mut self, r: &Req, services: &[S]) -> usize;
+    ///
+    /// `metadata` holds the latest metadata of each service, at the same index.
+    fn pick(&mut self, r: &Req, services: &[S], metadata: &[M]) -> usize;
 }
 
-impl<S, F, Req> Picker<S, Req> for F
+impl<S, F, Req, M> Picker<S, Req, M> for F
 where
     F: Fn(&Req, &[S]) -> usize,
 {
-    fn pick(&mut self, r: &Req, services: &[S]) -> usize {
+    fn pick(&mut self, r: &Req, services: &[S], _: &[M]) -> usize {
         self(r, services)
     }
 }
@@ -75,9 +83,10 @@ where
 /// component service with a `tower-buffer` with a high enough limit (the maximum number of
 /// concurrent requests) will prevent head-of-line blocking in `Steer`.
 #[derive(Debug)]
-pub struct Steer<S, F, Req> {
+pub struct Steer<S, F, Req, M = ()> {
     router: F,
     services: Vec<S>,
+    metadata: Vec<M>,
     not_ready: VecDeque<usize>,
     _phantom: std::marker::PhantomData<Req>,
 }
@@ -87,21 +96,40 @@ impl<S, F, Req> Steer<S, F, Req> {
     ///
     /// Note: the order of the `Service`s is significant for [`Picker::pick`]'s return value.
     pub fn new(services: impl IntoIterator<Item = S>, router: F) -> Self {
-        let services: Vec<_> = services.into_iter().collect();
+        Self::with_metadata(services.into_iter().map(|s| (s, ())), router)
+    }
+}
+
+impl<S, F, Req, M> Steer<S, F, Req, M> {
+    /// Make a new [`Steer`] with a list of `Service`s and their metadata, and a `Picker`.
+    ///
+    /// Note: the order of the `Service`s is significant for [`Picker::pick`]'s return value.
+    pub fn with_metadata(services: impl IntoIterator<Item = (S, M)>, router: F) -> Self {
+        let (services, metadata): (Vec<_>, Vec<_>) = services.into_iter().unzip();
         let not_ready: VecDeque<_> = services.iter().enumerate().map(|(i, _)| i).collect();
         Self {
             router,
             services,
+            metadata,
             not_ready,
             _phantom: Default::default(),
         }
     }
+
+    /// Replaces the metadata of the `Service` at `index`.
+    ///
+    /// # Panics
+    ///
+    /// If there is no `Service` at `index`.
+    pub fn update(&mut self, index: usize, meta: M) {
+        self.metadata[index] = meta;
+    }
 }
 
-impl<S, Req, F> Service<Req> for Steer<S, F, Req>
+impl<S, Req, F, M> Service<Req> for Steer<S, F, Req, M>
 where
     S: Service<Req>,
-    F: Picker<S, Req>,
+    F: Picker<S, Req, M>,
 {
     type Response = S::Response;
     type Error = S::Error;
@@ -129,7 +157,9 @@ where
             "Steer must wait for all services to be ready. Did you forget to call poll_ready()?"
         );
 
-        let idx = self.router.pick(&req, &self.services[..]);
+        let idx = self
+            .router
+            .pick(&req, &self.services[..], &self.metadata[..]);
         let cl = &mut self.services[idx];
         self.not_ready.push_back(idx);
         cl.call(req)
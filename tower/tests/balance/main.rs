@@ -33,7 +33,8 @@ impl tower::load::Load for Mock {
 #[test]
 fn stress() {
     let mut task = task::spawn(());
-    let (tx, rx) = tokio::sync::mpsc::unbounded_channel::<Result<_, &'static str>>();
+    let (tx, rx) =
+        tokio::sync::mpsc::unbounded_channel::<Result<Change<usize, Mock>, &'static str>>();
     let mut cache = Balance::<_, Req>::new(rx);
 
     let mut nready = 0;
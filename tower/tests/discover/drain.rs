@@ -0,0 +1,130 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::{sync::mpsc, time};
+use tokio_test::{assert_pending, assert_ready, task};
+use tower::discover::{drain::Drained, Change, Drain};
+use tower_service::Service;
+use tower_test::{assert_request_eq, mock};
+
+type Mock = mock::Mock<&'static str, &'static str>;
+type Handle = mock::Handle<&'static str, &'static str>;
+
+/// Records when the service is dropped.
+#[derive(Debug)]
+struct Tracked(Mock, Arc<AtomicBool>);
+
+impl Service<&'static str> for Tracked {
+    type Response = &'static str;
+    type Error = tower::BoxError;
+    type Future = mock::future::ResponseFuture<&'static str>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.0.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: &'static str) -> Self::Future {
+        self.0.call(req)
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        self.1.store(true, Ordering::SeqCst);
+    }
+}
+
+type Tx = mpsc::UnboundedSender<Result<Change<&'static str, Tracked>, &'static str>>;
+type Disco = Drain<mpsc::UnboundedReceiver<Result<Change<&'static str, Tracked>, &'static str>>>;
+
+fn new_drain() -> (task::Spawn<Disco>, Tx) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    (task::spawn(Drain::new(rx, Duration::from_secs(10))), tx)
+}
+
+fn discover(
+    disco: &mut task::Spawn<Disco>,
+    tx: &Tx,
+) -> (mock::Spawn<Drained<Tracked>>, Handle, Arc<AtomicBool>) {
+    let (mock, mut handle) = mock::pair();
+    handle.allow(100);
+    let dropped = Arc::new(AtomicBool::new(false));
+    tx.send(Ok(Change::Insert("a", Tracked(mock, dropped.clone()))))
+        .unwrap();
+    match assert_ready!(disco.poll_next()) {
+        Some(Ok(Change::Insert("a", svc))) => (mock::Spawn::new(svc), handle, dropped),
+        _ => panic!("expected insert"),
+    }
+}
+
+#[tokio::test]
+async fn keeps_service_until_responses_complete() {
+    let (mut disco, tx) = new_drain();
+    let (mut svc, mut handle, dropped) = discover(&mut disco, &tx);
+
+    assert!(svc.poll_ready().is_ready());
+    let mut fut = task::spawn(svc.call("req"));
+    let req = assert_request_eq!(handle, "req");
+    assert_eq!(svc.get_ref().in_flight(), 1);
+
+    // The balancer drops the removed service while the request is in flight.
+    drop(svc);
+    assert!(!dropped.load(Ordering::SeqCst));
+    assert_eq!(disco.draining(), 1);
+    assert_pending!(disco.poll_next());
+
+    req.send_response("rsp");
+    assert_eq!(assert_ready!(fut.poll()).unwrap(), "rsp");
+    drop(fut);
+    assert!(disco.is_woken());
+    assert_pending!(disco.poll_next());
+    assert!(dropped.load(Ordering::SeqCst));
+    assert_eq!(disco.draining(), 0);
+}
+
+#[tokio::test]
+async fn releases_service_after_timeout() {
+    time::pause();
+
+    let (mut disco, tx) = new_drain();
+    let (mut svc, mut handle, dropped) = discover(&mut disco, &tx);
+
+    assert!(svc.poll_ready().is_ready());
+    let _fut = svc.call("req");
+    let _req = assert_request_eq!(handle, "req");
+    drop(svc);
+    assert_pending!(disco.poll_next());
+    assert!(!dropped.load(Ordering::SeqCst));
+
+    time::advance(Duration::from_millis(10_001)).await;
+    assert!(disco.is_woken());
+    assert_pending!(disco.poll_next());
+    assert!(dropped.load(Ordering::SeqCst));
+}
+
+#[tokio::test]
+async fn idle_services_are_dropped_immediately() {
+    let (mut disco, tx) = new_drain();
+    let (svc, _handle, dropped) = discover(&mut disco, &tx);
+
+    drop(svc);
+    assert!(dropped.load(Ordering::SeqCst));
+    assert_eq!(disco.draining(), 0);
+}
+
+#[tokio::test]
+async fn responses_of_live_services_do_not_wake() {
+    let (mut disco, tx) = new_drain();
+    let (mut svc, mut handle, _dropped) = discover(&mut disco, &tx);
+    assert_pending!(disco.poll_next());
+
+    assert!(svc.poll_ready().is_ready());
+    let mut fut = task::spawn(svc.call("req"));
+    assert_request_eq!(handle, "req").send_response("rsp");
+    assert_eq!(assert_ready!(fut.poll()).unwrap(), "rsp");
+    drop(fut);
+    assert!(!disco.is_woken());
+}
@@ -0,0 +1,119 @@
+use std::task::Poll;
+use tokio_test::{assert_pending, assert_ready, task};
+use tower::discover::dynamic::{Batch, Dynamic};
+use tower::discover::Change;
+
+/// Returns all of the changes that are ready, as `(key, Some(service))` for insertions and
+/// `(key, None)` for removals.
+fn ready_changes(
+    discover: &mut task::Spawn<Dynamic<&'static str, u32>>,
+) -> Vec<(&'static str, Option<u32>)> {
+    let mut changes = Vec::new();
+    while let Poll::Ready(Some(change)) = discover.poll_next() {
+        match change.unwrap() {
+            Change::Insert(key, svc) => changes.push((key, Some(svc))),
+            Change::Remove(key) => changes.push((key, None)),
+            Change::Update(..) => unreachable!("no metadata is sent"),
+        }
+    }
+    changes
+}
+
+#[test]
+fn yields_updates_in_order() {
+    let (discover, handle) = Dynamic::new();
+    let mut discover = task::spawn(discover);
+    assert_pending!(discover.poll_next());
+
+    handle.insert("a", 1).unwrap();
+    handle.insert("b", 2).unwrap();
+    assert!(discover.is_woken());
+    assert_eq!(
+        ready_changes(&mut discover),
+        vec![("a", Some(1)), ("b", Some(2))]
+    );
+
+    handle.remove("a").unwrap();
+    handle.replace("b", 3).unwrap();
+    assert_eq!(
+        ready_changes(&mut discover),
+        vec![("a", None), ("b", None), ("b", Some(3))]
+    );
+    assert_pending!(discover.poll_next());
+}
+
+#[test]
+fn applies_batches_together() {
+    let (discover, handle) = Dynamic::new();
+    let mut discover = task::spawn(discover);
+
+    let mut batch = Batch::new();
+    batch.insert("a", 1).insert("b", 2).remove("c");
+    assert_eq!(batch.len(), 3);
+    handle.send(batch).unwrap();
+
+    // an empty batch is not sent
+    handle.send(Batch::new()).unwrap();
+
+    assert_eq!(
+        ready_changes(&mut discover),
+        vec![("a", Some(1)), ("b", Some(2)), ("c", None)]
+    );
+    assert!(!discover.is_woken());
+}
+
+#[test]
+fn ends_when_handles_are_dropped() {
+    let (discover, handle) = Dynamic::new();
+    let mut discover = task::spawn(discover);
+
+    let other = handle.clone();
+    handle.insert("a", 1).unwrap();
+    drop(handle);
+    other.insert("b", 2).unwrap();
+    drop(other);
+
+    assert_eq!(
+        ready_changes(&mut discover),
+        vec![("a", Some(1)), ("b", Some(2))]
+    );
+    assert!(assert_ready!(discover.poll_next()).is_none());
+}
+
+#[test]
+fn updates_fail_once_dropped() {
+    let (discover, handle) = Dynamic::<&str, u32>::new();
+    drop(discover);
+    assert!(handle.insert("a", 1).is_err());
+    assert!(handle.remove("a").is_err());
+}
+
+#[test]
+fn updates_metadata() {
+    let (discover, handle) = Dynamic::<&str, u32, &str>::with_metadata();
+    let mut discover = task::spawn(discover);
+
+    handle.insert("a", 1).unwrap();
+    handle.update("a", "zone-1").unwrap();
+    let mut batch = Batch::new();
+    batch.insert("b", 2).update("b", "zone-2");
+    handle.send(batch).unwrap();
+
+    let mut changes = Vec::new();
+    while let Poll::Ready(Some(change)) = discover.poll_next() {
+        match change.unwrap() {
+            Change::Insert(key, svc) => changes.push(format!("insert {} {}", key, svc)),
+            Change::Remove(key) => changes.push(format!("remove {}", key)),
+            Change::Update(key, meta) => changes.push(format!("update {} {}", key, meta)),
+        }
+    }
+    assert_eq!(
+        changes,
+        vec![
+            "insert a 1",
+            "update a zone-1",
+            "insert b 2",
+            "update b zone-2"
+        ]
+    );
+}
@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+use std::task::Poll;
+use std::time::Duration;
+use tokio::time;
+use tokio_test::{assert_pending, assert_ready, task};
+use tower::discover::dynamic::{Batch, Dynamic};
+use tower::discover::{Change, Discover, DiscoverExt};
+use tower_layer::Layer;
+
+/// Returns all of the changes that are ready, as `(key, Some(service))` for insertions and
+/// `(key, None)` for removals.
+fn ready_changes<D, K, S>(discover: &mut task::Spawn<D>) -> Vec<(K, Option<S>)>
+where
+    D: Discover<Key = K, Service = S> + Unpin,
+    D::Error: std::fmt::Debug,
+{
+    let mut changes = Vec::new();
+    while let Poll::Ready(Some(change)) = discover.enter(|cx, discover| discover.poll_discover(cx))
+    {
+        match change.unwrap() {
+            Change::Insert(key, svc) => changes.push((key, Some(svc))),
+            Change::Remove(key) => changes.push((key, None)),
+            Change::Update(..) => panic!("unexpected metadata update"),
+        }
+    }
+    changes
+}
+
+struct Double;
+
+impl Layer<u32> for Double {
+    type Service = u32;
+
+    fn layer(&self, svc: u32) -> u32 {
+        svc * 2
+    }
+}
+
+#[test]
+fn map_service_and_key() {
+    let (discover, handle) = Dynamic::new();
+    let mut discover = task::spawn(discover.map_service(Double).map_key(|k: u32| k + 100));
+
+    handle.insert(1, 10).unwrap();
+    handle.remove(1).unwrap();
+    assert_eq!(
+        ready_changes(&mut discover),
+        vec![(101, Some(20)), (101, None)]
+    );
+}
+
+#[test]
+fn filter_key() {
+    let (discover, handle) = Dynamic::new();
+    let mut discover = task::spawn(discover.filter_key(|k: &u32| *k > 1));
+
+    let mut batch = Batch::new();
+    batch.insert(1, 1).insert(2, 2).remove(1).remove(2);
+    handle.send(batch).unwrap();
+    assert_eq!(ready_changes(&mut discover), vec![(2, Some(2)), (2, None)]);
+}
+
+#[test]
+fn merge() {
+    let (first, first_handle) = Dynamic::<u32, u32>::new();
+    let (second, second_handle) = Dynamic::new();
+    let mut discover = task::spawn(first.merge(second.map_key(|k: u32| k + 100)));
+
+    first_handle.insert(1, 1).unwrap();
+    second_handle.insert(1, 2).unwrap();
+    let mut changes = ready_changes(&mut discover);
+    changes.sort();
+    assert_eq!(changes, vec![(1, Some(1)), (101, Some(2))]);
+
+    // the merged discover ends once both have ended
+    drop(first_handle);
+    assert_pending!(discover.poll_next());
+    second_handle.remove(1).unwrap();
+    drop(second_handle);
+    assert_eq!(ready_changes(&mut discover), vec![(101, None)]);
+    assert!(assert_ready!(discover.poll_next()).is_none());
+}
+
+#[tokio::test]
+async fn debounce() {
+    time::pause();
+    let (discover, handle) = Dynamic::new();
+    let mut discover = task::spawn(discover.debounce(Duration::from_secs(1)));
+
+    handle.insert(1, 1).unwrap();
+    handle.insert(2, 2).unwrap();
+    assert_pending!(discover.poll_next());
+
+    // a service that flaps is only yielded once it has settled
+    time::advance(Duration::from_millis(500)).await;
+    handle.remove(2).unwrap();
+    handle.insert(2, 3).unwrap();
+    assert_pending!(discover.poll_next());
+    time::advance(Duration::from_millis(501)).await;
+    assert_eq!(ready_changes(&mut discover), vec![(1, Some(1))]);
+    time::advance(Duration::from_millis(500)).await;
+    assert_eq!(ready_changes(&mut discover), vec![(2, Some(3))]);
+
+    // a service that is removed before it settles is never yielded
+    handle.insert(3, 3).unwrap();
+    handle.remove(3).unwrap();
+    handle.remove(1).unwrap();
+    drop(handle);
+    assert_pending!(discover.poll_next());
+    time::advance(Duration::from_millis(1_001)).await;
+    assert_eq!(ready_changes(&mut discover), vec![(1, None)]);
+    assert!(assert_ready!(discover.poll_next()).is_none());
+}
+
+/// Returns the next change, which must be ready, as a string.
+fn next_change<D>(discover: &mut task::Spawn<D>) -> String
+where
+    D: Discover<Key = u32, Service = u32, Meta = &'static str> + Unpin,
+    D::Error: std::fmt::Debug,
+{
+    match assert_ready!(discover.enter(|cx, discover| discover.poll_discover(cx))) {
+        Some(Ok(Change::Insert(key, svc))) => format!("insert {} {}", key, svc),
+        Some(Ok(Change::Remove(key))) => format!("remove {}", key),
+        Some(Ok(Change::Update(key, meta))) => format!("update {} {}", key, meta),
+        other => panic!("unexpected change: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn debounce_metadata() {
+    time::pause();
+    let (discover, handle) = Dynamic::<u32, u32, &str>::with_metadata();
+    let mut discover = task::spawn(discover.debounce(Duration::from_secs(1)));
+
+    // the metadata of a service that hasn't settled is yielded right after it
+    handle.insert(1, 1).unwrap();
+    handle.update(1, "a").unwrap();
+    assert_pending!(discover.poll_next());
+    time::advance(Duration::from_millis(1_001)).await;
+    assert_eq!(next_change(&mut discover), "insert 1 1");
+    assert_eq!(next_change(&mut discover), "update 1 a");
+
+    // the metadata of a settled service is yielded immediately
+    handle.update(1, "b").unwrap();
+    assert_eq!(next_change(&mut discover), "update 1 b");
+
+    // metadata discovered after a service was removed doesn't apply to its replacement
+    handle.remove(1).unwrap();
+    handle.update(1, "c").unwrap();
+    handle.insert(1, 2).unwrap();
+    assert_pending!(discover.poll_next());
+    time::advance(Duration::from_millis(1_001)).await;
+    assert_eq!(next_change(&mut discover), "insert 1 2");
+    assert_pending!(discover.poll_next());
+
+    // the metadata of a service that is being removed is dropped with it
+    handle.remove(1).unwrap();
+    handle.update(1, "d").unwrap();
+    drop(handle);
+    assert_pending!(discover.poll_next());
+    time::advance(Duration::from_millis(1_001)).await;
+    assert_eq!(next_change(&mut discover), "remove 1");
+    assert!(assert_ready!(discover.poll_next()).is_none());
+}
+
+#[test]
+fn subset() {
+    let (discover, handle) = Dynamic::new();
+    let mut discover = task::spawn(discover.subset("client", 5));
+    let mut selected = HashMap::new();
+    let mut apply = |discover: &mut task::Spawn<_>| {
+        for (key, svc) in ready_changes(discover) {
+            match svc {
+                Some(svc) => selected.insert(key, svc),
+                None => selected.remove(&key),
+            };
+        }
+        let mut keys: Vec<u32> = selected.keys().copied().collect();
+        keys.sort();
+        keys
+    };
+
+    // the same services are selected as by `Subset`
+    let mut batch = Batch::new();
+    for i in 0..50 {
+        batch.insert(i, i);
+    }
+    handle.send(batch).unwrap();
+    assert_eq!(apply(&mut discover), vec![8, 12, 13, 17, 24]);
+    assert_eq!(discover.len(), 5);
+
+    // removing an unselected service doesn't change the subset
+    handle.remove(0).unwrap();
+    assert_eq!(ready_changes(&mut discover), vec![]);
+
+    // a removed service is replaced by the highest ranked unselected service
+    handle.remove(8).unwrap();
+    let replaced = apply(&mut discover);
+    assert_eq!(replaced.len(), 5);
+    assert!(!replaced.contains(&8));
+
+    // and is selected again once it comes back
+    handle.insert(8, 8).unwrap();
+    assert_eq!(apply(&mut discover), vec![8, 12, 13, 17, 24]);
+
+    // a replaced service keeps its place in the subset
+    handle.insert(12, 100).unwrap();
+    assert_eq!(ready_changes(&mut discover), vec![(12, Some(100))]);
+    handle.insert(1, 100).unwrap();
+    assert_eq!(ready_changes(&mut discover), vec![]);
+}
+
+#[test]
+fn subset_metadata() {
+    let (discover, handle) = Dynamic::<u32, u32, &str>::with_metadata();
+    let mut discover = task::spawn(discover.subset("client", 1));
+
+    handle.insert(0, 0).unwrap();
+    handle.update(0, "a").unwrap();
+    assert_eq!(next_change(&mut discover), "insert 0 0");
+    assert_eq!(next_change(&mut discover), "update 0 a");
+
+    // the metadata of an unknown service is ignored
+    handle.update(8, "x").unwrap();
+    assert_pending!(discover.poll_next());
+
+    // 8 ranks higher than 0 for this client, and displaces it
+    handle.insert(8, 8).unwrap();
+    assert_eq!(next_change(&mut discover), "remove 0");
+    assert_eq!(next_change(&mut discover), "insert 8 8");
+    assert_pending!(discover.poll_next());
+
+    // the metadata of an unselected service is yielded once it is selected
+    handle.update(0, "b").unwrap();
+    assert_pending!(discover.poll_next());
+    handle.remove(8).unwrap();
+    assert_eq!(next_change(&mut discover), "remove 8");
+    assert_eq!(next_change(&mut discover), "insert 0 0");
+    assert_eq!(next_change(&mut discover), "update 0 b");
+    assert_pending!(discover.poll_next());
+}
@@ -0,0 +1,194 @@
+#![cfg(feature = "discover")]
+
+mod drain;
+mod dynamic;
+mod ext;
+#[cfg(feature = "make")]
+mod resolve;
+#[cfg(feature = "make")]
+mod subset;
+#[cfg(feature = "make")]
+mod watch;
+
+use std::time::Duration;
+use tokio::{sync::mpsc, time};
+use tokio_test::{assert_pending, assert_ready, task};
+use tower::discover::{Change, HealthCheck};
+use tower_test::{assert_request_eq, mock};
+
+type Mock = mock::Mock<&'static str, &'static str>;
+type Handle = mock::Handle<&'static str, &'static str>;
+type Tx = mpsc::UnboundedSender<Result<Change<&'static str, Mock>, &'static str>>;
+type Disco = HealthCheck<
+    mpsc::UnboundedReceiver<Result<Change<&'static str, Mock>, &'static str>>,
+    &'static str,
+>;
+
+fn new_health_check() -> (Disco, Tx) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let disco = HealthCheck::new(rx, "health", Duration::from_secs(10))
+        .timeout(Duration::from_secs(1))
+        .unhealthy_threshold(2);
+    (disco, tx)
+}
+
+fn new_mock() -> (Mock, Handle) {
+    let (mock, mut handle) = mock::pair();
+    handle.allow(100);
+    (mock, handle)
+}
+
+macro_rules! assert_change {
+    ($disco:expr, $change:ident($key:expr)) => {
+        match assert_ready!($disco.poll_next()) {
+            Some(Ok(Change::$change(key, ..))) => assert_eq!(key, $key),
+            change => panic!("unexpected change: {:?}", change.map(|c| c.is_ok())),
+        }
+    };
+}
+
+#[tokio::test]
+async fn inserts_once_healthy() {
+    time::pause();
+
+    let (disco, tx) = new_health_check();
+    let mut disco = task::spawn(disco);
+    let (mock, mut handle) = new_mock();
+
+    tx.send(Ok(Change::Insert("a", mock))).unwrap();
+    assert_pending!(disco.poll_next());
+
+    // The service is checked as soon as it is discovered.
+    assert_request_eq!(handle, "health").send_response("ok");
+    assert_change!(disco, Insert("a"));
+    assert_eq!(disco.healthy(), 1);
+    assert_pending!(disco.poll_next());
+}
+
+#[tokio::test]
+async fn removes_after_failures() {
+    time::pause();
+
+    let (disco, tx) = new_health_check();
+    let mut disco = task::spawn(disco);
+    let (mock, mut handle) = new_mock();
+
+    tx.send(Ok(Change::Insert("a", mock))).unwrap();
+    assert_pending!(disco.poll_next());
+    assert_request_eq!(handle, "health").send_response("ok");
+    assert_change!(disco, Insert("a"));
+
+    // A single failure does not remove the service.
+    time::advance(Duration::from_millis(10_001)).await;
+    assert_pending!(disco.poll_next());
+    assert_request_eq!(handle, "health").send_error("failed");
+    assert_pending!(disco.poll_next());
+    assert_eq!(disco.healthy(), 1);
+
+    // A timed out health check counts as a failure.
+    time::advance(Duration::from_millis(10_001)).await;
+    assert_pending!(disco.poll_next());
+    let _req = assert_request_eq!(handle, "health");
+    time::advance(Duration::from_millis(1_001)).await;
+    assert_change!(disco, Remove("a"));
+    assert_eq!(disco.healthy(), 0);
+
+    // The service is inserted again once it recovers.
+    time::advance(Duration::from_millis(10_001)).await;
+    assert_pending!(disco.poll_next());
+    assert_request_eq!(handle, "health").send_response("ok");
+    assert_change!(disco, Insert("a"));
+}
+
+#[tokio::test]
+async fn unhealthy_services_are_never_inserted() {
+    time::pause();
+
+    let (disco, tx) = new_health_check();
+    let mut disco = task::spawn(disco);
+    let (mock, mut handle) = new_mock();
+
+    tx.send(Ok(Change::Insert("a", mock))).unwrap();
+    assert_pending!(disco.poll_next());
+    assert_request_eq!(handle, "health").send_error("failed");
+    assert_pending!(disco.poll_next());
+
+    // Removing a service which was never inserted yields nothing.
+    tx.send(Ok(Change::Remove("a"))).unwrap();
+    assert_pending!(disco.poll_next());
+    assert_eq!(disco.healthy(), 0);
+
+    // The stream ends once discovery ends and no services remain.
+    drop(tx);
+    assert!(assert_ready!(disco.poll_next()).is_none());
+}
+
+#[tokio::test]
+async fn forwards_removals() {
+    time::pause();
+
+    let (disco, tx) = new_health_check();
+    let mut disco = task::spawn(disco);
+    let (mock, mut handle) = new_mock();
+
+    tx.send(Ok(Change::Insert("a", mock))).unwrap();
+    assert_pending!(disco.poll_next());
+    assert_request_eq!(handle, "health").send_response("ok");
+    assert_change!(disco, Insert("a"));
+
+    tx.send(Ok(Change::Remove("a"))).unwrap();
+    assert_change!(disco, Remove("a"));
+    assert_pending!(disco.poll_next());
+}
+
+#[tokio::test]
+async fn replacement_is_inserted_once_healthy() {
+    time::pause();
+
+    let (disco, tx) = new_health_check();
+    let mut disco = task::spawn(disco);
+    let (mock, mut handle) = new_mock();
+
+    tx.send(Ok(Change::Insert("a", mock))).unwrap();
+    assert_pending!(disco.poll_next());
+    assert_request_eq!(handle, "health").send_response("ok");
+    assert_change!(disco, Insert("a"));
+
+    // The replaced service is kept while its replacement is checked.
+    let (mock, mut handle) = new_mock();
+    tx.send(Ok(Change::Insert("a", mock))).unwrap();
+    assert_pending!(disco.poll_next());
+    assert_eq!(disco.healthy(), 1);
+
+    assert_request_eq!(handle, "health").send_response("ok");
+    assert_change!(disco, Insert("a"));
+    assert_pending!(disco.poll_next());
+    assert_eq!(disco.healthy(), 1);
+}
+
+#[tokio::test]
+async fn unhealthy_replacement_is_removed() {
+    time::pause();
+
+    let (disco, tx) = new_health_check();
+    let mut disco = task::spawn(disco);
+    let (mock, mut handle) = new_mock();
+
+    tx.send(Ok(Change::Insert("a", mock))).unwrap();
+    assert_pending!(disco.poll_next());
+    assert_request_eq!(handle, "health").send_response("ok");
+    assert_change!(disco, Insert("a"));
+
+    let (mock, mut handle) = new_mock();
+    tx.send(Ok(Change::Insert("a", mock))).unwrap();
+    assert_pending!(disco.poll_next());
+    assert_request_eq!(handle, "health").send_error("failed");
+    assert_pending!(disco.poll_next());
+
+    // The replaced service is removed once its replacement is unhealthy.
+    time::advance(Duration::from_millis(10_001)).await;
+    assert_pending!(disco.poll_next());
+    assert_request_eq!(handle, "health").send_error("failed");
+    assert_change!(disco, Remove("a"));
+    assert_eq!(disco.healthy(), 0);
+}
@@ -0,0 +1,134 @@
+use futures_util::future;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time;
+use tokio_test::{assert_pending, task};
+use tower::discover::{Change, Resolve};
+use tower::BoxError;
+use tower_service::Service;
+use tower_test::{assert_request_eq, mock};
+
+/// A service that responds with its target.
+#[derive(Debug)]
+struct Echo(&'static str);
+
+impl Service<()> for Echo {
+    type Response = &'static str;
+    type Error = BoxError;
+    type Future = future::Ready<Result<&'static str, BoxError>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), BoxError>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _: ()) -> Self::Future {
+        future::ready(Ok(self.0))
+    }
+}
+
+/// Makes an `Echo` for every target but `"bad"`.
+#[derive(Debug)]
+struct MakeEcho;
+
+impl Service<&'static str> for MakeEcho {
+    type Response = Echo;
+    type Error = BoxError;
+    type Future = future::Ready<Result<Echo, BoxError>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), BoxError>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, target: &'static str) -> Self::Future {
+        if target == "bad" {
+            return future::ready(Err("bad target".into()));
+        }
+        future::ready(Ok(Echo(target)))
+    }
+}
+
+type Resolver = mock::Mock<(), Vec<&'static str>>;
+type Discover = Resolve<Resolver, MakeEcho, &'static str, ()>;
+
+/// Returns all of the changes that are ready, sorted, as `(key, true)` for insertions and
+/// `(key, false)` for removals, and the errors that were yielded.
+fn ready_changes(discover: &mut task::Spawn<Discover>) -> (Vec<(&'static str, bool)>, Vec<String>) {
+    let mut changes = Vec::new();
+    let mut errors = Vec::new();
+    while let Poll::Ready(Some(change)) = discover.poll_next() {
+        match change {
+            Ok(Change::Insert(key, Echo(target))) => {
+                assert_eq!(key, target);
+                changes.push((key, true));
+            }
+            Ok(Change::Remove(key)) => changes.push((key, false)),
+            Ok(Change::Update(..)) => unreachable!("resolved targets have no metadata"),
+            Err(e) => errors.push(e.to_string()),
+        }
+    }
+    changes.sort();
+    (changes, errors)
+}
+
+#[tokio::test]
+async fn diffs_resolved_targets() {
+    time::pause();
+    let (resolver, mut handle) = mock::pair();
+    let mut discover = task::spawn(Resolve::new(resolver, MakeEcho, Duration::from_secs(10)));
+
+    // targets are resolved immediately
+    assert_pending!(discover.poll_next());
+    assert_request_eq!(handle, ()).send_response(vec!["a", "b"]);
+    assert_eq!(
+        ready_changes(&mut discover),
+        (vec![("a", true), ("b", true)], vec![])
+    );
+
+    // and again once the interval has elapsed
+    assert_pending!(handle.poll_request());
+    time::advance(Duration::from_millis(10_001)).await;
+    assert_pending!(discover.poll_next());
+    assert_request_eq!(handle, ()).send_response(vec!["b", "c"]);
+    assert_eq!(
+        ready_changes(&mut discover),
+        (vec![("a", false), ("c", true)], vec![])
+    );
+
+    // unchanged targets yield no changes
+    time::advance(Duration::from_millis(10_001)).await;
+    assert_pending!(discover.poll_next());
+    assert_request_eq!(handle, ()).send_response(vec!["c", "b"]);
+    assert_eq!(ready_changes(&mut discover), (vec![], vec![]));
+}
+
+#[tokio::test]
+async fn errors_keep_the_current_targets() {
+    time::pause();
+    let (resolver, mut handle) = mock::pair();
+    let mut discover = task::spawn(Resolve::new(resolver, MakeEcho, Duration::from_secs(10)));
+
+    assert_pending!(discover.poll_next());
+    assert_request_eq!(handle, ()).send_response(vec!["a", "bad"]);
+    assert_eq!(
+        ready_changes(&mut discover),
+        (vec![("a", true)], vec!["bad target".to_string()])
+    );
+
+    // a failed resolution is yielded as an error, and nothing is removed
+    time::advance(Duration::from_millis(10_001)).await;
+    assert_pending!(discover.poll_next());
+    assert_request_eq!(handle, ()).send_error("lookup failed");
+    assert_eq!(
+        ready_changes(&mut discover),
+        (vec![], vec!["lookup failed".to_string()])
+    );
+
+    // the target whose service could not be made is retried
+    time::advance(Duration::from_millis(10_001)).await;
+    assert_pending!(discover.poll_next());
+    assert_request_eq!(handle, ()).send_response(vec!["a", "bad"]);
+    assert_eq!(
+        ready_changes(&mut discover),
+        (vec![], vec!["bad target".to_string()])
+    );
+}
@@ -0,0 +1,187 @@
+use futures_util::future;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time;
+use tokio_test::{assert_pending, assert_ready_ok, task};
+use tower::discover::{Change, Resolve, Subset};
+use tower::BoxError;
+use tower_service::Service;
+
+/// A resolver that returns the targets it holds.
+#[derive(Clone, Debug, Default)]
+struct Targets(Arc<Mutex<Vec<u32>>>);
+
+impl Targets {
+    fn set(&self, targets: impl IntoIterator<Item = u32>) {
+        *self.0.lock().unwrap() = targets.into_iter().collect();
+    }
+}
+
+impl Service<()> for Targets {
+    type Response = Vec<u32>;
+    type Error = BoxError;
+    type Future = future::Ready<Result<Vec<u32>, BoxError>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), BoxError>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _: ()) -> Self::Future {
+        future::ok(self.0.lock().unwrap().clone())
+    }
+}
+
+/// A service that responds with its target.
+#[derive(Debug)]
+struct Echo(u32);
+
+impl Service<()> for Echo {
+    type Response = u32;
+    type Error = BoxError;
+    type Future = future::Ready<Result<u32, BoxError>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), BoxError>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _: ()) -> Self::Future {
+        future::ok(self.0)
+    }
+}
+
+/// Makes an `Echo` for a target, and records the targets it was called with.
+#[derive(Clone, Debug, Default)]
+struct MakeTarget(Arc<Mutex<Vec<u32>>>);
+
+impl Service<u32> for MakeTarget {
+    type Response = Echo;
+    type Error = BoxError;
+    type Future = future::Ready<Result<Echo, BoxError>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), BoxError>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, target: u32) -> Self::Future {
+        self.0.lock().unwrap().push(target);
+        future::ok(Echo(target))
+    }
+}
+
+/// Returns the subset of `targets` that `client_id` selects.
+fn select(client_id: &str, size: usize, targets: impl IntoIterator<Item = u32>) -> HashSet<u32> {
+    let resolver = Targets::default();
+    resolver.set(targets);
+    let mut subset = Subset::new(resolver, client_id, size);
+    let selected = assert_ready_ok!(task::spawn(subset.call(())).poll());
+    let len = selected.len();
+    let selected: HashSet<u32> = selected.into_iter().collect();
+    assert_eq!(selected.len(), len, "selected a target twice");
+    selected
+}
+
+#[test]
+fn selects_a_stable_subset() {
+    let a = select("client-a", 5, 0..50);
+    assert_eq!(a.len(), 5);
+    assert_eq!(a, select("client-a", 5, 0..50));
+    assert_ne!(a, select("client-b", 5, 0..50));
+}
+
+#[test]
+fn subsets_are_stable_across_builds() {
+    // Clients built with different toolchains must select the same targets.
+    assert_eq!(
+        select("client", 5, 0..50),
+        vec![8, 12, 13, 17, 24].into_iter().collect()
+    );
+}
+
+#[test]
+fn spreads_clients_across_targets() {
+    let mut counts = vec![0; 10];
+    for client in 0..200 {
+        for target in select(&format!("client-{}", client), 3, 0..10) {
+            counts[target as usize] += 1;
+        }
+    }
+    // Each target is expected to be selected by 60 clients.
+    for count in counts {
+        assert!(count > 30 && count < 90, "uneven spread: {}", count);
+    }
+}
+
+#[test]
+fn small_fleets_are_fully_selected() {
+    assert_eq!(select("client", 5, 0..3), (0..3).collect());
+    assert_eq!(select("client", 5, vec![0, 1, 1, 2]), (0..3).collect());
+}
+
+#[test]
+fn inserts_change_the_subset_minimally() {
+    let mut selected = select("client", 5, 0..50);
+    for end in 51..=100 {
+        let before = selected;
+        selected = select("client", 5, 0..end);
+        assert_eq!(selected.len(), 5);
+        // The new target either displaced a single target, or wasn't selected.
+        let added = end - 1;
+        if selected.contains(&added) {
+            assert_eq!(before.difference(&selected).count(), 1);
+        } else {
+            assert_eq!(before, selected);
+        }
+    }
+}
+
+#[test]
+fn removals_change_the_subset_minimally() {
+    let selected = select("client", 5, 0..50);
+
+    let unselected = (0..50).find(|t| !selected.contains(t)).unwrap();
+    let without_unselected = select("client", 5, (0..50).filter(|&t| t != unselected));
+    assert_eq!(selected, without_unselected);
+
+    let removed = *selected.iter().next().unwrap();
+    let without_selected = select("client", 5, (0..50).filter(|&t| t != removed));
+    assert!(!without_selected.contains(&removed));
+    assert_eq!(selected.intersection(&without_selected).count(), 4);
+}
+
+#[tokio::test]
+async fn only_selected_targets_are_made() {
+    time::pause();
+    let resolver = Targets::default();
+    resolver.set(0..50);
+    let make = MakeTarget::default();
+    let subset = Subset::new(resolver.clone(), "client", 5);
+    let mut discover = task::spawn(Resolve::new(subset, make.clone(), Duration::from_secs(10)));
+
+    let mut inserted = HashSet::new();
+    while let Poll::Ready(Some(change)) = discover.poll_next() {
+        match change.unwrap() {
+            Change::Insert(target, Echo(echo)) => {
+                assert_eq!(target, echo);
+                assert!(inserted.insert(target));
+            }
+            change => panic!("unexpected change: {:?}", change),
+        }
+    }
+    assert_eq!(inserted, select("client", 5, 0..50));
+    assert_eq!(make.0.lock().unwrap().len(), 5);
+
+    // a removed target is replaced by making a single service
+    let removed = *inserted.iter().next().unwrap();
+    resolver.set((0..50).filter(|&t| t != removed));
+    time::advance(Duration::from_millis(10_001)).await;
+    let mut changes = Vec::new();
+    while let Poll::Ready(Some(change)) = discover.poll_next() {
+        changes.push(change.unwrap());
+    }
+    assert_eq!(changes.len(), 2);
+    assert!(matches!(changes[0], Change::Remove(t) if t == removed));
+    assert_eq!(make.0.lock().unwrap().len(), 6);
+    assert_pending!(discover.poll_next());
+}
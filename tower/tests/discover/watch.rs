@@ -0,0 +1,190 @@
+use futures_util::future;
+use std::path::{Path, PathBuf};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use std::{fs, thread};
+use tokio::time;
+use tokio_test::{assert_pending, task};
+use tower::discover::watch::{ParseError, WatchFile};
+use tower::discover::{Change, Discover};
+use tower::BoxError;
+use tower_service::Service;
+
+/// A service that responds with its endpoint.
+#[derive(Debug)]
+struct Echo(u16);
+
+impl Service<()> for Echo {
+    type Response = u16;
+    type Error = BoxError;
+    type Future = future::Ready<Result<u16, BoxError>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), BoxError>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _: ()) -> Self::Future {
+        future::ready(Ok(self.0))
+    }
+}
+
+#[derive(Debug)]
+struct MakeEcho;
+
+impl Service<u16> for MakeEcho {
+    type Response = Echo;
+    type Error = BoxError;
+    type Future = future::Ready<Result<Echo, BoxError>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), BoxError>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, endpoint: u16) -> Self::Future {
+        future::ready(Ok(Echo(endpoint)))
+    }
+}
+
+/// Returns a path in the temporary directory that is unique to the test.
+fn temp_path(test: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("tower-watch-{}-{}", std::process::id(), test))
+}
+
+/// Writes `contents` to `path`, ensuring that the file's modification time changes.
+fn write(path: &Path, contents: &str) {
+    let modified = |path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    let before = modified(path);
+    loop {
+        fs::write(path, contents).unwrap();
+        if modified(path) != before {
+            return;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Returns the next `n` changes, sorted, as `(endpoint, true)` for insertions and
+/// `(endpoint, false)` for removals, and the errors that were yielded.
+///
+/// The file is read on the blocking thread pool, so this waits for the discovery to be woken
+/// while it is pending.
+fn next_changes<D>(discover: &mut task::Spawn<D>, n: usize) -> (Vec<(u16, bool)>, Vec<BoxError>)
+where
+    D: Discover<Key = u16, Service = Echo, Error = BoxError> + Unpin,
+{
+    let mut changes = Vec::new();
+    let mut errors = Vec::new();
+    while changes.len() + errors.len() < n {
+        let change = match discover.enter(|cx, discover| discover.poll_discover(cx)) {
+            Poll::Ready(change) => change.expect("discovery ended"),
+            Poll::Pending => {
+                wait_woken(discover);
+                continue;
+            }
+        };
+        match change {
+            Ok(Change::Insert(key, Echo(endpoint))) => {
+                assert_eq!(key, endpoint);
+                changes.push((key, true));
+            }
+            Ok(Change::Remove(key)) => changes.push((key, false)),
+            Ok(Change::Update(..)) => unreachable!("watched endpoints have no metadata"),
+            Err(e) => errors.push(e),
+        }
+    }
+    changes.sort();
+    (changes, errors)
+}
+
+/// Waits for a file that is being read to be read.
+fn wait_woken<D>(discover: &mut task::Spawn<D>) {
+    while !discover.is_woken() {
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+#[tokio::test]
+async fn reloads_modified_file() {
+    time::pause();
+    let path = temp_path("reloads_modified_file");
+    write(&path, "# ports\n1\n\n2\n");
+    let mut discover = task::spawn(WatchFile::new(&path, MakeEcho, Duration::from_secs(10)));
+
+    // the file is read immediately
+    let (changes, errors) = next_changes(&mut discover, 2);
+    assert_eq!(changes, vec![(1, true), (2, true)]);
+    assert!(errors.is_empty());
+
+    // and again once it has been modified
+    write(&path, "2\n3\n");
+    assert_pending!(discover.poll_next());
+    time::advance(Duration::from_millis(10_001)).await;
+    let (changes, errors) = next_changes(&mut discover, 2);
+    assert_eq!(changes, vec![(1, false), (3, true)]);
+    assert!(errors.is_empty());
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn errors_keep_the_current_endpoints() {
+    time::pause();
+    let path = temp_path("errors_keep_the_current_endpoints");
+    write(&path, "1\n2\n");
+    let mut discover = task::spawn(WatchFile::new(&path, MakeEcho, Duration::from_secs(10)));
+    let (changes, _) = next_changes(&mut discover, 2);
+    assert_eq!(changes, vec![(1, true), (2, true)]);
+
+    // an invalid file is reported once
+    write(&path, "1\nfoo\n");
+    time::advance(Duration::from_millis(10_001)).await;
+    let (changes, errors) = next_changes(&mut discover, 1);
+    assert!(changes.is_empty());
+    assert_eq!(errors.len(), 1);
+    let error = errors[0].downcast_ref::<ParseError>().unwrap();
+    assert_eq!(error.line(), 2);
+
+    time::advance(Duration::from_millis(10_001)).await;
+    assert_pending!(discover.poll_next());
+    wait_woken(&mut discover);
+    assert_pending!(discover.poll_next());
+
+    // and the next change comes from the next valid version of the file
+    write(&path, "1\n2\n3\n");
+    time::advance(Duration::from_millis(10_001)).await;
+    let (changes, errors) = next_changes(&mut discover, 1);
+    assert_eq!(changes, vec![(3, true)]);
+    assert!(errors.is_empty());
+
+    // a missing file is reported
+    fs::remove_file(&path).unwrap();
+    time::advance(Duration::from_millis(10_001)).await;
+    let (changes, errors) = next_changes(&mut discover, 1);
+    assert!(changes.is_empty());
+    assert_eq!(errors.len(), 1);
+}
+
+#[tokio::test]
+async fn custom_parser() {
+    time::pause();
+    let path = temp_path("custom_parser");
+    write(&path, "1,2,3");
+    let parse = |contents: &str| -> Result<Vec<u16>, BoxError> {
+        contents
+            .split(',')
+            .map(|port| port.trim().parse().map_err(Into::into))
+            .collect()
+    };
+    let mut discover = task::spawn(WatchFile::with_parser(
+        &path,
+        parse,
+        MakeEcho,
+        Duration::from_secs(10),
+    ));
+
+    let (changes, errors) = next_changes(&mut discover, 3);
+    assert_eq!(changes, vec![(1, true), (2, true), (3, true)]);
+    assert!(errors.is_empty());
+
+    fs::remove_file(&path).unwrap();
+}
@@ -1,9 +1,17 @@
 #![cfg(feature = "hedge")]
 
-use std::time::Duration;
+use futures_util::{stream, StreamExt};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 use tokio::time;
 use tokio_test::{assert_pending, assert_ready, assert_ready_ok, task};
-use tower::hedge::{Hedge, Policy};
+use tower::hedge::{
+    replay::{LimitExceeded, ReplayBody},
+    Builder, Hedge, Latencies, Policy,
+};
+use tower::retry::budget::Budget;
 use tower_test::{assert_request_eq, mock};
 
 #[tokio::test]
@@ -134,6 +142,223 @@ async fn request_not_clonable() {
     assert_eq!(assert_ready_ok!(fut.poll()), "orig-done");
 }
 
+#[tokio::test]
+async fn hedge_budget_overdrawn() {
+    time::pause();
+
+    // This budget never allows a withdrawal.
+    let budget = Arc::new(Budget::new(Duration::from_secs(1), 0, 0.0));
+    let (mut service, mut handle) =
+        new_service_with(TestPolicy, Builder::new().budget(Some(budget)));
+
+    assert_ready_ok!(service.poll_ready());
+    let mut fut = task::spawn(service.call("orig"));
+
+    // Check that orig request has been issued.
+    let req = assert_request_eq!(handle, "orig");
+    // Check fut is not ready.
+    assert_pending!(fut.poll());
+
+    time::advance(Duration::from_millis(11)).await;
+    // Check fut is not ready.
+    assert_pending!(fut.poll());
+    // Check hedge has not been issued.
+    assert_pending!(handle.poll_request());
+
+    req.send_response("orig-done");
+    // Check that fut gets orig response.
+    assert_eq!(assert_ready_ok!(fut.poll()), "orig-done");
+}
+
+#[tokio::test]
+async fn hedge_max_in_flight() {
+    time::pause();
+
+    let (mut service, mut handle) =
+        new_service_with(TestPolicy, Builder::new().max_in_flight(Some(1)));
+
+    assert_ready_ok!(service.poll_ready());
+    let mut fut1 = task::spawn(service.call("orig1"));
+    assert_ready_ok!(service.poll_ready());
+    let mut fut2 = task::spawn(service.call("orig2"));
+
+    // Check that orig requests have been issued.
+    let req1 = assert_request_eq!(handle, "orig1");
+    let req2 = assert_request_eq!(handle, "orig2");
+    assert_pending!(fut1.poll());
+    assert_pending!(fut2.poll());
+
+    time::advance(Duration::from_millis(11)).await;
+    assert_pending!(fut1.poll());
+    assert_pending!(fut2.poll());
+
+    // Check that only the first hedge has been issued.
+    let _hedge_req = assert_request_eq!(handle, "orig1");
+    assert_pending!(handle.poll_request());
+    assert_eq!(service.get_ref().hedges_in_flight(), 1);
+
+    // Completing the first request cancels its hedge, which frees the slot.
+    req1.send_response("orig1-done");
+    assert_eq!(assert_ready_ok!(fut1.poll()), "orig1-done");
+    drop(fut1);
+    assert_eq!(service.get_ref().hedges_in_flight(), 0);
+
+    req2.send_response("orig2-done");
+    assert_eq!(assert_ready_ok!(fut2.poll()), "orig2-done");
+}
+
+#[tokio::test]
+async fn hedge_multiple_percentiles() {
+    time::pause();
+
+    let (mut service, mut handle) =
+        new_service_with(TestPolicy, Builder::new().latency_percentiles(&[0.5, 0.9]));
+
+    assert_ready_ok!(service.poll_ready());
+    let mut fut = task::spawn(service.call("orig"));
+
+    // Check that orig request has been issued.
+    let _req = assert_request_eq!(handle, "orig");
+    assert_pending!(fut.poll());
+
+    // Check that the first hedge is issued at the p50 latency.
+    time::advance(Duration::from_millis(2)).await;
+    assert_pending!(fut.poll());
+    let _hedge_req1 = assert_request_eq!(handle, "orig");
+    assert_pending!(handle.poll_request());
+
+    // Check that the second hedge is issued at the p90 latency.
+    time::advance(Duration::from_millis(9)).await;
+    assert_pending!(fut.poll());
+    let hedge_req2 = assert_request_eq!(handle, "orig");
+    assert_eq!(service.get_ref().hedges_in_flight(), 2);
+
+    // Check that fut gets the second hedge's response, and the first hedge is canceled.
+    hedge_req2.send_response("hedge2-done");
+    assert_eq!(assert_ready_ok!(fut.poll()), "hedge2-done");
+    assert_eq!(service.get_ref().hedges_in_flight(), 0);
+}
+
+#[tokio::test]
+async fn hedge_custom_latencies() {
+    time::pause();
+
+    let latencies = Arc::new(FixedLatencies::new(Duration::from_millis(5)));
+    let (service_a, mut handle_a) = tower_test::mock::pair::<Req, Res>();
+    let (service_b, _handle_b) = tower_test::mock::pair::<Req, Res>();
+    let mut service = mock::Spawn::new(Builder::new().min_data_points(0).build_with_latencies(
+        service_a,
+        TestPolicy,
+        latencies.clone(),
+    ));
+    // A second middleware sharing the same latencies.
+    let other = Builder::new().build_with_latencies(service_b, TestPolicy, latencies.clone());
+    assert!(Arc::ptr_eq(
+        service.get_ref().latencies(),
+        other.latencies()
+    ));
+    assert_eq!(
+        service.get_ref().hedge_delays(),
+        vec![Duration::from_millis(5)]
+    );
+
+    assert_ready_ok!(service.poll_ready());
+    let mut fut = task::spawn(service.call("orig"));
+    let req = assert_request_eq!(handle_a, "orig");
+    assert_pending!(fut.poll());
+
+    // Check that the hedge is issued after the delay read from the latencies.
+    time::advance(Duration::from_millis(6)).await;
+    assert_pending!(fut.poll());
+    let _hedge_req = assert_request_eq!(handle_a, "orig");
+
+    req.send_response("orig-done");
+    assert_eq!(assert_ready_ok!(fut.poll()), "orig-done");
+    // Check that the latency of the original request was recorded.
+    assert_eq!(latencies.recorded.lock().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn hedge_replay_body() {
+    time::pause();
+
+    let (mut service, mut handle) = new_replay_service();
+
+    assert_ready_ok!(service.poll_ready());
+    let mut fut = task::spawn(service.call(replay_body(16)));
+
+    // Check that orig request has been issued, and read its body.
+    let (req, _send) = handle.next_request().await.unwrap();
+    assert_eq!(read_body(req).await, vec!["hello", "world"]);
+    assert_pending!(fut.poll());
+
+    // Check that the hedge replays the body.
+    time::advance(Duration::from_millis(11)).await;
+    assert_pending!(fut.poll());
+    let (hedge_req, send_hedge) = handle.next_request().await.unwrap();
+    assert_eq!(read_body(hedge_req).await, vec!["hello", "world"]);
+
+    send_hedge.send_response("hedge-done");
+    assert_eq!(assert_ready_ok!(fut.poll()), "hedge-done");
+}
+
+#[tokio::test]
+async fn hedge_replay_body_limit_exceeded() {
+    time::pause();
+
+    let (mut service, mut handle) = new_replay_service();
+
+    assert_ready_ok!(service.poll_ready());
+    let mut fut = task::spawn(service.call(replay_body(8)));
+
+    // Check that orig request has been issued, and read past the limit.
+    let (req, send) = handle.next_request().await.unwrap();
+    assert_eq!(read_body(req).await, vec!["hello", "world"]);
+    assert_pending!(fut.poll());
+
+    // Check hedge has not been issued.
+    time::advance(Duration::from_millis(11)).await;
+    assert_pending!(fut.poll());
+    assert_pending!(handle.poll_request());
+
+    send.send_response("orig-done");
+    assert_eq!(assert_ready_ok!(fut.poll()), "orig-done");
+}
+
+#[tokio::test]
+async fn hedge_replay_body_limit_exceeded_after_hedge() {
+    time::pause();
+
+    let (mut service, mut handle) = new_replay_service();
+
+    assert_ready_ok!(service.poll_ready());
+    let mut fut = task::spawn(service.call(replay_body(8)));
+
+    // Read part of the body of the orig request, staying under the limit.
+    let (mut req, send) = handle.next_request().await.unwrap();
+    assert_eq!(req.next().await.unwrap().unwrap(), "hello");
+    assert_pending!(fut.poll());
+
+    // Issue the hedge, then read past the limit with the orig request.
+    time::advance(Duration::from_millis(11)).await;
+    assert_pending!(fut.poll());
+    let (hedge_req, send_hedge) = handle.next_request().await.unwrap();
+    assert_eq!(req.next().await.unwrap().unwrap(), "world");
+
+    // The hedge cannot replay the body, which only aborts the hedge.
+    let err = hedge_req
+        .map(|chunk| chunk.unwrap_err())
+        .collect::<Vec<_>>()
+        .await
+        .remove(0);
+    assert!(err.is::<LimitExceeded>());
+    send_hedge.send_error(err);
+    assert_pending!(fut.poll());
+
+    send.send_response("orig-done");
+    assert_eq!(assert_ready_ok!(fut.poll()), "orig-done");
+}
+
 type Req = &'static str;
 type Res = &'static str;
 type Mock = tower_test::mock::Mock<Req, Res>;
@@ -159,6 +384,85 @@ impl tower::hedge::Policy<Req> for TestPolicy {
     }
 }
 
+type Body = stream::Iter<std::vec::IntoIter<Result<&'static str, tower::BoxError>>>;
+type ReplayReq = ReplayBody<Body>;
+type ReplayMock = tower_test::mock::Mock<ReplayReq, Res>;
+
+#[derive(Clone)]
+struct ReplayPolicy;
+
+impl Policy<ReplayReq> for ReplayPolicy {
+    fn can_retry(&self, req: &ReplayReq) -> bool {
+        !req.is_capped()
+    }
+
+    fn clone_request(&self, req: &ReplayReq) -> Option<ReplayReq> {
+        req.try_clone()
+    }
+}
+
+fn replay_body(limit: usize) -> ReplayReq {
+    ReplayBody::new(stream::iter(vec![Ok("hello"), Ok("world")]), limit)
+}
+
+async fn read_body(body: ReplayReq) -> Vec<&'static str> {
+    body.map(|chunk| chunk.unwrap()).collect().await
+}
+
+fn new_replay_service() -> (
+    mock::Spawn<Hedge<ReplayMock, ReplayPolicy>>,
+    tower_test::mock::Handle<ReplayReq, Res>,
+) {
+    let (service, handle) = tower_test::mock::pair();
+
+    let mock_latencies: [u64; 10] = [1, 1, 1, 1, 1, 1, 1, 1, 10, 10];
+
+    let service = Builder::new().build_with_mock_latencies(service, ReplayPolicy, &mock_latencies);
+
+    (mock::Spawn::new(service), handle)
+}
+
+struct FixedLatencies {
+    delay: Duration,
+    recorded: Mutex<Vec<Duration>>,
+}
+
+impl FixedLatencies {
+    fn new(delay: Duration) -> Self {
+        FixedLatencies {
+            delay,
+            recorded: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl Latencies for FixedLatencies {
+    fn record(&self, latency: Duration) {
+        self.recorded.lock().unwrap().push(latency);
+    }
+
+    fn value_at_quantile(&self, _quantile: f64) -> Duration {
+        self.delay
+    }
+
+    fn data_points(&self) -> u64 {
+        self.recorded.lock().unwrap().len() as u64
+    }
+}
+
+fn new_service_with<P: Policy<Req> + Clone>(
+    policy: P,
+    builder: &Builder,
+) -> (mock::Spawn<Hedge<Mock, P>>, Handle) {
+    let (service, handle) = tower_test::mock::pair();
+
+    let mock_latencies: [u64; 10] = [1, 1, 1, 1, 1, 1, 1, 1, 10, 10];
+
+    let service = builder.build_with_mock_latencies(service, policy, &mock_latencies);
+
+    (mock::Spawn::new(service), handle)
+}
+
 fn new_service<P: Policy<Req> + Clone>(policy: P) -> (mock::Spawn<Hedge<Mock, P>>, Handle) {
     let (service, handle) = tower_test::mock::pair();
 
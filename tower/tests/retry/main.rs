@@ -1,8 +1,18 @@
 #![cfg(feature = "retry")]
 
 use futures_util::future;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::time;
 use tokio_test::{assert_pending, assert_ready_err, assert_ready_ok, task};
-use tower::retry::Policy;
+use tower::retry::{
+    backoff::{ExponentialBackoff, Jitter},
+    budget::{Budget, BudgetedPolicy},
+    error::{AttemptTimedOut, DeadlineExhausted},
+    Attempts, Classification, Policy, TimeoutRetryLayer,
+};
 use tower_test::{assert_request_eq, mock};
 
 #[tokio::test]
@@ -78,6 +88,203 @@ async fn success_with_cannot_clone() {
     assert_ready_ok!(fut.poll(), "world");
 }
 
+#[tokio::test]
+async fn backoff_delays_retries() {
+    time::pause();
+
+    let policy = ExponentialBackoff::new(Duration::from_millis(100))
+        .jitter(Jitter::None)
+        .max_attempts(Some(3))
+        .classify(|_: &Req, result: Result<&Res, &Error>| result.is_err());
+    let (mut service, mut handle) = new_service(policy);
+
+    assert_ready_ok!(service.poll_ready());
+    let mut fut = task::spawn(service.call("hello"));
+
+    assert_request_eq!(handle, "hello").send_error("retry 1");
+    assert_pending!(fut.poll());
+
+    time::advance(Duration::from_millis(99)).await;
+    assert_pending!(fut.poll());
+    assert_pending!(handle.poll_request());
+
+    time::advance(Duration::from_millis(2)).await;
+    assert_pending!(fut.poll());
+    assert_request_eq!(handle, "hello").send_error("retry 2");
+    assert_pending!(fut.poll());
+
+    time::advance(Duration::from_millis(201)).await;
+    assert_pending!(fut.poll());
+    assert_request_eq!(handle, "hello").send_error("retry 3");
+    assert_eq!(assert_ready_err!(fut.poll()).to_string(), "retry 3");
+}
+
+#[tokio::test]
+async fn backoff_respects_budget() {
+    time::pause();
+
+    // The reserve allows for exactly one retry.
+    let budget = Arc::new(Budget::new(Duration::from_secs(1), 1, 0.0));
+    let policy = ExponentialBackoff::new(Duration::from_millis(100))
+        .jitter(Jitter::None)
+        .classify(|_: &Req, result: Result<&Res, &Error>| result.is_err())
+        .with_budget(budget);
+    let (mut service, mut handle) = new_service(policy);
+
+    assert_ready_ok!(service.poll_ready());
+    let mut fut = task::spawn(service.call("hello"));
+
+    assert_request_eq!(handle, "hello").send_error("retry 1");
+    assert_pending!(fut.poll());
+
+    time::advance(Duration::from_millis(101)).await;
+    assert_pending!(fut.poll());
+    assert_request_eq!(handle, "hello").send_error("retry 2");
+    assert_eq!(assert_ready_err!(fut.poll()).to_string(), "retry 2");
+}
+
+#[tokio::test]
+async fn budget_is_shared_between_clones() {
+    // Every original request allows for exactly one retry.
+    let budget = Arc::new(Budget::new(Duration::from_secs(1), 0, 1.0));
+    let (mut service, mut handle) = new_service(BudgetedPolicy::new(RetryErrors, budget));
+    let mut clone = service.clone();
+
+    assert_ready_ok!(service.poll_ready());
+    let mut fut1 = task::spawn(service.call("hello"));
+    assert_ready_ok!(clone.poll_ready());
+    let mut fut2 = task::spawn(clone.call("world"));
+
+    // The first request spends both deposits.
+    assert_request_eq!(handle, "hello").send_error("retry 1");
+    assert_pending!(fut1.poll());
+    assert_request_eq!(handle, "world").send_response("world");
+    assert_ready_ok!(fut2.poll(), "world");
+    assert_request_eq!(handle, "hello").send_error("retry 2");
+    assert_pending!(fut1.poll());
+    assert_request_eq!(handle, "hello").send_error("retry 3");
+    assert_eq!(assert_ready_err!(fut1.poll()).to_string(), "retry 3");
+}
+
+#[tokio::test]
+async fn attempt_timeout_is_retried() {
+    time::pause();
+
+    let layer = TimeoutRetryLayer::new(Limit(1)).attempt_timeout(Duration::from_millis(100));
+    let (mut service, mut handle) = mock::spawn_layer(layer);
+
+    assert_ready_ok!(service.poll_ready());
+    let mut fut = task::spawn(service.call("hello"));
+
+    let _req = assert_request_eq!(handle, "hello");
+    assert_pending!(fut.poll());
+
+    time::advance(Duration::from_millis(101)).await;
+    assert_pending!(fut.poll());
+    let _req = assert_request_eq!(handle, "hello");
+
+    time::advance(Duration::from_millis(101)).await;
+    let err = assert_ready_err!(fut.poll());
+    assert!(err.is::<AttemptTimedOut>(), "unexpected error: {}", err);
+}
+
+#[tokio::test]
+async fn deadline_bounds_attempts() {
+    time::pause();
+
+    let layer = TimeoutRetryLayer::new(RetryErrors)
+        .attempt_timeout(Duration::from_millis(100))
+        .deadline(Duration::from_millis(150));
+    let (mut service, mut handle) = mock::spawn_layer(layer);
+
+    assert_ready_ok!(service.poll_ready());
+    let mut fut = task::spawn(service.call("hello"));
+
+    let _req = assert_request_eq!(handle, "hello");
+    assert_pending!(fut.poll());
+
+    time::advance(Duration::from_millis(101)).await;
+    assert_pending!(fut.poll());
+    let _req = assert_request_eq!(handle, "hello");
+
+    time::advance(Duration::from_millis(50)).await;
+    let err = assert_ready_err!(fut.poll());
+    assert!(err.is::<DeadlineExhausted>(), "unexpected error: {}", err);
+}
+
+#[tokio::test]
+async fn deadline_bounds_backoff() {
+    time::pause();
+
+    let policy = ExponentialBackoff::new(Duration::from_secs(1))
+        .jitter(Jitter::None)
+        .classify(|_: &Req, result: Result<&Res, &Error>| result.is_err());
+    let layer = TimeoutRetryLayer::new(policy).deadline(Duration::from_millis(500));
+    let (mut service, mut handle) = mock::spawn_layer(layer);
+
+    assert_ready_ok!(service.poll_ready());
+    let mut fut = task::spawn(service.call("hello"));
+
+    assert_request_eq!(handle, "hello").send_error("retry 1");
+    assert_pending!(fut.poll());
+
+    time::advance(Duration::from_millis(501)).await;
+    let err = assert_ready_err!(fut.poll());
+    assert!(err.is::<DeadlineExhausted>(), "unexpected error: {}", err);
+}
+
+#[tokio::test]
+async fn attempts_are_reported() {
+    time::pause();
+
+    let attempts = Arc::new(Mutex::new(None));
+    let (mut service, mut handle) = new_service(Record(attempts.clone()));
+
+    assert_ready_ok!(service.poll_ready());
+    let mut fut = task::spawn(service.call("hello"));
+
+    assert_request_eq!(handle, "hello").send_error("retry 1");
+    assert_pending!(fut.poll());
+    assert!(attempts.lock().unwrap().is_none());
+
+    assert_request_eq!(handle, "hello").send_error("retry 2");
+    assert_pending!(fut.poll());
+
+    assert_request_eq!(handle, "hello").send_response("world");
+    assert_ready_ok!(fut.poll(), "world");
+
+    let attempts = attempts
+        .lock()
+        .unwrap()
+        .take()
+        .expect("completed was called");
+    assert_eq!(attempts.count(), 3);
+    assert_eq!(attempts.retries(), 2);
+    assert_eq!(attempts.backoff(), Duration::from_secs(0));
+    assert_eq!(attempts.classification(), Some(Classification::Accepted));
+}
+
+#[tokio::test]
+async fn attempts_are_reported_when_not_cloned() {
+    let attempts = Arc::new(Mutex::new(None));
+    let policy = BudgetedPolicy::new(Record(attempts.clone()), Arc::new(Budget::default()));
+    let (mut service, mut handle) = new_service(policy);
+
+    assert_ready_ok!(service.poll_ready());
+    let mut fut = task::spawn(service.call("not cloned"));
+
+    assert_request_eq!(handle, "not cloned").send_error("retry 1");
+    assert_eq!(assert_ready_err!(fut.poll()).to_string(), "retry 1");
+
+    let attempts = attempts
+        .lock()
+        .unwrap()
+        .take()
+        .expect("completed was called");
+    assert_eq!(attempts.count(), 1);
+    assert_eq!(attempts.classification(), Some(Classification::NotCloned));
+}
+
 type Req = &'static str;
 type Res = &'static str;
 type InnerError = &'static str;
@@ -155,6 +362,32 @@ impl Policy<Req, Res, Error> for CannotClone {
     }
 }
 
+#[derive(Clone)]
+struct Record(Arc<Mutex<Option<Attempts>>>);
+
+impl Policy<Req, Res, Error> for Record {
+    type Future = future::Ready<Self>;
+    fn retry(&self, _: &Req, result: Result<&Res, &Error>) -> Option<Self::Future> {
+        if result.is_err() {
+            Some(future::ready(self.clone()))
+        } else {
+            None
+        }
+    }
+
+    fn clone_request(&self, req: &Req) -> Option<Req> {
+        if *req == "not cloned" {
+            None
+        } else {
+            Some(*req)
+        }
+    }
+
+    fn completed(&self, _: Result<&mut Res, &mut Error>, attempts: &Attempts) {
+        *self.0.lock().unwrap() = Some(attempts.clone());
+    }
+}
+
 fn new_service<P: Policy<Req, Res, Error> + Clone>(
     policy: P,
 ) -> (mock::Spawn<tower::retry::Retry<P, Mock>>, Handle) {
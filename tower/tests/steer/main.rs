@@ -2,7 +2,7 @@
 
 use futures_util::future::{ready, Ready};
 use std::task::{Context, Poll};
-use tower::steer::Steer;
+use tower::steer::{Picker, Steer};
 use tower_service::Service;
 
 type StdError = Box<dyn std::error::Error + Send + Sync + 'static>;
@@ -58,3 +58,35 @@ fn pending_all_ready() {
         }
     });
 }
+
+/// Picks the service whose metadata is the request.
+struct ByShard;
+
+impl<S> Picker<S, String, &'static str> for ByShard {
+    fn pick(&mut self, r: &String, _: &[S], shards: &[&'static str]) -> usize {
+        shards.iter().position(|s| s == r).unwrap()
+    }
+}
+
+#[test]
+fn pick_by_metadata() {
+    let mut rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async move {
+        let srvs = vec![(MyService(42, true), "a"), (MyService(57, true), "b")];
+        let mut st = Steer::with_metadata(srvs, ByShard);
+
+        futures_util::future::poll_fn(|cx| st.poll_ready(cx))
+            .await
+            .unwrap();
+        let r = st.call(String::from("b")).await.unwrap();
+        assert_eq!(r, 57);
+
+        st.update(0, "b");
+        st.update(1, "a");
+        futures_util::future::poll_fn(|cx| st.poll_ready(cx))
+            .await
+            .unwrap();
+        let r = st.call(String::from("b")).await.unwrap();
+        assert_eq!(r, 42);
+    });
+}